@@ -0,0 +1,46 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use mips_parser::source::SourceDb;
+use mips_parser::MipsCompiler;
+
+/// Builds a program like the ones generated by compilers, with many labels, instructions
+/// and strings
+fn synthetic_program(blocks: usize) -> String {
+    let mut program = String::from(".data\n");
+    for i in 0..blocks {
+        program.push_str(&format!(
+            "message_{i}: .asciiz \"block number {i}\\n\"\nvalues_{i}: .word {i}, 0x{i:x}, -{i}\n"
+        ));
+    }
+    program.push_str(".text\nmain:\n");
+    for i in 0..blocks {
+        program.push_str(&format!(
+            "block_{i}:
+    lw $t0, values_{i}
+    addi $t1, $t0, {i} # increment
+    sll $t2, $t1, 2
+    sw $t2, 4($sp)
+    beq $t0, $t1, block_{i}
+    j block_{i}
+"
+        ));
+    }
+    program
+}
+
+fn compile(c: &mut Criterion) {
+    let program = synthetic_program(20_000);
+    let mut group = c.benchmark_group("compile");
+    group.throughput(Throughput::Bytes(program.len() as u64));
+    group.sample_size(10);
+    group.bench_function("synthetic", |b| {
+        b.iter(|| {
+            let mut sources = SourceDb::new();
+            let file = sources.add_file("synthetic.asm", &program);
+            MipsCompiler::new(&mut sources, file).compile().unwrap()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, compile);
+criterion_main!(benches);
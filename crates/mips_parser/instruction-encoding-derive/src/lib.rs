@@ -1,17 +1,35 @@
-use std::collections::HashMap;
 use proc_macro::TokenStream;
-use syn::{token::Comma, Data, DeriveInput, Ident, LitInt};
+use std::collections::HashMap;
+use syn::{token::Comma, Data, DeriveInput, Ident, LitInt, Token};
+
+/// The fields besides the opcode and the funct that can select an instruction
+const SELECTOR_FIELDS: [&str; 4] = ["rs", "rt", "rd", "shamt"];
 
 #[derive(Debug)]
 struct VariantAttrs {
     opcode: u8,
     format: Ident,
     funct: Option<u8>,
+    /// The fixed value of each of the fields in [`SELECTOR_FIELDS`], if any
+    selectors: [Option<u8>; 4],
+}
+
+/// Parses an integer literal that fits in `bits` bits
+fn parse_field(input: syn::parse::ParseStream, bits: u32) -> syn::Result<u8> {
+    let lit = input.parse::<LitInt>()?;
+    let value = lit.base10_parse::<u8>()?;
+    if u32::from(value) >= 1 << bits {
+        return Err(syn::Error::new(
+            lit.span(),
+            format!("The field is {bits} bits wide."),
+        ));
+    }
+    Ok(value)
 }
 
 impl syn::parse::Parse for VariantAttrs {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
-        let opcode = input.parse::<LitInt>()?.base10_parse::<u8>()?;
+        let opcode = parse_field(input, 6)?;
         input.parse::<Comma>()?;
         let format = input.parse::<Ident>()?;
         if !(format == "R" || format == "J" || format == "I") {
@@ -20,9 +38,9 @@ impl syn::parse::Parse for VariantAttrs {
                 "Invalid variant for instruction format.",
             ));
         }
-        let funct = if input.peek(Comma) {
+        let funct = if input.peek(Comma) && input.peek2(LitInt) {
             input.parse::<Comma>()?;
-            let funct = input.parse::<LitInt>()?.base10_parse::<u8>()?;
+            let funct = parse_field(input, 6)?;
             if format != "R" {
                 return Err(syn::Error::new(
                     input.span(),
@@ -40,10 +58,36 @@ impl syn::parse::Parse for VariantAttrs {
             }
             None
         };
+        let mut selectors = [None; 4];
+        while input.peek(Comma) {
+            input.parse::<Comma>()?;
+            let field = input.parse::<Ident>()?;
+            let Some(index) = SELECTOR_FIELDS.iter().position(|f| field == f) else {
+                return Err(syn::Error::new(
+                    field.span(),
+                    "Expected one of the fields `rs`, `rt`, `rd` or `shamt`.",
+                ));
+            };
+            if format == "J" || (format == "I" && index > 1) {
+                return Err(syn::Error::new(
+                    field.span(),
+                    format!("The {format} format has no `{field}` field."),
+                ));
+            }
+            if selectors[index].is_some() {
+                return Err(syn::Error::new(
+                    field.span(),
+                    format!("`{field}` is specified more than once."),
+                ));
+            }
+            input.parse::<Token![=]>()?;
+            selectors[index] = Some(parse_field(input, 5)?);
+        }
         Ok(VariantAttrs {
             opcode,
             format,
             funct,
+            selectors,
         })
     }
 }
@@ -91,6 +135,22 @@ fn impl_encoding_trait(ast: DeriveInput) -> syn::Result<TokenStream> {
             }
         })
         .collect::<Vec<_>>();
+    let selector_fns = SELECTOR_FIELDS
+        .iter()
+        .enumerate()
+        .map(|(index, field)| {
+            let name = Ident::new(field, proc_macro2::Span::call_site());
+            let arms = attrs.iter().map(|(k, v)| match v.selectors[index] {
+                Some(value) => quote::quote! {#enum_ident::#k => Some(Bits::new(#value as u32))},
+                None => quote::quote! {#enum_ident::#k => None},
+            });
+            quote::quote! {
+                fn #name(&self) -> Option<Bits<5>> {
+                    match self {#(#arms),*}
+                }
+            }
+        })
+        .collect::<Vec<_>>();
     let opcode_match = attrs
         .into_iter()
         .map(|(k, v)| {
@@ -110,6 +170,7 @@ fn impl_encoding_trait(ast: DeriveInput) -> syn::Result<TokenStream> {
             fn funct(&self) -> Option<Bits<6>> {
                 match self {#(#funct_match),*}
             }
+            #(#selector_fns)*
         }
     }
     .into())
@@ -118,6 +179,9 @@ fn impl_encoding_trait(ast: DeriveInput) -> syn::Result<TokenStream> {
 /// This macro implements the [`mips_parser::defs::InstructionEncoding`] trait on an enum.
 /// Each variant must have an `#[instruction(opcode, format, funct)]` attribute.
 /// funct must only be specified if format is `R`.
+/// The fields that select the instruction besides the opcode and the funct, like the `rt` of
+/// the REGIMM branches, follow as `field = value`. They can be `rs`, `rt`, `rd` and `shamt` in
+/// the `R` format and `rs` and `rt` in the `I` format.
 /// # Example
 /// ```ignore
 /// #[derive(InstructionEncoding)]
@@ -126,6 +190,10 @@ fn impl_encoding_trait(ast: DeriveInput) -> syn::Result<TokenStream> {
 ///     Add,
 ///     #[instruction(0b010011, I)]
 ///     Addi,
+///     #[instruction(0b000001, I, rt = 0b00001)]
+///     Bgez,
+///     #[instruction(0b010000, R, 0b011000, rs = 0b10000)]
+///     Eret,
 /// }
 /// ```
 #[proc_macro_derive(InstructionEncoding, attributes(instruction))]
@@ -0,0 +1,300 @@
+use crate::defs::dialect::Dialect;
+use crate::defs::directive::Directive;
+use crate::defs::program::{
+    DirectiveStatement, Immediate, Instruction, OperandKind, Program, Relocation, SegmentKind,
+    Statement, StorableData,
+};
+use crate::defs::symbols::{Symbol, SymbolTable};
+use crate::encoder;
+use crate::errors::{AssemblerError, AssemblerErrorKind};
+
+#[cfg(test)]
+mod tests;
+
+/// Lays out the program in memory and fills in its symbol table and segments.
+///
+/// The first pass computes the address of every statement, so that every label gets the address
+/// of the statement that follows it. The second pass resolves the values that depend on labels,
+/// which allows labels to be used before their definition, stores the data and encodes the
+/// instructions. `%gp_rel` is relative to the `$gp` of the dialect.
+pub(crate) fn assemble(program: &mut Program, dialect: Dialect) -> Result<(), Vec<AssemblerError>> {
+    let mut errors = Vec::new();
+    program.symbols.set_gp(dialect.gp_address());
+    for segment in &program.segments {
+        let mut address = segment.address;
+        let mut overflowed = false;
+        for (i, stmt) in segment.stmts.iter().enumerate() {
+            if let Statement::Label { name, span } = stmt {
+                // the label points to the aligned address of the value that follows it
+                let alignment = segment.stmts[i..]
+                    .iter()
+                    .find(|s| !matches!(s, Statement::Label { .. }))
+                    .map_or(1, |s| s.alignment());
+                let symbol = Symbol {
+                    segment: segment.kind,
+                    address: address.next_multiple_of(alignment),
+                    span: span.clone(),
+                    global: false,
+                };
+                if let Err(first) = program.symbols.insert(name.clone(), symbol) {
+                    errors.push(AssemblerError::new(
+                        AssemblerErrorKind::DuplicateLabel {
+                            name: name.clone(),
+                            first: first.span.clone(),
+                        },
+                        span.clone(),
+                    ));
+                }
+            }
+            address = address.next_multiple_of(stmt.alignment());
+            address = match end_of(stmt, address, segment.kind) {
+                Some(end) => end,
+                None => {
+                    if !overflowed {
+                        errors.push(AssemblerError::new(
+                            AssemblerErrorKind::SegmentOverflow(segment.kind),
+                            stmt.span().clone(),
+                        ));
+                    }
+                    overflowed = true;
+                    // the rest of the segment is placed at its end, so that its labels exist
+                    segment.kind.end_address()
+                }
+            };
+        }
+    }
+
+    let mut globals = Vec::new();
+    for segment in &mut program.segments {
+        let mut address = segment.address;
+        for stmt in &segment.stmts {
+            let padding = address.next_multiple_of(stmt.alignment()) - address;
+            if padding > 0 && segment.kind.is_data() {
+                segment.data.push(StorableData::Space(padding));
+            }
+            address += padding;
+            match stmt {
+                Statement::Instruction(instruction) => {
+                    let checked = errors.len();
+                    check_operands(instruction, &program.symbols, &mut errors);
+                    segment
+                        .relocations
+                        .extend(relocations(instruction, address));
+                    // the operands that were reported can't be encoded
+                    if errors.len() == checked {
+                        match encoder::resolve(instruction, address, &program.symbols) {
+                            Ok(machine) => segment.data.push(StorableData::Word(machine.encode())),
+                            Err(err) => errors.push(err),
+                        }
+                    }
+                }
+                // only `.align` is allowed in a text segment, its padding is made of `nop`s
+                Statement::Data(stmt) if !segment.kind.is_data() => {
+                    let size = stmt.size(address).unwrap_or(0);
+                    if size > 0 {
+                        push_run(&mut segment.data, StorableData::Word(0), size / 4);
+                    }
+                }
+                Statement::Data(stmt) => store(
+                    stmt,
+                    address,
+                    &program.symbols,
+                    &mut segment.data,
+                    &mut errors,
+                ),
+                Statement::Directive(stmt)
+                    if matches!(stmt.directive, Directive::Globl | Directive::Global) =>
+                {
+                    globals.extend(stmt.operands.iter().filter_map(|o| match &o.kind {
+                        OperandKind::Label(label) => Some(label.clone()),
+                        _ => None,
+                    }))
+                }
+                Statement::Label { .. } | Statement::Directive(_) => {}
+            }
+            address = end_of(stmt, address, segment.kind).unwrap_or(segment.kind.end_address());
+        }
+    }
+    for label in globals {
+        if let Some(symbol) = program.symbols.get_mut(&label) {
+            symbol.global = true;
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Checks that the labels used by an instruction are defined and that the values that depend on
+/// them fit in their fields
+fn check_operands(
+    instruction: &Instruction,
+    symbols: &SymbolTable,
+    errors: &mut Vec<AssemblerError>,
+) {
+    let signature = instruction.kind.matching_signature(&instruction.operands);
+    for (i, operand) in instruction.operands.iter().enumerate() {
+        let immediate = match &operand.kind {
+            OperandKind::Label(label) => {
+                if symbols.get(label).is_none() {
+                    errors.push(AssemblerError::new(
+                        AssemblerErrorKind::UndefinedLabel(label.clone()),
+                        operand.span.clone(),
+                    ));
+                }
+                continue;
+            }
+            OperandKind::Immediate(imm) => imm,
+            OperandKind::Address(address) => &address.offset,
+            _ => continue,
+        };
+        let value = match immediate.resolve(symbols, &operand.span) {
+            Ok(value) => value,
+            Err(err) => {
+                errors.push(err.into());
+                continue;
+            }
+        };
+        // `%hi` and `%lo` always fit in their 16 bits, `%gp_rel` must be close to `$gp`
+        match immediate {
+            Immediate::Hi(_) | Immediate::Lo(_) => continue,
+            Immediate::GpRel(_) if i16::try_from(value).is_err() => {
+                errors.push(AssemblerError::new(
+                    AssemblerErrorKind::ValueOutOfRange {
+                        range: i16::MIN as i64..=i16::MAX as i64,
+                        expected: "`%gp_rel` expects an address within 32 KiB of `$gp`".into(),
+                    },
+                    operand.span.clone(),
+                ));
+                continue;
+            }
+            _ => {}
+        }
+        // constants were already checked by the parser
+        let Some(shape) = signature.map(|s| s[i]) else {
+            continue;
+        };
+        if let Some(range) = shape.range().filter(|_| operand.kind.number().is_none()) {
+            if !range.contains(&value) {
+                errors.push(AssemblerError::new(
+                    AssemblerErrorKind::ValueOutOfRange {
+                        range,
+                        expected: format!("`{}` expects {} here", instruction.kind, shape.width()),
+                    },
+                    operand.span.clone(),
+                ));
+            }
+        }
+    }
+}
+
+/// Returns the relocations of the operands of an instruction placed at `address`. Only the
+/// relocation operators applied to a label need one, the others are constants.
+fn relocations(instruction: &Instruction, address: u32) -> impl Iterator<Item = Relocation> + '_ {
+    instruction.operands.iter().filter_map(move |operand| {
+        let immediate = match &operand.kind {
+            OperandKind::Immediate(imm) => imm,
+            OperandKind::Address(memory) => &memory.offset,
+            _ => return None,
+        };
+        match immediate.relocation()? {
+            (kind, Immediate::Label(symbol, addend)) => Some(Relocation {
+                kind,
+                address,
+                symbol: symbol.clone(),
+                addend: *addend,
+            }),
+            _ => None,
+        }
+    })
+}
+
+/// Adds the values of a data directive placed at `address` to the data of its segment
+fn store(
+    stmt: &DirectiveStatement,
+    address: u32,
+    symbols: &SymbolTable,
+    data: &mut Vec<StorableData>,
+    errors: &mut Vec<AssemblerError>,
+) {
+    if matches!(stmt.directive, Directive::Space | Directive::Align) {
+        let size = stmt.size(address).unwrap_or(0);
+        if size > 0 {
+            data.push(StorableData::Space(size));
+        }
+        return;
+    }
+    for operand in &stmt.operands {
+        let (kind, count) = match &operand.kind {
+            OperandKind::Repeat(kind, count) => (kind.as_ref(), *count),
+            kind => (kind, 1),
+        };
+        let value = match kind {
+            OperandKind::String(string) => {
+                let mut chars = string.clone();
+                if stmt.directive == Directive::Asciiz {
+                    chars.push(0);
+                }
+                data.push(StorableData::Ascii(chars));
+                continue;
+            }
+            OperandKind::Label(label) => match symbols.get(label) {
+                Some(symbol) => symbol.address as i64,
+                None => {
+                    errors.push(AssemblerError::new(
+                        AssemblerErrorKind::UndefinedLabel(label.clone()),
+                        operand.span.clone(),
+                    ));
+                    continue;
+                }
+            },
+            OperandKind::Float(bits) => {
+                let num = f64::from_bits(*bits);
+                let value = match stmt.directive {
+                    Directive::Float => StorableData::Float((num as f32).to_bits()),
+                    _ => StorableData::Double(*bits),
+                };
+                push_run(data, value, count);
+                continue;
+            }
+            OperandKind::Immediate(imm) => match imm.resolve(symbols, &operand.span) {
+                Ok(value) => value,
+                Err(err) => {
+                    errors.push(err.into());
+                    continue;
+                }
+            },
+            _ => unreachable!("The operands of data directives should be checked"),
+        };
+        // values that don't fit are truncated, the parser warns about the constant ones
+        let value = match stmt.directive {
+            Directive::Byte => StorableData::Byte(value as u8),
+            Directive::Half => StorableData::Half(value as u16),
+            Directive::Word => StorableData::Word(value as u32),
+            Directive::Float => StorableData::Float((value as f32).to_bits()),
+            Directive::Double => StorableData::Double((value as f64).to_bits()),
+            _ => unreachable!("Only data directives should be stored"),
+        };
+        push_run(data, value, count);
+    }
+}
+
+/// Stores a value `count` times, as a single entry if it's repeated
+fn push_run(data: &mut Vec<StorableData>, value: StorableData, count: u32) {
+    if count == 1 {
+        data.push(value);
+    } else {
+        data.push(StorableData::Repeat(Box::new(value), count));
+    }
+}
+
+/// Returns the address after a statement placed at `address`, which must be aligned, or
+/// `None` if the statement goes past the end of the address space of its segment
+fn end_of(stmt: &Statement, address: u32, segment: SegmentKind) -> Option<u32> {
+    stmt.size(address)
+        .and_then(|size| address.checked_add(size))
+        .filter(|end| *end <= segment.end_address())
+}
@@ -0,0 +1,634 @@
+use crate::defs::dialect::Dialect;
+use crate::defs::program::{Endianness, Immediate, RelocationKind, SegmentKind, StorableData};
+use crate::disassembler::{decode, Disassembler};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+
+use super::*;
+
+/// Parses and lays out the input, which must have no syntax errors
+fn assemble_str(input: &str) -> Result<Program, Vec<AssemblerError>> {
+    assemble_dialect(input, Dialect::Mars)
+}
+
+fn assemble_dialect(input: &str, dialect: Dialect) -> Result<Program, Vec<AssemblerError>> {
+    let tokens = Lexer::new(input).with_dialect(dialect).lex().unwrap();
+    let (mut program, _) = Parser::new(tokens.into_iter())
+        .with_dialect(dialect)
+        .parse()
+        .unwrap();
+    assemble(&mut program, dialect).map(|_| program)
+}
+
+#[test]
+fn layout() {
+    let input = ".data
+x: .byte 1
+.align 2
+y: .word 2, 3
+.space 5
+z: .asciiz \"hi\"
+.text
+.globl main
+main: lw $t0, z
+    j end
+end: jr $ra";
+    let program = assemble_str(input).unwrap();
+    let symbols = program
+        .symbols
+        .iter()
+        .map(|(name, s)| (name, s.segment, s.address, s.global))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        symbols,
+        vec![
+            // `lw` with a label address takes two instructions
+            ("end", SegmentKind::Text, 0x0040_000c, false),
+            ("main", SegmentKind::Text, 0x0040_0000, true),
+            ("x", SegmentKind::Data, 0x1001_0000, false),
+            ("y", SegmentKind::Data, 0x1001_0004, false),
+            ("z", SegmentKind::Data, 0x1001_0011, false),
+        ]
+    );
+    assert_eq!(program.symbols.get("main").unwrap().span, 83..87);
+
+    let z = Immediate::Label("z".into(), 4);
+    assert_eq!(z.resolve(&program.symbols, &(0..0)), Ok(0x1001_0015));
+    assert_eq!(
+        Immediate::Hi(Box::new(z.clone())).resolve(&program.symbols, &(0..0)),
+        Ok(0x1001)
+    );
+    assert_eq!(
+        Immediate::Lo(Box::new(z)).resolve(&program.symbols, &(0..0)),
+        Ok(0x15)
+    );
+    // the lower half is sign extended, so the upper half must compensate
+    let mut symbols = program.symbols;
+    symbols.get_mut("z").unwrap().address = 0x1001_8000;
+    let z = Immediate::Label("z".into(), 0);
+    assert_eq!(
+        Immediate::Hi(Box::new(z.clone())).resolve(&symbols, &(0..0)),
+        Ok(0x1002)
+    );
+    assert_eq!(
+        Immediate::Lo(Box::new(z)).resolve(&symbols, &(0..0)),
+        Ok(-0x8000)
+    );
+}
+
+#[test]
+fn segment_addresses() {
+    let input = ".data 0x10010100
+x: .word 1
+.text 0x00400100
+main: j main
+.data 0x10010100
+y: .word 2";
+    let program = assemble_str(input).unwrap();
+    let address = |name| program.symbols.get(name).unwrap().address;
+    assert_eq!(address("x"), 0x1001_0100);
+    assert_eq!(address("y"), 0x1001_0104);
+    assert_eq!(address("main"), 0x0040_0100);
+    // the jump encodes the new address
+    let text = program
+        .segments
+        .iter()
+        .find(|s| s.kind == SegmentKind::Text);
+    assert_eq!(text.unwrap().data, [StorableData::Word(0x0810_0040)]);
+}
+
+#[test]
+fn text_align() {
+    let input = ".text
+main: addi $t0, $t0, 1
+.align 3
+loop: j loop
+.align 1
+    jr $ra";
+    let program = assemble_str(input).unwrap();
+    assert_eq!(program.symbols.get("loop").unwrap().address, 0x0040_0008);
+    let text = &program.segments[0];
+    assert_eq!(text.kind, SegmentKind::Text);
+    // the padding is a `nop`, and `.align 1` does nothing after an instruction
+    assert_eq!(
+        text.data[1..],
+        [
+            StorableData::Word(0),
+            StorableData::Word(0x0810_0002),
+            StorableData::Word(0x03e0_0008),
+        ]
+    );
+}
+
+#[test]
+fn errors() {
+    let input = "main: j end
+loop: b loop
+main: lw $t0, missing+4";
+    assert_eq!(
+        assemble_str(input),
+        Err(vec![
+            AssemblerError::new(
+                AssemblerErrorKind::DuplicateLabel {
+                    name: "main".into(),
+                    first: 0..4,
+                },
+                25..29
+            ),
+            AssemblerError::new(AssemblerErrorKind::UndefinedLabel("end".into()), 8..11),
+            AssemblerError::new(AssemblerErrorKind::UndefinedLabel("missing".into()), 39..48),
+            AssemblerError::new(AssemblerErrorKind::UndefinedLabel("missing".into()), 39..48),
+        ])
+    );
+}
+
+#[test]
+fn expressions() {
+    let input = ".data
+start: .word end - start, (end - start) / 4
+.space 2 * 4
+end: .byte 0
+.text
+main: addi $t0, $t0, end - start
+    addi $t0, $t0, end + 4
+    lw $t0, (end - start) / (start - start)
+    addi $t0, $t0, end - missing";
+    let program = assemble_str(input).unwrap_err();
+    assert_eq!(
+        program,
+        vec![
+            AssemblerError::new(
+                AssemblerErrorKind::ValueOutOfRange {
+                    range: -32768..=32767,
+                    expected: "`addi` expects a signed 16 bit immediate here".into(),
+                },
+                134..141
+            ),
+            AssemblerError::new(AssemblerErrorKind::DivisionByZero, 170..185),
+            AssemblerError::new(AssemblerErrorKind::DivisionByZero, 170..185),
+            AssemblerError::new(
+                AssemblerErrorKind::UndefinedLabel("missing".into()),
+                211..218
+            ),
+        ]
+    );
+}
+
+#[test]
+fn data() {
+    let input = ".data
+chars: .byte 97, 0:3, 0x7f
+half: .half -1
+.align 3
+str: .asciiz \"hi\", \"\"
+table: .word 0:2, half, str+1
+.space 3
+dbl: .double 2
+end: .ascii \"!\"";
+    let program = assemble_str(input).unwrap();
+    let symbols = program
+        .symbols
+        .iter()
+        .map(|(name, s)| (name, s.address - 0x1001_0000))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        symbols,
+        vec![
+            ("chars", 0),
+            ("dbl", 0x20),
+            ("end", 0x28),
+            ("half", 6),
+            ("str", 8),
+            ("table", 0xc),
+        ]
+    );
+    use StorableData::*;
+    assert_eq!(
+        program.segments[0].data,
+        vec![
+            Byte(b'a'),
+            Repeat(Box::new(Byte(0)), 3),
+            Byte(0x7f),
+            Space(1),
+            Half(0xffff),
+            Ascii(b"hi\0".to_vec()),
+            Ascii(b"\0".to_vec()),
+            Repeat(Box::new(Word(0)), 2),
+            Word(0x1001_0006),
+            Word(0x1001_0009),
+            Space(3),
+            Space(1),
+            Double(2f64.to_bits()),
+            Ascii(b"!".to_vec()),
+        ]
+    );
+    let size = program.segments[0]
+        .data
+        .iter()
+        .map(|d| d.size())
+        .sum::<u32>();
+    assert_eq!(size, 0x29);
+}
+
+#[test]
+fn data_overflow() {
+    // a run is stored as a single value, however long it is
+    let program = assemble_str(".data\n.word 7:0x1000000").unwrap();
+    assert_eq!(
+        program.segments[0].data,
+        [StorableData::Repeat(
+            Box::new(StorableData::Word(7)),
+            0x100_0000
+        )]
+    );
+
+    // only the first statement past the end is reported
+    let cases = [
+        (".data", ".word 0:2000000000", "\nafter: .word after"),
+        (".data", ".double 0:1000000000", ""),
+        (".data", ".byte 1:4000000000, 1:4000000000", ""),
+        (".kdata", ".space 0x7fff0000", "\n.word 1"),
+    ];
+    for (segment, stmt, rest) in cases {
+        let input = format!("{segment}\n{stmt}{rest}");
+        let start = segment.len() + 1;
+        let kind = match segment {
+            ".kdata" => SegmentKind::Kdata,
+            _ => SegmentKind::Data,
+        };
+        assert_eq!(
+            assemble_str(&input),
+            Err(vec![AssemblerError::new(
+                AssemblerErrorKind::SegmentOverflow(kind),
+                start..start + stmt.len()
+            )]),
+            "{input}"
+        );
+    }
+}
+
+#[test]
+fn floats() {
+    let input = ".data
+.float 1.5, -2, inf
+.double -1.0:2, 3.5e38, nan";
+    let program = assemble_str(input).unwrap();
+    use StorableData::*;
+    assert_eq!(
+        program.segments[0].data,
+        vec![
+            Float(0x3fc0_0000),
+            Float(0xc000_0000),
+            Float(0x7f80_0000),
+            Space(4),
+            Repeat(Box::new(Double(0xbff0_0000_0000_0000)), 2),
+            Double(3.5e38f64.to_bits()),
+            Double(f64::NAN.to_bits()),
+        ]
+    );
+    let Repeat(double, _) = &program.segments[0].data[4] else {
+        panic!("`-1.0:2` should be stored as a run");
+    };
+    assert_eq!(
+        double.to_bytes(Endianness::Little),
+        [0, 0, 0, 0, 0, 0, 0xf0, 0xbf]
+    );
+    assert_eq!(
+        double.to_bytes(Endianness::Big),
+        [0xbf, 0xf0, 0, 0, 0, 0, 0, 0]
+    );
+    let bytes = program.segments[0].data_bytes(Endianness::Little);
+    assert_eq!(bytes[..4], [0, 0, 0xc0, 0x3f]);
+    assert_eq!(bytes.len(), 48);
+}
+
+#[test]
+fn relocations() {
+    let input = ".data
+pad: .space 0x8000
+buf: .word 0
+.text
+main:
+    lui $t0, %hi(buf + 4)
+    addiu $t0, $t0, %lo(buf + 4)
+    lw $t1, %gp_rel(pad)($gp)
+    ori $t2, $zero, %lo(0x12345678)";
+    let program = assemble_dialect(input, Dialect::Gnu).unwrap();
+    let text = &program.segments[1];
+    let values = text
+        .stmts
+        .iter()
+        .filter_map(|s| match s {
+            Statement::Instruction(i) => Some(i.operands.last().unwrap()),
+            _ => None,
+        })
+        .map(|o| match &o.kind {
+            OperandKind::Immediate(imm) => imm.resolve(&program.symbols, &o.span),
+            OperandKind::Address(address) => address.offset.resolve(&program.symbols, &o.span),
+            _ => unreachable!(),
+        })
+        .collect::<Vec<_>>();
+    // `buf + 4` is 0x10018004, whose lower half is negative once sign extended
+    assert_eq!(values, [Ok(0x1002), Ok(-0x7ffc), Ok(-0x7ff0), Ok(0x5678)]);
+    let relocations = text
+        .relocations
+        .iter()
+        .map(|r| (r.kind, r.address, r.symbol.as_str(), r.addend))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        relocations,
+        [
+            (RelocationKind::Hi16, 0x0040_0000, "buf", 4),
+            (RelocationKind::Lo16, 0x0040_0004, "buf", 4),
+            (RelocationKind::GpRel16, 0x0040_0008, "pad", 0),
+        ]
+    );
+
+    let input = ".data
+pad: .space 0x10000
+far: .word 0
+.text
+    lw $t0, %gp_rel(far)($gp)";
+    let errors = assemble_dialect(input, Dialect::Gnu).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(
+        errors[0].kind,
+        AssemblerErrorKind::ValueOutOfRange { .. }
+    ));
+
+    // the simulators start `$gp` 32 KiB past the `.extern` area at 0x10000000
+    let input = ".data\nx: .word 0\n.text\nlw $t0, %gp_rel(x - 0x8004)($gp)";
+    for dialect in [Dialect::Mars, Dialect::Spim] {
+        let program = assemble_dialect(input, dialect).unwrap();
+        assert_eq!(program.symbols.gp(), 0x1000_8000);
+        assert_eq!(program.segments[1].data, [StorableData::Word(0x8f88_fffc)]);
+    }
+}
+
+#[test]
+fn encoding() {
+    let input = "main: add $t0, $t1, $t2
+    addi $t0, $t1, -1
+    lui $t0, 0x1001
+    lw $t0, 4($sp)
+    sw $ra, -8($sp)
+    sll $t0, $t1, 4
+    jr $ra
+    jalr $t9
+    syscall
+    nop
+    ssnop
+    mul $t0, $t1, $t2
+    clz $t0, $t1
+    mfc0 $k0, $13
+    mtc0 $k0, $12
+    eret
+    add.s $f0, $f1, $f2
+    c.eq.d $f2, $f4
+    mfc1 $t0, $f1
+    mtc1 $t0, $f1
+    cvt.d.w $f0, $f2
+    lwc1 $f0, 0($t0)
+    break 1
+    movt $t0, $t1, 1
+    teqi $t0, 5
+    pref 1, 0($t0)
+    sync
+    mfc0 $t0, $12, 1
+back: beq $t0, $t1, next
+    b back
+next: bgez $t0, back
+    bal next
+    bc1t 2, next
+    j main
+    jal next";
+    let program = assemble_str(input).unwrap();
+    let text = program
+        .segments
+        .iter()
+        .find(|s| s.kind == SegmentKind::Text)
+        .unwrap();
+    let words = text
+        .data
+        .iter()
+        .map(|d| match d {
+            StorableData::Word(word) => *word,
+            _ => panic!("Instructions should be stored as words"),
+        })
+        .collect::<Vec<_>>();
+    // the encodings of the MIPS32 manual
+    assert_eq!(
+        words,
+        [
+            0x012a_4020,
+            0x2128_ffff,
+            0x3c08_1001,
+            0x8fa8_0004,
+            0xafbf_fff8,
+            0x0009_4100,
+            0x03e0_0008,
+            0x0320_f809,
+            0x0000_000c,
+            0x0000_0000,
+            0x0000_0040,
+            0x712a_4002,
+            0x7128_4020,
+            0x401a_6800,
+            0x409a_6000,
+            0x4200_0018,
+            0x4602_0800,
+            0x4624_1032,
+            0x4408_0800,
+            0x4488_0800,
+            0x4680_1021,
+            0xc500_0000,
+            0x0000_004d,
+            0x0125_4001,
+            0x050c_0005,
+            0xcd01_0000,
+            0x0000_000f,
+            0x4008_6001,
+            0x1109_0001,
+            0x1000_fffe,
+            0x0501_fffd,
+            0x0411_fffe,
+            0x4509_fffd,
+            0x0810_0000,
+            0x0c10_001e,
+        ]
+    );
+    assert_eq!(
+        text.data_bytes(Endianness::Big)[..4],
+        [0x01, 0x2a, 0x40, 0x20]
+    );
+}
+
+#[test]
+fn pseudo_instructions() {
+    // each pseudo-instruction, and the shortest expansion for its operands
+    let table = [
+        ("li $t0, 5", "addiu $t0, $zero, 5"),
+        ("li $t0, -32768", "addiu $t0, $zero, -32768"),
+        ("li $t0, 0xffff", "ori $t0, $zero, 0xffff"),
+        ("li $t0, 0x10000", "lui $t0, 0x1"),
+        ("li $t0, -65536", "lui $t0, 0xffff"),
+        (
+            "li $t0, 0x12345678",
+            "lui $t0, 0x1234; ori $t0, $t0, 0x5678",
+        ),
+        ("li $t0, 0xffffffff", "addiu $t0, $zero, -1"),
+        ("la $t0, data", "lui $t0, 0x1001; addiu $t0, $t0, 4"),
+        ("la $t0, 8($sp)", "addiu $t0, $sp, 8"),
+        (
+            "la $t0, 0x12345($sp)",
+            "lui $at, 0x1; ori $at, $at, 0x2345; addu $t0, $at, $sp",
+        ),
+        ("move $t0, $t1", "addu $t0, $zero, $t1"),
+        (
+            "blt $t0, $t1, main",
+            "slt $at, $t0, $t1; bne $at, $zero, main",
+        ),
+        (
+            "bgt $t0, $t1, main",
+            "slt $at, $t1, $t0; bne $at, $zero, main",
+        ),
+        (
+            "ble $t0, $t1, main",
+            "slt $at, $t1, $t0; beq $at, $zero, main",
+        ),
+        (
+            "bge $t0, $t1, main",
+            "slt $at, $t0, $t1; beq $at, $zero, main",
+        ),
+        (
+            "bltu $t0, 5, main",
+            "sltiu $at, $t0, 5; bne $at, $zero, main",
+        ),
+        (
+            "bgeu $t0, 0x12345, main",
+            "lui $at, 0x1; ori $at, $at, 0x2345; sltu $at, $t0, $at; beq $at, $zero, main",
+        ),
+        (
+            "bgt $t0, 5, main",
+            "addiu $at, $zero, 5; slt $at, $at, $t0; bne $at, $zero, main",
+        ),
+        ("beqz $t0, main", "beq $t0, $zero, main"),
+        ("bnez $t0, main", "bne $t0, $zero, main"),
+        ("b main", "b main"),
+        ("not $t0, $t1", "nor $t0, $t1, $zero"),
+        ("neg $t0, $t1", "sub $t0, $zero, $t1"),
+        (
+            "abs $t0, $t1",
+            "sra $at, $t1, 31; xor $t0, $t1, $at; subu $t0, $t0, $at",
+        ),
+        ("subi $t0, $t1, 4", "addiu $at, $zero, 4; sub $t0, $t1, $at"),
+        (
+            "subiu $t0, $t1, 0x12345",
+            "lui $at, 0x1; ori $at, $at, 0x2345; subu $t0, $t1, $at",
+        ),
+        ("mul $t0, $t1, $t2", "mul $t0, $t1, $t2"),
+        (
+            "mul $t0, $t1, 10",
+            "addiu $at, $zero, 10; mul $t0, $t1, $at",
+        ),
+        ("div $t0, $t1", "div $t0, $t1"),
+        ("div $t0, $t1, $t2", "div $t1, $t2; mflo $t0"),
+        ("divu $t0, $t1, $t2", "divu $t1, $t2; mflo $t0"),
+        (
+            "rem $t0, $t1, 4",
+            "addiu $at, $zero, 4; div $t1, $at; mfhi $t0",
+        ),
+        ("remu $t0, $t1, $t2", "divu $t1, $t2; mfhi $t0"),
+        ("seq $t0, $t1, $t2", "subu $t0, $t1, $t2; sltiu $t0, $t0, 1"),
+        ("seq $t0, $t1, 3", "xori $t0, $t1, 0x3; sltiu $t0, $t0, 1"),
+        (
+            "sne $t0, $t1, $t2",
+            "subu $t0, $t1, $t2; sltu $t0, $zero, $t0",
+        ),
+        ("sge $t0, $t1, 5", "slti $t0, $t1, 5; xori $t0, $t0, 0x1"),
+        (
+            "sgeu $t0, $t1, $t2",
+            "sltu $t0, $t1, $t2; xori $t0, $t0, 0x1",
+        ),
+        ("sgt $t0, $t1, $t2", "slt $t0, $t2, $t1"),
+        ("sgtu $t0, $t1, $t2", "sltu $t0, $t2, $t1"),
+        ("ulw $t0, 2($t1)", "lwl $t0, 5($t1); lwr $t0, 2($t1)"),
+        (
+            "ulw $t0, 0($t0)",
+            "addiu $at, $t0, 0; lwl $t0, 3($at); lwr $t0, 0($at)",
+        ),
+        (
+            "usw $t0, data",
+            "lui $at, 0x1001; addiu $at, $at, 4; swl $t0, 3($at); swr $t0, 0($at)",
+        ),
+    ];
+    for (input, expected) in table {
+        let program = assemble_str(&format!("main: {input}\n.data\n.word 0\ndata: .word 0"))
+            .unwrap_or_else(|errs| panic!("{input}: {errs:?}"));
+        let text = program
+            .segments
+            .iter()
+            .find(|s| s.kind == SegmentKind::Text)
+            .unwrap();
+        let disassembler = Disassembler::new().with_symbols(&program.symbols);
+        let listing = text
+            .data
+            .iter()
+            .enumerate()
+            .map(|(i, d)| match d {
+                StorableData::Word(word) => {
+                    let address = 0x0040_0000 + 4 * i as u32;
+                    disassembler.format(&decode(*word).unwrap(), address)
+                }
+                _ => panic!("Instructions should be stored as words"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(listing.join("; "), expected, "{input}");
+    }
+}
+
+#[test]
+fn encoding_errors() {
+    let input = "main: j 0x10000000
+    j 0x400002
+    b odd
+    b far
+.data
+.byte 1
+odd: .byte 2
+far: .word 0";
+    let span = |s: &str| {
+        let start = input.find(s).unwrap();
+        start..start + s.len()
+    };
+    assert_eq!(
+        assemble_str(input),
+        Err(vec![
+            AssemblerError::new(
+                AssemblerErrorKind::ValueOutOfRange {
+                    range: 0..=0x0fff_fffc,
+                    expected: "`j` expects an address in the same 256 MiB region as the \
+                               instruction after it"
+                        .into(),
+                },
+                span("0x10000000")
+            ),
+            AssemblerError::new(
+                AssemblerErrorKind::MisalignedTarget(0x40_0002),
+                span("0x400002")
+            ),
+            AssemblerError::new(
+                AssemblerErrorKind::MisalignedTarget(0x1001_0001),
+                span("odd")
+            ),
+            AssemblerError::new(
+                AssemblerErrorKind::ValueOutOfRange {
+                    range: -0x2_0000..=0x1_fffc,
+                    expected: "`b` expects a label within 128 KiB of the instruction after it"
+                        .into(),
+                },
+                span("far")
+            ),
+        ])
+    );
+}
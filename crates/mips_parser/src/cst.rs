@@ -0,0 +1,334 @@
+//! A lossless concrete syntax tree, which keeps the comments and the whitespace of a file so that
+//! it can be printed back exactly as it was written. It's meant for the tools that rewrite the
+//! source, like a formatter; compiling uses the parser instead.
+
+use std::fmt;
+use std::ops::Range;
+
+use crate::defs::dialect::Dialect;
+use crate::defs::directive::Directive;
+use crate::defs::instruction::InstructionKind;
+use crate::defs::pseudo::PseudoKind;
+use crate::defs::token::TokenKind;
+use crate::lexer::Lexer;
+use crate::source::{FileId, SourceDb};
+
+/// The text between two tokens that doesn't affect the meaning of the file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub span: Range<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    Whitespace,
+    Comment,
+}
+
+/// A token along with the trivia around it. The trivia on the same line after a token is
+/// its trailing trivia, the rest belongs to the token that follows it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CstToken<'a> {
+    /// The kind of the token, [`TokenKind::Error`] for the text the lexer couldn't read
+    pub kind: TokenKind<'a>,
+    pub span: Range<usize>,
+    pub leading: Vec<Trivia>,
+    pub trailing: Vec<Trivia>,
+}
+
+/// What a statement is made of, besides its labels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatementKind {
+    /// A line with only trivia or labels
+    Empty,
+    Instruction(InstructionKind),
+    Pseudo(PseudoKind),
+    Directive(Directive),
+    /// A statement that doesn't start with an instruction or a directive, or that contains
+    /// text the lexer couldn't read
+    Error,
+}
+
+/// The tokens of a statement, the last one is the newline or `;` that ends it, if any
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CstStatement<'a> {
+    pub kind: StatementKind,
+    pub tokens: Vec<CstToken<'a>>,
+}
+
+impl CstStatement<'_> {
+    /// Returns the span of the statement, trivia included
+    pub fn span(&self) -> Range<usize> {
+        let start = self
+            .tokens
+            .first()
+            .map_or(0, |tok| match tok.leading.first() {
+                Some(trivia) => trivia.span.start,
+                None => tok.span.start,
+            });
+        let end = self
+            .tokens
+            .last()
+            .map_or(0, |tok| match tok.trailing.last() {
+                Some(trivia) => trivia.span.end,
+                None => tok.span.end,
+            });
+        start..end
+    }
+
+    /// Returns the tokens of the labels defined at the start of the statement, without the
+    /// colons
+    pub fn labels(&self) -> impl Iterator<Item = &CstToken<'_>> {
+        self.tokens
+            .chunks(2)
+            .take_while(|pair| {
+                matches!(
+                    pair,
+                    [
+                        CstToken {
+                            kind: TokenKind::Ident(_) | TokenKind::LocalLabel(_),
+                            ..
+                        },
+                        CstToken {
+                            kind: TokenKind::Colon,
+                            ..
+                        },
+                    ]
+                )
+            })
+            .map(|pair| &pair[0])
+    }
+}
+
+/// The concrete syntax tree of a file. Printing it with [`fmt::Display`] gives back the text
+/// of the file byte for byte, even if it contains errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxTree<'a> {
+    /// The text the spans point into
+    text: &'a str,
+    pub statements: Vec<CstStatement<'a>>,
+    /// The trivia after the newline that ends the last statement
+    pub trailing: Vec<Trivia>,
+}
+
+impl<'a> SyntaxTree<'a> {
+    /// Builds the tree of a file of the database. The spans point into the database, like the
+    /// ones of the compiler. The errors aren't reported: the text that couldn't be read is kept
+    /// in [`TokenKind::Error`] tokens, and compiling the file reports them.
+    pub fn parse(sources: &'a SourceDb, file: FileId, dialect: Dialect) -> Self {
+        let text = sources.text();
+        let mut tree = SyntaxTree {
+            text,
+            statements: Vec::new(),
+            trailing: Vec::new(),
+        };
+        let mut tokens: Vec<CstToken<'a>> = Vec::new();
+        let mut trivia = Vec::new();
+        // the end of the text covered so far, what the lexer skipped after an error is not
+        let mut pos = sources.span(file).start;
+        let lexer = Lexer::for_file(sources, file)
+            .with_dialect(dialect)
+            .with_trivia();
+        for tok in lexer {
+            // the errors are followed by the tokens after the skipped text
+            let Ok(tok) = tok else {
+                continue;
+            };
+            if tok.span.start > pos {
+                tokens.push(CstToken::new(TokenKind::Error, pos..tok.span.start, trivia));
+                trivia = Vec::new();
+            }
+            pos = tok.span.end;
+            let kind = match tok.kind {
+                TokenKind::Whitespace => TriviaKind::Whitespace,
+                TokenKind::Comment => TriviaKind::Comment,
+                TokenKind::Eof => break,
+                kind => {
+                    let ends_statement = kind == TokenKind::Newline;
+                    tokens.push(CstToken::new(kind, tok.span, trivia));
+                    trivia = Vec::new();
+                    if ends_statement {
+                        tree.push_statement(std::mem::take(&mut tokens));
+                    }
+                    continue;
+                }
+            };
+            let trivia_tok = Trivia {
+                kind,
+                span: tok.span,
+            };
+            let multiline = text[trivia_tok.span.clone()].contains('\n');
+            match tokens.last_mut() {
+                // a block comment over several lines ends the statement like a newline
+                Some(last) if multiline => {
+                    last.trailing.push(trivia_tok);
+                    tree.push_statement(std::mem::take(&mut tokens));
+                }
+                Some(last) => last.trailing.push(trivia_tok),
+                None => trivia.push(trivia_tok),
+            }
+        }
+        if tokens.is_empty() {
+            tree.trailing = trivia;
+        } else {
+            tokens.last_mut().unwrap().trailing.extend(trivia);
+            tree.push_statement(tokens);
+        }
+        tree
+    }
+
+    /// Returns the text of a token or of some trivia
+    pub fn text(&self, span: Range<usize>) -> &'a str {
+        &self.text[span]
+    }
+
+    fn push_statement(&mut self, tokens: Vec<CstToken<'a>>) {
+        let kind = statement_kind(&tokens);
+        self.statements.push(CstStatement { kind, tokens });
+    }
+}
+
+impl<'a> CstToken<'a> {
+    fn new(kind: TokenKind<'a>, span: Range<usize>, leading: Vec<Trivia>) -> Self {
+        Self {
+            kind,
+            span,
+            leading,
+            trailing: Vec::new(),
+        }
+    }
+}
+
+/// Returns the kind of the statement made of the tokens
+fn statement_kind(tokens: &[CstToken]) -> StatementKind {
+    if tokens.iter().any(|tok| tok.kind == TokenKind::Error) {
+        return StatementKind::Error;
+    }
+    let mut tokens = tokens.iter().map(|tok| &tok.kind).peekable();
+    // skip the labels
+    while let Some(TokenKind::Ident(_) | TokenKind::LocalLabel(_)) = tokens.peek() {
+        tokens.next();
+        if tokens.next_if_eq(&&TokenKind::Colon).is_none() {
+            return StatementKind::Error;
+        }
+    }
+    match tokens.next() {
+        None | Some(TokenKind::Newline) => StatementKind::Empty,
+        Some(TokenKind::Instruction(kind)) => StatementKind::Instruction(*kind),
+        Some(TokenKind::Pseudo(kind)) => StatementKind::Pseudo(*kind),
+        Some(TokenKind::Directive(directive)) => StatementKind::Directive(*directive),
+        Some(_) => StatementKind::Error,
+    }
+}
+
+impl fmt::Display for SyntaxTree<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let trivia = |f: &mut fmt::Formatter<'_>, trivia: &[Trivia]| {
+            trivia
+                .iter()
+                .try_for_each(|t| f.write_str(self.text(t.span.clone())))
+        };
+        for tok in self.statements.iter().flat_map(|s| &s.tokens) {
+            trivia(f, &tok.leading)?;
+            f.write_str(self.text(tok.span.clone()))?;
+            trivia(f, &tok.trailing)?;
+        }
+        trivia(f, &self.trailing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree_of(sources: &mut SourceDb, input: &str, dialect: Dialect) -> String {
+        let file = sources.add_file("test.asm", input);
+        let tree = SyntaxTree::parse(sources, file, dialect);
+        tree.to_string()
+    }
+
+    #[test]
+    fn round_trip() {
+        let inputs = [
+            "",
+            "\n\n",
+            "  # only a comment",
+            "main: addi $t0, $zero, 1 # one\n\tsyscall\n",
+            "\t.data\nvalues: .word 1, 2,3   \n  # end\n   ",
+            "label:\nloop: j loop\r\n",
+            "addi $t0, $t0, @\n.word 2\n",
+            "la $t0, \"unterminated\nnop",
+        ];
+        for input in inputs {
+            let mut sources = SourceDb::new();
+            assert_eq!(tree_of(&mut sources, input, Dialect::Mars), input);
+        }
+        let gnu = "/* header\n */ li $t0, 1; /* inline */ nop\n1: b 1b /* unterminated";
+        let mut sources = SourceDb::new();
+        assert_eq!(tree_of(&mut sources, gnu, Dialect::Gnu), gnu);
+    }
+
+    #[test]
+    fn trivia() {
+        let mut sources = SourceDb::new();
+        // the spans point into the database, after the first file
+        sources.add_file("first.asm", "nop\n");
+        let input = "  main: # entry\n  jr $ra # return";
+        let file = sources.add_file("test.asm", input);
+        let tree = SyntaxTree::parse(&sources, file, Dialect::Mars);
+        assert_eq!(tree.to_string(), input);
+
+        let kinds: Vec<_> = tree.statements.iter().map(|s| s.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                StatementKind::Empty,
+                StatementKind::Instruction(InstructionKind::Jr)
+            ]
+        );
+        let main = &tree.statements[0];
+        let labels: Vec<_> = main.labels().map(|l| tree.text(l.span.clone())).collect();
+        assert_eq!(labels, ["main"]);
+        assert_eq!(tree.text(main.span()), "  main: # entry\n");
+        let leading: Vec<_> = main.tokens[0].leading.iter().map(|t| t.kind).collect();
+        assert_eq!(leading, [TriviaKind::Whitespace]);
+        let trailing: Vec<_> = main.tokens[1]
+            .trailing
+            .iter()
+            .map(|t| (t.kind, tree.text(t.span.clone())))
+            .collect();
+        assert_eq!(
+            trailing,
+            [
+                (TriviaKind::Whitespace, " "),
+                (TriviaKind::Comment, "# entry")
+            ]
+        );
+        let ra = &tree.statements[1].tokens.last().unwrap();
+        assert_eq!(ra.trailing.len(), 2);
+        assert_eq!(tree.trailing, []);
+    }
+
+    #[test]
+    fn errors() {
+        let mut sources = SourceDb::new();
+        let file = sources.add_file("test.asm", "nop\n add $t0, ` $t1\nfoo bar\n");
+        let tree = SyntaxTree::parse(&sources, file, Dialect::Mars);
+        let kinds: Vec<_> = tree.statements.iter().map(|s| s.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                StatementKind::Instruction(InstructionKind::Nop),
+                StatementKind::Error,
+                StatementKind::Error
+            ]
+        );
+        let error = tree.statements[1]
+            .tokens
+            .iter()
+            .find(|t| t.kind == TokenKind::Error)
+            .unwrap();
+        assert_eq!(tree.text(error.span.clone()), "` $t1");
+    }
+}
@@ -1,22 +1,28 @@
+pub mod dialect;
+pub mod directive;
+pub mod expression;
 pub mod instruction;
-pub(crate) mod register;
-pub(crate) mod token;
-pub(crate) mod directive;
 pub mod program;
+pub mod pseudo;
+pub mod register;
+pub mod symbols;
+pub mod token;
 
 pub(crate) trait ValidBitRepr {}
 impl ValidBitRepr for Bits<32> {}
+impl ValidBitRepr for Bits<26> {}
+impl ValidBitRepr for Bits<16> {}
 impl ValidBitRepr for Bits<6> {}
 impl ValidBitRepr for Bits<5> {}
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BitStorage {
     U8(u8),
     U32(u32),
 }
 
 /// Represents up to 32 bits of information
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[allow(private_bounds)]
 pub struct Bits<const N: usize>
 //PERF: replace with its own type if used only in opcode
@@ -30,22 +36,81 @@ impl<const N: usize> Bits<N>
 where
     Self: ValidBitRepr,
 {
+    /// Panics if `data` doesn't fit in `N` bits, use [`Bits::try_new`] for values that are not
+    /// known to fit
     pub fn new(data: u32) -> Self {
+        Self::try_new(data).unwrap_or_else(|| panic!("{data:#x} doesn't fit in {N} bits"))
+    }
+
+    /// Returns `None` if `data` doesn't fit in `N` bits
+    pub fn try_new(data: u32) -> Option<Self> {
+        if N < 32 && data >> N != 0 {
+            return None;
+        }
         let data = if N <= 8 {
-            assert!(data.leading_zeros() >= 8 - N as u32,);
             BitStorage::U8(data as u8)
-        } else if N <= 32 {
-            assert!(data.leading_zeros() >= 32 - N as u32);
-            BitStorage::U32(data)
         } else {
-            panic!("Bits repr must be at most 32");
+            BitStorage::U32(data)
         };
-        Self { data }
+        Some(Self { data })
+    }
+
+    /// Stores the two's complement of `value`, returns `None` if it doesn't fit in a signed
+    /// field of `N` bits
+    pub fn from_signed(value: i64) -> Option<Self> {
+        let max = (1i64 << (N - 1)) - 1;
+        if !(-max - 1..=max).contains(&value) {
+            return None;
+        }
+        let mask = u32::MAX >> (32 - N);
+        Self::try_new(value as u32 & mask)
     }
+
     pub fn get(&self) -> u32 {
         match self.data {
             BitStorage::U8(data) => data as u32,
             BitStorage::U32(data) => data,
         }
     }
+
+    /// Returns the value of the bits as a signed field of `N` bits
+    pub fn get_signed(&self) -> i32 {
+        ((self.get() << (32 - N)) as i32) >> (32 - N)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bits() {
+        assert_eq!(Bits::<5>::try_new(31).map(|b| b.get()), Some(31));
+        assert_eq!(Bits::<5>::try_new(32), None);
+        assert_eq!(Bits::<6>::try_new(0x100), None);
+        assert_eq!(Bits::<16>::try_new(0xffff).map(|b| b.get()), Some(0xffff));
+        assert_eq!(Bits::<16>::try_new(0x1_0000), None);
+        assert_eq!(
+            Bits::<26>::try_new(0x3ff_ffff).map(|b| b.get()),
+            Some(0x3ff_ffff)
+        );
+        assert_eq!(Bits::<26>::try_new(0x400_0000), None);
+        assert_eq!(
+            Bits::<32>::try_new(u32::MAX).map(|b| b.get()),
+            Some(u32::MAX)
+        );
+
+        assert_eq!(Bits::<16>::from_signed(-1).map(|b| b.get()), Some(0xffff));
+        assert_eq!(
+            Bits::<16>::from_signed(-0x8000).map(|b| b.get()),
+            Some(0x8000)
+        );
+        assert_eq!(Bits::<16>::from_signed(0x8000), None);
+        assert_eq!(Bits::<16>::from_signed(-0x8001), None);
+        assert_eq!(
+            Bits::<16>::from_signed(-2).map(|b| b.get_signed()),
+            Some(-2)
+        );
+        assert_eq!(Bits::<32>::from_signed(-1).map(|b| b.get()), Some(u32::MAX));
+    }
 }
@@ -0,0 +1,113 @@
+use strum::{Display, EnumIter, EnumString};
+
+use super::directive::Directive;
+use super::program::SegmentKind;
+use super::pseudo::PseudoKind;
+use super::register::{Register, RegisterName};
+
+/// The assembler whose syntax the source follows. The dialects agree on the machine instructions
+/// but differ in the pseudo-instructions, the directives, the register names, the comments and
+/// the literals they accept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, EnumString, EnumIter, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum Dialect {
+    /// The MARS simulator, the default
+    #[default]
+    Mars,
+    /// The SPIM simulator
+    Spim,
+    /// The GNU assembler `as`
+    Gnu,
+}
+
+impl Dialect {
+    /// Returns true if the directive exists in this dialect
+    pub fn supports_directive(&self, directive: Directive) -> bool {
+        use Directive::*;
+        match self {
+            Dialect::Mars => !matches!(directive, Global | Section),
+            Dialect::Spim => !matches!(
+                directive,
+                Eqv | Macro | EndMacro | Include | Global | Section
+            ),
+            Dialect::Gnu => !matches!(directive, Eqv | Macro | EndMacro | Kdata | Ktext),
+        }
+    }
+
+    /// Returns true if the pseudo-instruction exists in this dialect. `subi` and `subiu` are
+    /// MARS extensions, SPIM and GNU `as` only have `sub` and `subu`.
+    pub fn supports_pseudo(&self, pseudo: PseudoKind) -> bool {
+        match self {
+            Dialect::Mars => true,
+            Dialect::Spim | Dialect::Gnu => !matches!(pseudo, PseudoKind::Subi | PseudoKind::Subiu),
+        }
+    }
+
+    /// Returns the register a name without the `$` stands for, if it's a name that only this
+    /// dialect accepts, like `$s8` for `$fp`
+    pub fn register_alias(&self, name: &str) -> Option<Register> {
+        match (self, name) {
+            (Dialect::Spim | Dialect::Gnu, "s8") => Some(Register::Name(RegisterName::Fp)),
+            (Dialect::Gnu, _) => {
+                let num = name.strip_prefix('r')?.parse::<u8>().ok()?;
+                (num < 32).then_some(Register::Number(num))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns true if an integer starting with `0`, like `017`, is written in octal
+    pub fn leading_zero_octal(&self) -> bool {
+        matches!(self, Dialect::Spim | Dialect::Gnu)
+    }
+
+    /// Returns true if the dialect has C style `/* */` comments and separates statements on
+    /// the same line with `;`
+    pub fn c_comments(&self) -> bool {
+        *self == Dialect::Gnu
+    }
+
+    /// Returns true if `.set noat` stops the assembler from using `$at` to expand
+    /// pseudo-instructions. In MARS `.set` only defines constants.
+    pub fn has_noat(&self) -> bool {
+        matches!(self, Dialect::Spim | Dialect::Gnu)
+    }
+
+    /// Returns the value of the global pointer `$gp` that `%gp_rel` is relative to. MARS and
+    /// SPIM start `$gp` at `0x10008000`. GNU `ld` sets `_gp` to `0x7ff0` past the start of the
+    /// small data, which here is the data segment.
+    pub fn gp_address(&self) -> u32 {
+        match self {
+            Dialect::Mars | Dialect::Spim => 0x1000_8000,
+            Dialect::Gnu => SegmentKind::Data.base_address() + 0x7ff0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dialects() {
+        assert_eq!("gnu".parse::<Dialect>(), Ok(Dialect::Gnu));
+        assert_eq!(Dialect::default().to_string(), "mars");
+        assert!(Dialect::Gnu.supports_directive(Directive::Global));
+        assert!(!Dialect::Mars.supports_directive(Directive::Section));
+        assert!(!Dialect::Spim.supports_directive(Directive::Eqv));
+        assert!(Dialect::Mars.supports_pseudo(PseudoKind::Subi));
+        assert!(!Dialect::Gnu.supports_pseudo(PseudoKind::Subiu));
+        assert!(Dialect::Gnu.supports_pseudo(PseudoKind::Ulw));
+        assert_eq!(Dialect::Mars.register_alias("s8"), None);
+        assert_eq!(
+            Dialect::Spim.register_alias("s8"),
+            Some(Register::Name(RegisterName::Fp))
+        );
+        assert_eq!(Dialect::Spim.register_alias("r3"), None);
+        assert_eq!(
+            Dialect::Gnu.register_alias("r31"),
+            Some(Register::Number(31))
+        );
+        assert_eq!(Dialect::Gnu.register_alias("r32"), None);
+    }
+}
@@ -1,12 +1,14 @@
-use strum::EnumString;
+use strum::{Display, EnumString};
 
-#[derive(Debug, PartialEq, Eq, EnumString)]
+use super::program::SegmentKind;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display)]
 #[strum(serialize_all = "snake_case")]
 /// A mips directive like `.text`,`.align`,`.half`...
 /// Note that the `.` dot is not represented in the tokens,
 /// a directive token implicitly contains the dot.
-pub(crate) enum Directive {
-    /// Align the next data item on the specified byte boundary
+pub enum Directive {
+    /// Align the next data item or instruction on the specified byte boundary
     Align,
     /// Store the string in the data segment without null terminator
     Ascii,
@@ -26,12 +28,13 @@ pub(crate) enum Directive {
     Extern,
     /// Store the following values as single precision floating point numbers
     Float,
+    /// Set the following labels as global, the GNU spelling of `.globl`
+    Global,
     /// Set the following labels as global
     Globl,
     /// Store the following values as half words (16 bit)
     Half,
     /// Includes the contents of a file, specified as path between quotes
-    // TODO: parse this
     Include,
     /// Begin kdata segment
     Kdata,
@@ -39,6 +42,10 @@ pub(crate) enum Directive {
     Ktext,
     /// Begin macro definition
     Macro,
+    /// Define a constant like `.eqv`, or set an assembler option like `.set noat`
+    Set,
+    /// Begin the segment named by the operand, like `.section .data`
+    Section,
     /// Reserve the specified amount of bytes in the data segment
     Space,
     /// Begin the text segment
@@ -47,6 +54,63 @@ pub(crate) enum Directive {
     Word,
 }
 
+impl Directive {
+    /// Returns the kind of segment this directive begins, if it's a segment directive
+    pub fn segment(&self) -> Option<SegmentKind> {
+        match self {
+            Directive::Data => Some(SegmentKind::Data),
+            Directive::Text => Some(SegmentKind::Text),
+            Directive::Kdata => Some(SegmentKind::Kdata),
+            Directive::Ktext => Some(SegmentKind::Ktext),
+            _ => None,
+        }
+    }
+
+    /// Describes the operands the directive expects, used in error messages
+    pub fn usage(&self) -> &'static str {
+        match self {
+            Directive::Align => "`.align` expects the power of two to align to, like `.align 2`",
+            Directive::Space => "`.space` expects the number of bytes to reserve, like `.space 16`",
+            Directive::Ascii | Directive::Asciiz => {
+                "`.ascii` and `.asciiz` expect a comma separated list of strings"
+            }
+            Directive::Byte | Directive::Half | Directive::Word => {
+                "`.byte`, `.half` and `.word` expect a comma separated list of integers"
+            }
+            Directive::Float | Directive::Double => {
+                "`.float` and `.double` expect a comma separated list of numbers"
+            }
+            Directive::Globl | Directive::Global => "`.globl` expects a list of labels",
+            Directive::Section => "`.section` expects the name of a segment, like `.section .data`",
+            Directive::Extern => "`.extern` expects a label and its size in bytes",
+            Directive::Eqv => "`.eqv` expects a name and the constant it stands for",
+            Directive::Set => "`.set` expects a name and the constant it stands for, or an option",
+            Directive::Include => "`.include` expects the path of a file between quotes",
+            Directive::Macro => "`.macro` expects the name of the macro and its parameters",
+            Directive::Data | Directive::Text | Directive::Kdata | Directive::Ktext => {
+                "segment directives expect an optional start address"
+            }
+            Directive::EndMacro => "`.end_macro` expects no operands",
+        }
+    }
+
+    /// Returns true if this directive stores or reserves data in the data segment
+    pub fn is_data(&self) -> bool {
+        matches!(
+            self,
+            Directive::Align
+                | Directive::Ascii
+                | Directive::Asciiz
+                | Directive::Byte
+                | Directive::Double
+                | Directive::Float
+                | Directive::Half
+                | Directive::Space
+                | Directive::Word
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::defs::directive::Directive;
@@ -59,7 +123,7 @@ mod tests {
         let strs = ["kdata", "end_macro", "endmacro", "EndMacro", "asciiz","ascii", "include", "word"];
         #[rustfmt::skip]
         let dirs = [Ok(Kdata), Ok(EndMacro), ERR, ERR, Ok(Asciiz), Ok(Ascii), Ok(Include), Ok(Word)];
-        for (s, d) in strs.into_iter().zip(dirs.into_iter()) {
+        for (s, d) in strs.into_iter().zip(dirs) {
             assert_eq!(s.parse::<Directive>(), d);
         }
     }
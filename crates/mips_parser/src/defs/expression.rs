@@ -0,0 +1,347 @@
+use std::ops::Range;
+
+use strum::Display;
+
+/// A constant expression like `(SIZE * 4) + 1` or `end - start`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Range<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExprKind {
+    Number(i64),
+    /// A label, or a constant defined with `.eqv` or `.set`
+    Symbol(String),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum UnaryOp {
+    #[strum(serialize = "-")]
+    Neg,
+    #[strum(serialize = "~")]
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum BinaryOp {
+    #[strum(serialize = "+")]
+    Add,
+    #[strum(serialize = "-")]
+    Sub,
+    #[strum(serialize = "*")]
+    Mul,
+    #[strum(serialize = "/")]
+    Div,
+    #[strum(serialize = "%")]
+    Rem,
+    #[strum(serialize = "<<")]
+    Shl,
+    #[strum(serialize = ">>")]
+    Shr,
+    #[strum(serialize = "&")]
+    And,
+    #[strum(serialize = "|")]
+    Or,
+    #[strum(serialize = "^")]
+    Xor,
+}
+
+impl BinaryOp {
+    /// Returns how tightly the operator binds, operators with a higher precedence are applied
+    /// first. The order is the same as in C.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Or => 0,
+            BinaryOp::Xor => 1,
+            BinaryOp::And => 2,
+            BinaryOp::Shl | BinaryOp::Shr => 3,
+            BinaryOp::Add | BinaryOp::Sub => 4,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => 5,
+        }
+    }
+
+    /// Checks the right operand on its own: divisors can't be zero and shift amounts must be
+    /// less than the 64 bits of the values
+    fn check_rhs(&self, rhs: i64) -> Result<(), EvalErrorKind> {
+        match self {
+            BinaryOp::Div | BinaryOp::Rem if rhs == 0 => Err(EvalErrorKind::DivisionByZero),
+            BinaryOp::Shl | BinaryOp::Shr if !(0..64).contains(&rhs) => {
+                Err(EvalErrorKind::ShiftOutOfRange(rhs))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn apply(&self, lhs: i64, rhs: i64) -> Result<i64, EvalErrorKind> {
+        self.check_rhs(rhs)?;
+        let value = match self {
+            BinaryOp::Add => lhs.checked_add(rhs),
+            BinaryOp::Sub => lhs.checked_sub(rhs),
+            BinaryOp::Mul => lhs.checked_mul(rhs),
+            BinaryOp::Div => lhs.checked_div(rhs),
+            BinaryOp::Rem => lhs.checked_rem(rhs),
+            BinaryOp::Shl => Some(lhs << rhs),
+            BinaryOp::Shr => Some(lhs >> rhs),
+            BinaryOp::And => Some(lhs & rhs),
+            BinaryOp::Or => Some(lhs | rhs),
+            BinaryOp::Xor => Some(lhs ^ rhs),
+        };
+        value.ok_or(EvalErrorKind::Overflow)
+    }
+}
+
+/// An error found while computing the value of an expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalError {
+    pub kind: EvalErrorKind,
+    /// The span of the sub-expression that caused the error
+    pub span: Range<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalErrorKind {
+    DivisionByZero,
+    /// A shift by a negative amount or by 64 bits or more
+    ShiftOutOfRange(i64),
+    /// The result doesn't fit in 64 bits
+    Overflow,
+    UndefinedSymbol(String),
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Range<usize>) -> Self {
+        Self { kind, span }
+    }
+
+    /// Returns the symbols the expression depends on, in the order they appear
+    pub fn symbols(&self) -> Vec<&str> {
+        match &self.kind {
+            ExprKind::Number(_) => Vec::new(),
+            ExprKind::Symbol(name) => vec![name],
+            ExprKind::Unary(_, expr) => expr.symbols(),
+            ExprKind::Binary(_, lhs, rhs) => {
+                let mut symbols = lhs.symbols();
+                symbols.extend(rhs.symbols());
+                symbols
+            }
+        }
+    }
+
+    /// Computes the value of the expression, `lookup` returns the value of a symbol.
+    /// The values are 64 bit integers, an operation whose result doesn't fit is an error.
+    pub fn evaluate(&self, lookup: &dyn Fn(&str) -> Option<i64>) -> Result<i64, EvalError> {
+        match &self.kind {
+            ExprKind::Number(num) => Ok(*num),
+            ExprKind::Symbol(name) => lookup(name).ok_or_else(|| EvalError {
+                kind: EvalErrorKind::UndefinedSymbol(name.clone()),
+                span: self.span.clone(),
+            }),
+            ExprKind::Unary(op, expr) => {
+                let value = expr.evaluate(lookup)?;
+                match op {
+                    UnaryOp::Neg => value.checked_neg().ok_or_else(|| EvalError {
+                        kind: EvalErrorKind::Overflow,
+                        span: self.span.clone(),
+                    }),
+                    UnaryOp::Not => Ok(!value),
+                }
+            }
+            ExprKind::Binary(op, lhs, rhs) => {
+                let (lhs, rhs_value) = (lhs.evaluate(lookup)?, rhs.evaluate(lookup)?);
+                op.apply(lhs, rhs_value).map_err(|kind| {
+                    // the errors about the right operand point to it
+                    let span = match kind {
+                        EvalErrorKind::Overflow => self.span.clone(),
+                        _ => rhs.span.clone(),
+                    };
+                    EvalError { kind, span }
+                })
+            }
+        }
+    }
+
+    /// Replaces the symbols known by `lookup` with their value and computes the sub-expressions
+    /// that don't depend on other symbols
+    pub fn simplify(self, lookup: &dyn Fn(&str) -> Option<i64>) -> Result<Expr, EvalError> {
+        let kind = match self.kind {
+            ExprKind::Number(_) => return Ok(self),
+            ExprKind::Symbol(ref name) => match lookup(name) {
+                Some(value) => ExprKind::Number(value),
+                None => return Ok(self),
+            },
+            ExprKind::Unary(op, expr) => ExprKind::Unary(op, Box::new(expr.simplify(lookup)?)),
+            ExprKind::Binary(op, lhs, rhs) => ExprKind::Binary(
+                op,
+                Box::new(lhs.simplify(lookup)?),
+                Box::new(rhs.simplify(lookup)?),
+            ),
+        };
+        // a constant right operand can be wrong even if the left one depends on a label
+        if let ExprKind::Binary(op, _, rhs) = &kind {
+            if let ExprKind::Number(value) = rhs.kind {
+                op.check_rhs(value).map_err(|kind| EvalError {
+                    kind,
+                    span: rhs.span.clone(),
+                })?;
+            }
+        }
+        let expr = Expr::new(kind, self.span);
+        // the sub-expressions are already simplified, so this only succeeds if they are numbers
+        match expr.evaluate(&|_| None) {
+            Ok(value) => Ok(Expr::new(ExprKind::Number(value), expr.span)),
+            Err(EvalError {
+                kind: EvalErrorKind::UndefinedSymbol(_),
+                ..
+            }) => Ok(expr),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            ExprKind::Number(num) => write!(f, "{num}"),
+            ExprKind::Symbol(name) => write!(f, "{name}"),
+            ExprKind::Unary(op, expr) => write!(f, "{op}{expr}"),
+            ExprKind::Binary(op, lhs, rhs) => write!(f, "({lhs} {op} {rhs})"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn num(num: i64, span: Range<usize>) -> Box<Expr> {
+        Box::new(Expr::new(ExprKind::Number(num), span))
+    }
+
+    fn symbol(name: &str, span: Range<usize>) -> Box<Expr> {
+        Box::new(Expr::new(ExprKind::Symbol(name.into()), span))
+    }
+
+    #[test]
+    fn evaluate() {
+        // ~(SIZE << 2) % 7
+        let expr = Expr::new(
+            ExprKind::Binary(
+                BinaryOp::Rem,
+                Box::new(Expr::new(
+                    ExprKind::Unary(
+                        UnaryOp::Not,
+                        Box::new(Expr::new(
+                            ExprKind::Binary(BinaryOp::Shl, symbol("SIZE", 2..6), num(2, 10..11)),
+                            1..12,
+                        )),
+                    ),
+                    0..12,
+                )),
+                num(7, 15..16),
+            ),
+            0..16,
+        );
+        let lookup = |name: &str| (name == "SIZE").then_some(3);
+        assert_eq!(expr.evaluate(&lookup), Ok(!(3 << 2) % 7));
+        assert_eq!(
+            expr.evaluate(&|_| None),
+            Err(EvalError {
+                kind: EvalErrorKind::UndefinedSymbol("SIZE".into()),
+                span: 2..6
+            })
+        );
+        assert_eq!(expr.to_string(), "(~(SIZE << 2) % 7)");
+    }
+
+    #[test]
+    fn simplify() {
+        // end - start + 2 * 4
+        let expr = Expr::new(
+            ExprKind::Binary(
+                BinaryOp::Add,
+                Box::new(Expr::new(
+                    ExprKind::Binary(BinaryOp::Sub, symbol("end", 0..3), symbol("start", 6..11)),
+                    0..11,
+                )),
+                Box::new(Expr::new(
+                    ExprKind::Binary(BinaryOp::Mul, num(2, 14..15), num(4, 18..19)),
+                    14..19,
+                )),
+            ),
+            0..19,
+        );
+        let simplified = expr.simplify(&|_| None).unwrap();
+        assert_eq!(simplified.to_string(), "((end - start) + 8)");
+        let lookup = |name: &str| match name {
+            "start" => Some(0x40),
+            "end" => Some(0x64),
+            _ => None,
+        };
+        assert_eq!(
+            simplified.simplify(&lookup).unwrap().kind,
+            ExprKind::Number(0x24 + 8)
+        );
+
+        let expr = Expr::new(
+            ExprKind::Binary(BinaryOp::Div, symbol("x", 0..1), num(0, 4..5)),
+            0..5,
+        );
+        assert_eq!(
+            expr.simplify(&|_| None),
+            Err(EvalError {
+                kind: EvalErrorKind::DivisionByZero,
+                span: 4..5
+            })
+        );
+    }
+
+    #[test]
+    fn shifts_and_overflow() {
+        let binary =
+            |op, lhs, rhs| Expr::new(ExprKind::Binary(op, num(lhs, 0..1), num(rhs, 4..6)), 0..6);
+        let error = |kind, span| EvalError { kind, span };
+        assert_eq!(
+            binary(BinaryOp::Shl, 1, 63).evaluate(&|_| None),
+            Ok(i64::MIN)
+        );
+        assert_eq!(binary(BinaryOp::Shr, -8, 2).evaluate(&|_| None), Ok(-2));
+        assert_eq!(
+            binary(BinaryOp::Shl, 1, 64)
+                .evaluate(&|_| None)
+                .unwrap_err(),
+            error(EvalErrorKind::ShiftOutOfRange(64), 4..6)
+        );
+        assert_eq!(
+            binary(BinaryOp::Shr, 1, -1)
+                .evaluate(&|_| None)
+                .unwrap_err(),
+            error(EvalErrorKind::ShiftOutOfRange(-1), 4..6)
+        );
+        assert_eq!(
+            binary(BinaryOp::Div, i64::MIN, -1)
+                .evaluate(&|_| None)
+                .unwrap_err(),
+            error(EvalErrorKind::Overflow, 0..6)
+        );
+        assert_eq!(
+            binary(BinaryOp::Mul, i64::MAX, 2)
+                .simplify(&|_| None)
+                .unwrap_err(),
+            error(EvalErrorKind::Overflow, 0..6)
+        );
+
+        // the shift amount is checked even if the value depends on a label
+        let expr = Expr::new(
+            ExprKind::Binary(BinaryOp::Shl, symbol("x", 0..1), num(99, 4..6)),
+            0..6,
+        );
+        assert_eq!(
+            expr.simplify(&|_| None).unwrap_err(),
+            error(EvalErrorKind::ShiftOutOfRange(99), 4..6)
+        );
+    }
+}
@@ -1,6 +1,12 @@
-use strum::EnumString;
+use std::ops::RangeInclusive;
 
-use super::Bits;
+use instruction_encoding_derive::InstructionEncoding;
+use strum::{Display, EnumIter, EnumString};
+
+use super::{
+    program::{Operand, OperandKind},
+    Bits,
+};
 
 /// The instruction format defines how the bits that compose it are interpreted.
 /// The three possible variants contain documentation for the respective bit layout.
@@ -22,231 +28,985 @@ pub trait InstructionEncoding {
     fn format(&self) -> InstructionFormat;
     fn opcode(&self) -> Bits<6>;
     fn funct(&self) -> Option<Bits<6>>;
+    /// The fixed value of the rs field, for the instructions it selects like the COP0 ones
+    fn rs(&self) -> Option<Bits<5>>;
+    /// The fixed value of the rt field, for the instructions it selects like the REGIMM ones
+    fn rt(&self) -> Option<Bits<5>>;
+    /// The fixed value of the rd field, for the instructions it selects
+    fn rd(&self) -> Option<Bits<5>>;
+    /// The fixed value of the shamt field, for the instructions it selects like `ssnop`
+    fn shamt(&self) -> Option<Bits<5>>;
+}
+
+/// The values of the fields of an instruction after its opcode, laid out in its format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fields {
+    R {
+        rs: Bits<5>,
+        rt: Bits<5>,
+        rd: Bits<5>,
+        shamt: Bits<5>,
+        funct: Bits<6>,
+    },
+    I {
+        rs: Bits<5>,
+        rt: Bits<5>,
+        imm: Bits<16>,
+    },
+    J {
+        target: Bits<26>,
+    },
+}
+
+/// An instruction with its operands resolved to the values of its fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MachineInstruction {
+    pub kind: InstructionKind,
+    pub fields: Fields,
 }
 
-#[derive(Debug, PartialEq, Eq, EnumString)]
+impl MachineInstruction {
+    /// Returns the machine word of the instruction
+    pub fn encode(&self) -> u32 {
+        let opcode = self.kind.opcode().get() << 26;
+        match self.fields {
+            Fields::R {
+                rs,
+                rt,
+                rd,
+                shamt,
+                funct,
+            } => {
+                opcode
+                    | rs.get() << 21
+                    | rt.get() << 16
+                    | rd.get() << 11
+                    | shamt.get() << 6
+                    | funct.get()
+            }
+            Fields::I { rs, rt, imm } => opcode | rs.get() << 21 | rt.get() << 16 | imm.get(),
+            Fields::J { target } => opcode | target.get(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, EnumIter, Display, InstructionEncoding)]
 #[strum(serialize_all = "lowercase")]
-/// All possible instructions
-//TODO: pseudo-instructions
-pub(crate) enum InstructionKind {
+/// All possible machine instructions, the pseudo-instructions are in
+/// [`PseudoKind`](super::pseudo::PseudoKind)
+pub enum InstructionKind {
     /***** ARITHMETIC INSTRUCTIONS *****/
     /// Add Word
-    // #[instruction(opcode=0b010010, format=J)]
+    #[instruction(0b000000, R, 0b100000)]
     Add,
     /// Add Immediate Word
+    #[instruction(0b001000, I)]
     Addi,
     /// Add Immediate Unsigned Word
+    #[instruction(0b001001, I)]
     Addiu,
     /// Add unsigned word
+    #[instruction(0b000000, R, 0b100001)]
     Addu,
     /// Count leading ones in word
+    #[instruction(0b011100, R, 0b100001)]
     Clo,
     /// Count leading zeros in word
+    #[instruction(0b011100, R, 0b100000)]
     Clz,
     /// Divide word
+    #[instruction(0b000000, R, 0b011010)]
     Div,
     /// Divide unsigned word
+    #[instruction(0b000000, R, 0b011011)]
     Divu,
     /// Multiply and add word to hi, lo
+    #[instruction(0b011100, R, 0b000000)]
     Madd,
     /// Multiply and add unsigned word to hi, lo
+    #[instruction(0b011100, R, 0b000001)]
     Maddu,
     /// Multiply and subtract word to hi, lo
+    #[instruction(0b011100, R, 0b000100)]
     Msub,
     /// Multiply and subtract unsigned word to hi, lo
+    #[instruction(0b011100, R, 0b000101)]
     Msubu,
     /// Multiply word to gpr
+    #[instruction(0b011100, R, 0b000010)]
     Mul,
     /// Multiply word
+    #[instruction(0b000000, R, 0b011000)]
     Mult,
     /// Multiply unsigned word
+    #[instruction(0b000000, R, 0b011001)]
     Multu,
     /// Set on less than
+    #[instruction(0b000000, R, 0b101010)]
     Slt,
     /// Set on less than immediate
+    #[instruction(0b001010, I)]
     Slti,
     /// Set on less than immediate unsigned
+    #[instruction(0b001011, I)]
     Sltiu,
     /// Set on less than unsigned
+    #[instruction(0b000000, R, 0b101011)]
     Sltu,
     /// Subtract word
+    #[instruction(0b000000, R, 0b100010)]
     Sub,
     /// Subtract unsigned word
+    #[instruction(0b000000, R, 0b100011)]
     Subu,
 
     /***** BRANCH AND JUMP *****/
     /// Unconditional Branch
+    #[instruction(0b000100, I, rs = 0b00000, rt = 0b00000)]
     B,
     /// Branch and link
+    #[instruction(0b000001, I, rs = 0b00000, rt = 0b10001)]
     Bal,
     /// Branch on equal
+    #[instruction(0b000100, I)]
     Beq,
     /// Branch on greater than or equal to zero
+    #[instruction(0b000001, I, rt = 0b00001)]
     Bgez,
     /// Branch on greater than or equal to zero and link
+    #[instruction(0b000001, I, rt = 0b10001)]
     Bgezal,
     /// Branch on greater than zero
+    #[instruction(0b000111, I)]
     Bgtz,
     /// Branch on less than or equal to zero
+    #[instruction(0b000110, I)]
     Blez,
     /// Branch on less than zero
+    #[instruction(0b000001, I, rt = 0b00000)]
     Bltz,
     /// Branch on less than zero and link
-    Bbltzal,
+    #[instruction(0b000001, I, rt = 0b10000)]
+    Bltzal,
     /// Branch on not equal
+    #[instruction(0b000101, I)]
     Bne,
     /// Jump
+    #[instruction(0b000010, J)]
     J,
     /// Jump and link
+    #[instruction(0b000011, J)]
     Jal,
     /// Jump and link register
+    #[instruction(0b000000, R, 0b001001)]
     Jalr,
     /// Jump register
+    #[instruction(0b000000, R, 0b001000)]
     Jr,
 
     /***** CPU CONTROL *****/
     /// No Operation
+    #[instruction(0b000000, R, 0b000000)]
     Nop,
     /// Superscalar No Operation
+    #[instruction(0b000000, R, 0b000000, shamt = 0b00001)]
     Ssnop,
 
     /***** LOAD, STORE, AND MEMORY *****/
     /// Load Byte
+    #[instruction(0b100000, I)]
     Lb,
     /// Load Byte Unsigned
+    #[instruction(0b100100, I)]
     Lbu,
     /// Load Halfword
+    #[instruction(0b100001, I)]
     Lh,
     /// Load Halfword Unsigned
+    #[instruction(0b100101, I)]
     Lhu,
     /// Load Linked Word
+    #[instruction(0b110000, I)]
     Ll,
     /// Load Word
+    #[instruction(0b100011, I)]
     Lw,
     /// Load Word Left
+    #[instruction(0b100010, I)]
     Lwl,
     /// Load Word Right
+    #[instruction(0b100110, I)]
     Lwr,
     /// Prefetch
+    #[instruction(0b110011, I)]
     Pref,
     /// Store Byte
+    #[instruction(0b101000, I)]
     Sb,
     /// Store Conditional Word
+    #[instruction(0b111000, I)]
     Sc,
     /// Store Doubleword
+    #[instruction(0b111111, I)]
     Sd,
     /// Store Halfword
+    #[instruction(0b101001, I)]
     Sh,
     /// Store Word
+    #[instruction(0b101011, I)]
     Sw,
     /// Store Word Left
+    #[instruction(0b101010, I)]
     Swl,
     /// Store Word Right
+    #[instruction(0b101110, I)]
     Swr,
     /// Synchronize Shared Memory
+    #[instruction(0b000000, R, 0b001111)]
     Sync,
 
     /***** LOGICAL INSTRUCTIONS *****/
     /// And
+    #[instruction(0b000000, R, 0b100100)]
     And,
     /// And Immediate
+    #[instruction(0b001100, I)]
     Andi,
     /// Load Upper Immediate
+    #[instruction(0b001111, I)]
     Lui,
     /// Not Or
+    #[instruction(0b000000, R, 0b100111)]
     Nor,
     /// Or
+    #[instruction(0b000000, R, 0b100101)]
     Or,
     /// Or Immediate
+    #[instruction(0b001101, I)]
     Ori,
     /// Exclusive Or
+    #[instruction(0b000000, R, 0b100110)]
     Xor,
     /// Exclusive Or Immediate
+    #[instruction(0b001110, I)]
     Xori,
 
     /***** MOVE INSTRUCTIONS *****/
     /// Move From HI Register
+    #[instruction(0b000000, R, 0b010000)]
     Mfhi,
     /// Move From LO Register
+    #[instruction(0b000000, R, 0b010010)]
     Mflo,
     /// Move Conditional on Floating Point False
+    #[instruction(0b000000, R, 0b000001, rt = 0b00000)]
     Movf,
     /// Move Conditional on Not Zero
+    #[instruction(0b000000, R, 0b001011)]
     Movn,
     /// Move Conditional on Floating Point True
+    #[instruction(0b000000, R, 0b000001, rt = 0b00001)]
     Movt,
     /// Move Conditional on Zero
+    #[instruction(0b000000, R, 0b001010)]
     Movz,
     /// Move To HI Register
+    #[instruction(0b000000, R, 0b010001)]
     Mthi,
     /// Move To LO Register
+    #[instruction(0b000000, R, 0b010011)]
     Mtlo,
 
     /***** SHIFT INSTRUCTIONS *****/
     /// Shift Word Left Logical
+    #[instruction(0b000000, R, 0b000000)]
     Sll,
     /// Shift Word Left Logical Variable
+    #[instruction(0b000000, R, 0b000100)]
     Sllv,
     /// Shift Word Right Arithmetic
+    #[instruction(0b000000, R, 0b000011)]
     Sra,
     /// Shift Word Right Arithmetic Variable
+    #[instruction(0b000000, R, 0b000111)]
     Srav,
     /// Shift Word Right Logical
+    #[instruction(0b000000, R, 0b000010)]
     Srl,
     /// Shift Word Right Logical Variable
+    #[instruction(0b000000, R, 0b000110)]
     Srlv,
 
     /***** TRAP INSTRUCTIONS *****/
     /// Breakpoint
+    #[instruction(0b000000, R, 0b001101)]
     Break,
     /// System Call
+    #[instruction(0b000000, R, 0b001100)]
     Syscall,
     /// Trap if Equal
+    #[instruction(0b000000, R, 0b110100)]
     Teq,
     /// Trap if Equal Immediate
+    #[instruction(0b000001, I, rt = 0b01100)]
     Teqi,
     /// Trap if Greater or Equal
+    #[instruction(0b000000, R, 0b110000)]
     Tge,
     /// Trap if Greater of Equal Immediate
+    #[instruction(0b000001, I, rt = 0b01000)]
     Tgei,
     /// Trap if Greater or Equal Immediate Unsigned
+    #[instruction(0b000001, I, rt = 0b01001)]
     Tgeiu,
     /// Trap if Greater or Equal Unsigned
+    #[instruction(0b000000, R, 0b110001)]
     Tgeu,
     /// Trap if Less Than
+    #[instruction(0b000000, R, 0b110010)]
     Tlt,
     /// Trap if Less Than Immediate
+    #[instruction(0b000001, I, rt = 0b01010)]
     Tlti,
     /// Trap if Less Than Immediate Unsigned
+    #[instruction(0b000001, I, rt = 0b01011)]
     Tltiu,
     /// Trap if Less Than Unsigned
+    #[instruction(0b000000, R, 0b110011)]
     Tltu,
     /// Trap if Not Equal
+    #[instruction(0b000000, R, 0b110110)]
     Tne,
     /// Trap if Not Equal Immediate
+    #[instruction(0b000001, I, rt = 0b01110)]
     Tnei,
 
     /***** PRIVILEGED INSTRUCTIONS *****/
     /// Perform Cache Operation
+    #[instruction(0b101111, I)]
     Cache,
     /// Exception Return
+    #[instruction(0b010000, R, 0b011000, rs = 0b10000)]
     Eret,
     /// Move from Coprocessor 0
+    #[instruction(0b010000, R, 0b000000, rs = 0b00000)]
     Mfc0,
     /// Move to Coprocessor 0
+    #[instruction(0b010000, R, 0b000000, rs = 0b00100)]
     Mtc0,
     /// Probe TLB for Matching Entry
+    #[instruction(0b010000, R, 0b001000, rs = 0b10000)]
     Tlbp,
     /// Read Indexed TLB Entry
+    #[instruction(0b010000, R, 0b000001, rs = 0b10000)]
     Tlbr,
     /// Write Indexed TLB Entry
+    #[instruction(0b010000, R, 0b000010, rs = 0b10000)]
     Tlbwi,
     /// Write Random TLB Entry
+    #[instruction(0b010000, R, 0b000110, rs = 0b10000)]
     Tlbwr,
     /// Enter Standby Mode
+    #[instruction(0b010000, R, 0b100000, rs = 0b10000)]
     Wait,
     /// Debug Exception Return
+    #[instruction(0b010000, R, 0b011111, rs = 0b10000)]
     Deret,
     /// Software Debug Breakpoint
+    #[instruction(0b011100, R, 0b111111)]
     Sdbbp,
+
+    /***** FLOATING POINT INSTRUCTIONS *****/
+    /// Floating Point Absolute Value Single
+    #[strum(serialize = "abs.s")]
+    #[instruction(0b010001, R, 0b000101, rs = 0b10000)]
+    AbsS,
+    /// Floating Point Absolute Value Double
+    #[strum(serialize = "abs.d")]
+    #[instruction(0b010001, R, 0b000101, rs = 0b10001)]
+    AbsD,
+    /// Floating Point Add Single
+    #[strum(serialize = "add.s")]
+    #[instruction(0b010001, R, 0b000000, rs = 0b10000)]
+    AddS,
+    /// Floating Point Add Double
+    #[strum(serialize = "add.d")]
+    #[instruction(0b010001, R, 0b000000, rs = 0b10001)]
+    AddD,
+    /// Branch on FP False
+    #[instruction(0b010001, I, rs = 0b01000, rt = 0b00000)]
+    Bc1f,
+    /// Branch on FP True
+    #[instruction(0b010001, I, rs = 0b01000, rt = 0b00001)]
+    Bc1t,
+    /// Floating Point Compare Equal Single
+    #[strum(serialize = "c.eq.s")]
+    #[instruction(0b010001, R, 0b110010, rs = 0b10000)]
+    CEqS,
+    /// Floating Point Compare Equal Double
+    #[strum(serialize = "c.eq.d")]
+    #[instruction(0b010001, R, 0b110010, rs = 0b10001)]
+    CEqD,
+    /// Floating Point Compare Less Than or Equal Single
+    #[strum(serialize = "c.le.s")]
+    #[instruction(0b010001, R, 0b111110, rs = 0b10000)]
+    CLeS,
+    /// Floating Point Compare Less Than or Equal Double
+    #[strum(serialize = "c.le.d")]
+    #[instruction(0b010001, R, 0b111110, rs = 0b10001)]
+    CLeD,
+    /// Floating Point Compare Less Than Single
+    #[strum(serialize = "c.lt.s")]
+    #[instruction(0b010001, R, 0b111100, rs = 0b10000)]
+    CLtS,
+    /// Floating Point Compare Less Than Double
+    #[strum(serialize = "c.lt.d")]
+    #[instruction(0b010001, R, 0b111100, rs = 0b10001)]
+    CLtD,
+    /// Floating Point Convert Double to Single
+    #[strum(serialize = "cvt.s.d")]
+    #[instruction(0b010001, R, 0b100000, rs = 0b10001)]
+    CvtSD,
+    /// Floating Point Convert Word to Single
+    #[strum(serialize = "cvt.s.w")]
+    #[instruction(0b010001, R, 0b100000, rs = 0b10100)]
+    CvtSW,
+    /// Floating Point Convert Single to Double
+    #[strum(serialize = "cvt.d.s")]
+    #[instruction(0b010001, R, 0b100001, rs = 0b10000)]
+    CvtDS,
+    /// Floating Point Convert Word to Double
+    #[strum(serialize = "cvt.d.w")]
+    #[instruction(0b010001, R, 0b100001, rs = 0b10100)]
+    CvtDW,
+    /// Floating Point Convert Single to Word
+    #[strum(serialize = "cvt.w.s")]
+    #[instruction(0b010001, R, 0b100100, rs = 0b10000)]
+    CvtWS,
+    /// Floating Point Convert Double to Word
+    #[strum(serialize = "cvt.w.d")]
+    #[instruction(0b010001, R, 0b100100, rs = 0b10001)]
+    CvtWD,
+    /// Floating Point Divide Single
+    #[strum(serialize = "div.s")]
+    #[instruction(0b010001, R, 0b000011, rs = 0b10000)]
+    DivS,
+    /// Floating Point Divide Double
+    #[strum(serialize = "div.d")]
+    #[instruction(0b010001, R, 0b000011, rs = 0b10001)]
+    DivD,
+    /// Load Doubleword to Floating Point
+    #[instruction(0b110101, I)]
+    Ldc1,
+    /// Load Word to Floating Point
+    #[instruction(0b110001, I)]
+    Lwc1,
+    /// Move Word From Floating Point
+    #[instruction(0b010001, R, 0b000000, rs = 0b00000)]
+    Mfc1,
+    /// Floating Point Move Single
+    #[strum(serialize = "mov.s")]
+    #[instruction(0b010001, R, 0b000110, rs = 0b10000)]
+    MovS,
+    /// Floating Point Move Double
+    #[strum(serialize = "mov.d")]
+    #[instruction(0b010001, R, 0b000110, rs = 0b10001)]
+    MovD,
+    /// Move Word to Floating Point
+    #[instruction(0b010001, R, 0b000000, rs = 0b00100)]
+    Mtc1,
+    /// Floating Point Multiply Single
+    #[strum(serialize = "mul.s")]
+    #[instruction(0b010001, R, 0b000010, rs = 0b10000)]
+    MulS,
+    /// Floating Point Multiply Double
+    #[strum(serialize = "mul.d")]
+    #[instruction(0b010001, R, 0b000010, rs = 0b10001)]
+    MulD,
+    /// Floating Point Negate Single
+    #[strum(serialize = "neg.s")]
+    #[instruction(0b010001, R, 0b000111, rs = 0b10000)]
+    NegS,
+    /// Floating Point Negate Double
+    #[strum(serialize = "neg.d")]
+    #[instruction(0b010001, R, 0b000111, rs = 0b10001)]
+    NegD,
+    /// Store Doubleword from Floating Point
+    #[instruction(0b111101, I)]
+    Sdc1,
+    /// Floating Point Square Root Single
+    #[strum(serialize = "sqrt.s")]
+    #[instruction(0b010001, R, 0b000100, rs = 0b10000)]
+    SqrtS,
+    /// Floating Point Square Root Double
+    #[strum(serialize = "sqrt.d")]
+    #[instruction(0b010001, R, 0b000100, rs = 0b10001)]
+    SqrtD,
+    /// Floating Point Subtract Single
+    #[strum(serialize = "sub.s")]
+    #[instruction(0b010001, R, 0b000001, rs = 0b10000)]
+    SubS,
+    /// Floating Point Subtract Double
+    #[strum(serialize = "sub.d")]
+    #[instruction(0b010001, R, 0b000001, rs = 0b10001)]
+    SubD,
+    /// Store Word from Floating Point
+    #[instruction(0b111001, I)]
+    Swc1,
+}
+
+/// The kind of operand expected at a position of an instruction, named after the field of the
+/// instruction it's encoded in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperandShape {
+    /// Destination register
+    Rd,
+    /// Source register
+    Rs,
+    /// Target register, either a source or a destination depending on the instruction
+    Rt,
+    /// 16 bit immediate, sign extended
+    Imm16,
+    /// 16 bit immediate, zero extended
+    UImm16,
+    /// 32 bit immediate, only taken by pseudo-instructions which load it in a register
+    Imm32,
+    /// 5 bit shift amount
+    Shamt,
+    /// Code passed to the exception handler, like in `break` or `sdbbp`
+    Code,
+    /// 5 bit hint or operation, like the one of `pref`, `cache` and `sync`
+    Hint,
+    /// 3 bit floating point condition code
+    Cc,
+    /// 3 bit coprocessor register select
+    Sel,
+    /// A memory address like `offset($base)`
+    Address,
+    /// The destination of a pc-relative branch, a label or an absolute address
+    Label,
+    /// The destination of a jump
+    Target,
+    /// Floating point destination register
+    Fd,
+    /// Floating point source register
+    Fs,
+    /// Floating point target register, either a source or a destination
+    Ft,
+}
+
+impl OperandShape {
+    /// Returns true if the operand can be used where this shape is expected
+    pub fn accepts(&self, operand: &OperandKind) -> bool {
+        use OperandShape::*;
+        match self {
+            Rd | Rs | Rt => matches!(operand, OperandKind::Register(reg) if !reg.is_float()),
+            Fd | Fs | Ft => matches!(operand, OperandKind::Register(reg) if reg.is_float()),
+            Imm16 | UImm16 | Imm32 | Shamt | Code | Hint | Cc | Sel => {
+                matches!(operand, OperandKind::Immediate(_))
+            }
+            // a label or a constant alone are also valid addresses
+            Address => match operand {
+                OperandKind::Address(address) => {
+                    !address.base.as_ref().is_some_and(|b| b.is_float())
+                }
+                OperandKind::Label(_) | OperandKind::Immediate(_) => true,
+                _ => false,
+            },
+            Label | Target => {
+                matches!(operand, OperandKind::Label(_) | OperandKind::Immediate(_))
+            }
+        }
+    }
+
+    /// Returns the values a constant can have in this position, the offset in the case of an
+    /// address. Returns `None` if the shape is not a constant or its range is not fixed.
+    pub fn range(&self) -> Option<RangeInclusive<i64>> {
+        use OperandShape::*;
+        match self {
+            Imm16 => Some(i16::MIN as i64..=i16::MAX as i64),
+            // offsets that don't fit in 16 bits are computed in `$at`
+            Imm32 | Address => Some(i32::MIN as i64..=u32::MAX as i64),
+            UImm16 => Some(0..=u16::MAX as i64),
+            Shamt | Hint => Some(0..=31),
+            Code => Some(0..=0xf_ffff),
+            Cc | Sel => Some(0..=7),
+            Rd | Rs | Rt | Fd | Fs | Ft | Label | Target => None,
+        }
+    }
+
+    /// Describes the width and signedness of a constant in this position, used in error notes
+    pub fn width(&self) -> &'static str {
+        use OperandShape::*;
+        match self {
+            Imm16 => "a signed 16 bit immediate",
+            UImm16 => "a zero extended 16 bit immediate",
+            Imm32 => "a 32 bit immediate",
+            Shamt => "a 5 bit shift amount",
+            Code => "a 20 bit code",
+            Hint => "a 5 bit hint",
+            Cc => "a 3 bit condition code",
+            Sel => "a 3 bit select",
+            Address => "a 32 bit address",
+            Rd | Rs | Rt | Fd | Fs | Ft | Label | Target => "a register or a label",
+        }
+    }
+}
+
+impl std::fmt::Display for OperandShape {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use OperandShape::*;
+        let s = match self {
+            Rd => "$rd",
+            Rs => "$rs",
+            Rt => "$rt",
+            Imm16 | UImm16 | Imm32 => "imm",
+            Shamt => "shamt",
+            Code => "code",
+            Hint => "hint",
+            Cc => "cc",
+            Sel => "sel",
+            Address => "offset($base)",
+            Label => "label",
+            Target => "target",
+            Fd => "$fd",
+            Fs => "$fs",
+            Ft => "$ft",
+        };
+        write!(f, "{s}")
+    }
+}
+
+impl InstructionKind {
+    /// Returns all the accepted operand lists of the instruction, the first one is the most common.
+    pub fn signatures(&self) -> &'static [&'static [OperandShape]] {
+        use InstructionKind::*;
+        use OperandShape::*;
+        match self {
+            Add | Addu | Sub | Subu | Slt | Sltu | Mul | And | Nor | Or | Xor | Movn | Movz => {
+                &[&[Rd, Rs, Rt]]
+            }
+            Addi | Addiu | Slti | Sltiu => &[&[Rt, Rs, Imm16]],
+            Andi | Ori | Xori => &[&[Rt, Rs, UImm16]],
+            Lui => &[&[Rt, UImm16]],
+            Clo | Clz => &[&[Rd, Rs]],
+            Div | Divu | Mult | Multu | Madd | Maddu | Msub | Msubu => &[&[Rs, Rt]],
+            Teq | Tge | Tgeu | Tlt | Tltu | Tne => &[&[Rs, Rt]],
+            Teqi | Tgei | Tgeiu | Tlti | Tltiu | Tnei => &[&[Rs, Imm16]],
+
+            B | Bal => &[&[Label]],
+            Beq | Bne => &[&[Rs, Rt, Label]],
+            Bgez | Bgezal | Bgtz | Blez | Bltz | Bltzal => &[&[Rs, Label]],
+            J | Jal => &[&[Target]],
+            Jalr => &[&[Rs], &[Rd, Rs]],
+            Jr => &[&[Rs]],
+
+            Lb | Lbu | Lh | Lhu | Ll | Lw | Lwl | Lwr | Sb | Sc | Sd | Sh | Sw | Swl | Swr => {
+                &[&[Rt, Address]]
+            }
+            Pref | Cache => &[&[Hint, Address]],
+            Sync => &[&[], &[Hint]],
+
+            Mfhi | Mflo => &[&[Rd]],
+            Mthi | Mtlo => &[&[Rs]],
+            Movf | Movt => &[&[Rd, Rs, Cc], &[Rd, Rs]],
+
+            Sll | Sra | Srl => &[&[Rd, Rt, Shamt]],
+            Sllv | Srav | Srlv => &[&[Rd, Rt, Rs]],
+
+            Nop | Ssnop | Syscall | Eret | Deret | Tlbp | Tlbr | Tlbwi | Tlbwr => &[&[]],
+            Break | Wait | Sdbbp => &[&[], &[Code]],
+            Mfc0 | Mtc0 => &[&[Rt, Rd], &[Rt, Rd, Sel]],
+
+            AddS | AddD | SubS | SubD | MulS | MulD | DivS | DivD => &[&[Fd, Fs, Ft]],
+            AbsS | AbsD | NegS | NegD | MovS | MovD | SqrtS | SqrtD => &[&[Fd, Fs]],
+            CvtSD | CvtSW | CvtDS | CvtDW | CvtWS | CvtWD => &[&[Fd, Fs]],
+            CEqS | CEqD | CLtS | CLtD | CLeS | CLeD => &[&[Fs, Ft], &[Cc, Fs, Ft]],
+            Bc1f | Bc1t => &[&[Label], &[Cc, Label]],
+            Lwc1 | Swc1 | Ldc1 | Sdc1 => &[&[Ft, Address]],
+            Mfc1 | Mtc1 => &[&[Rt, Fs]],
+        }
+    }
+
+    /// Returns the operand holding the general purpose register the instruction writes to,
+    /// if it writes to one that is specified explicitly
+    pub fn destination(&self) -> Option<OperandShape> {
+        use InstructionKind::*;
+        match self {
+            Addi | Addiu | Slti | Sltiu | Andi | Ori | Xori | Lui | Lb | Lbu | Lh | Lhu | Ll
+            | Lw | Lwl | Lwr | Sc | Mfc0 | Mfc1 => Some(OperandShape::Rt),
+            // the destination is a coprocessor register
+            Mtc0 => None,
+            _ if self
+                .signatures()
+                .iter()
+                .any(|sig| sig.contains(&OperandShape::Rd)) =>
+            {
+                Some(OperandShape::Rd)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the signature that accepts the operands, if there is one
+    pub fn matching_signature(&self, operands: &[Operand]) -> Option<&'static [OperandShape]> {
+        find_signature(self.signatures(), operands)
+    }
+
+    /// Describes the accepted operands, used in error messages
+    pub fn usage(&self) -> String {
+        describe_signatures(self, self.signatures())
+    }
+}
+
+/// Returns the signature that accepts the operands, if there is one
+pub(crate) fn find_signature(
+    signatures: &'static [&'static [OperandShape]],
+    operands: &[Operand],
+) -> Option<&'static [OperandShape]> {
+    signatures.iter().copied().find(|sig| {
+        sig.len() == operands.len()
+            && sig
+                .iter()
+                .zip(operands)
+                .all(|(shape, o)| shape.accepts(&o.kind))
+    })
+}
+
+/// Describes the operands accepted by the signatures of `name`
+pub(crate) fn describe_signatures(
+    name: &dyn std::fmt::Display,
+    signatures: &[&[OperandShape]],
+) -> String {
+    let forms = signatures
+        .iter()
+        .filter(|sig| !sig.is_empty())
+        .map(|sig| {
+            let operands = sig.iter().map(|o| o.to_string()).collect::<Vec<_>>();
+            format!("`{}`", operands.join(", "))
+        })
+        .collect::<Vec<_>>();
+    match (forms.is_empty(), signatures.iter().any(|s| s.is_empty())) {
+        (true, _) => format!("{name} expects no operands"),
+        (false, true) => format!("{name} expects no operands or {}", forms.join(" or ")),
+        (false, false) => format!("{name} expects {}", forms.join(" or ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use super::*;
+
+    #[test]
+    fn encodings() {
+        use InstructionFormat::*;
+        // mnemonic, opcode, format and funct, from the opcode tables of the MIPS32 manual
+        let table = [
+            ("add", 0x00, R, Some(0x20)),
+            ("addi", 0x08, I, None),
+            ("addiu", 0x09, I, None),
+            ("addu", 0x00, R, Some(0x21)),
+            ("clo", 0x1c, R, Some(0x21)),
+            ("clz", 0x1c, R, Some(0x20)),
+            ("div", 0x00, R, Some(0x1a)),
+            ("divu", 0x00, R, Some(0x1b)),
+            ("madd", 0x1c, R, Some(0x00)),
+            ("maddu", 0x1c, R, Some(0x01)),
+            ("msub", 0x1c, R, Some(0x04)),
+            ("msubu", 0x1c, R, Some(0x05)),
+            ("mul", 0x1c, R, Some(0x02)),
+            ("mult", 0x00, R, Some(0x18)),
+            ("multu", 0x00, R, Some(0x19)),
+            ("slt", 0x00, R, Some(0x2a)),
+            ("slti", 0x0a, I, None),
+            ("sltiu", 0x0b, I, None),
+            ("sltu", 0x00, R, Some(0x2b)),
+            ("sub", 0x00, R, Some(0x22)),
+            ("subu", 0x00, R, Some(0x23)),
+            ("b", 0x04, I, None),
+            ("bal", 0x01, I, None),
+            ("beq", 0x04, I, None),
+            ("bgez", 0x01, I, None),
+            ("bgezal", 0x01, I, None),
+            ("bgtz", 0x07, I, None),
+            ("blez", 0x06, I, None),
+            ("bltz", 0x01, I, None),
+            ("bltzal", 0x01, I, None),
+            ("bne", 0x05, I, None),
+            ("j", 0x02, J, None),
+            ("jal", 0x03, J, None),
+            ("jalr", 0x00, R, Some(0x09)),
+            ("jr", 0x00, R, Some(0x08)),
+            ("nop", 0x00, R, Some(0x00)),
+            ("ssnop", 0x00, R, Some(0x00)),
+            ("lb", 0x20, I, None),
+            ("lbu", 0x24, I, None),
+            ("lh", 0x21, I, None),
+            ("lhu", 0x25, I, None),
+            ("ll", 0x30, I, None),
+            ("lw", 0x23, I, None),
+            ("lwl", 0x22, I, None),
+            ("lwr", 0x26, I, None),
+            ("pref", 0x33, I, None),
+            ("sb", 0x28, I, None),
+            ("sc", 0x38, I, None),
+            ("sd", 0x3f, I, None),
+            ("sh", 0x29, I, None),
+            ("sw", 0x2b, I, None),
+            ("swl", 0x2a, I, None),
+            ("swr", 0x2e, I, None),
+            ("sync", 0x00, R, Some(0x0f)),
+            ("and", 0x00, R, Some(0x24)),
+            ("andi", 0x0c, I, None),
+            ("lui", 0x0f, I, None),
+            ("nor", 0x00, R, Some(0x27)),
+            ("or", 0x00, R, Some(0x25)),
+            ("ori", 0x0d, I, None),
+            ("xor", 0x00, R, Some(0x26)),
+            ("xori", 0x0e, I, None),
+            ("mfhi", 0x00, R, Some(0x10)),
+            ("mflo", 0x00, R, Some(0x12)),
+            ("movf", 0x00, R, Some(0x01)),
+            ("movn", 0x00, R, Some(0x0b)),
+            ("movt", 0x00, R, Some(0x01)),
+            ("movz", 0x00, R, Some(0x0a)),
+            ("mthi", 0x00, R, Some(0x11)),
+            ("mtlo", 0x00, R, Some(0x13)),
+            ("sll", 0x00, R, Some(0x00)),
+            ("sllv", 0x00, R, Some(0x04)),
+            ("sra", 0x00, R, Some(0x03)),
+            ("srav", 0x00, R, Some(0x07)),
+            ("srl", 0x00, R, Some(0x02)),
+            ("srlv", 0x00, R, Some(0x06)),
+            ("break", 0x00, R, Some(0x0d)),
+            ("syscall", 0x00, R, Some(0x0c)),
+            ("teq", 0x00, R, Some(0x34)),
+            ("teqi", 0x01, I, None),
+            ("tge", 0x00, R, Some(0x30)),
+            ("tgei", 0x01, I, None),
+            ("tgeiu", 0x01, I, None),
+            ("tgeu", 0x00, R, Some(0x31)),
+            ("tlt", 0x00, R, Some(0x32)),
+            ("tlti", 0x01, I, None),
+            ("tltiu", 0x01, I, None),
+            ("tltu", 0x00, R, Some(0x33)),
+            ("tne", 0x00, R, Some(0x36)),
+            ("tnei", 0x01, I, None),
+            ("cache", 0x2f, I, None),
+            ("eret", 0x10, R, Some(0x18)),
+            ("mfc0", 0x10, R, Some(0x00)),
+            ("mtc0", 0x10, R, Some(0x00)),
+            ("tlbp", 0x10, R, Some(0x08)),
+            ("tlbr", 0x10, R, Some(0x01)),
+            ("tlbwi", 0x10, R, Some(0x02)),
+            ("tlbwr", 0x10, R, Some(0x06)),
+            ("wait", 0x10, R, Some(0x20)),
+            ("deret", 0x10, R, Some(0x1f)),
+            ("sdbbp", 0x1c, R, Some(0x3f)),
+            ("abs.s", 0x11, R, Some(0x05)),
+            ("abs.d", 0x11, R, Some(0x05)),
+            ("add.s", 0x11, R, Some(0x00)),
+            ("add.d", 0x11, R, Some(0x00)),
+            ("bc1f", 0x11, I, None),
+            ("bc1t", 0x11, I, None),
+            ("c.eq.s", 0x11, R, Some(0x32)),
+            ("c.eq.d", 0x11, R, Some(0x32)),
+            ("c.le.s", 0x11, R, Some(0x3e)),
+            ("c.le.d", 0x11, R, Some(0x3e)),
+            ("c.lt.s", 0x11, R, Some(0x3c)),
+            ("c.lt.d", 0x11, R, Some(0x3c)),
+            ("cvt.s.d", 0x11, R, Some(0x20)),
+            ("cvt.s.w", 0x11, R, Some(0x20)),
+            ("cvt.d.s", 0x11, R, Some(0x21)),
+            ("cvt.d.w", 0x11, R, Some(0x21)),
+            ("cvt.w.s", 0x11, R, Some(0x24)),
+            ("cvt.w.d", 0x11, R, Some(0x24)),
+            ("div.s", 0x11, R, Some(0x03)),
+            ("div.d", 0x11, R, Some(0x03)),
+            ("ldc1", 0x35, I, None),
+            ("lwc1", 0x31, I, None),
+            ("mfc1", 0x11, R, Some(0x00)),
+            ("mov.s", 0x11, R, Some(0x06)),
+            ("mov.d", 0x11, R, Some(0x06)),
+            ("mtc1", 0x11, R, Some(0x00)),
+            ("mul.s", 0x11, R, Some(0x02)),
+            ("mul.d", 0x11, R, Some(0x02)),
+            ("neg.s", 0x11, R, Some(0x07)),
+            ("neg.d", 0x11, R, Some(0x07)),
+            ("sdc1", 0x3d, I, None),
+            ("sqrt.s", 0x11, R, Some(0x04)),
+            ("sqrt.d", 0x11, R, Some(0x04)),
+            ("sub.s", 0x11, R, Some(0x01)),
+            ("sub.d", 0x11, R, Some(0x01)),
+            ("swc1", 0x39, I, None),
+        ];
+        for (mnemonic, opcode, format, funct) in &table {
+            let kind = mnemonic.parse::<InstructionKind>().unwrap();
+            assert_eq!(kind.opcode(), Bits::new(*opcode), "opcode of {mnemonic}");
+            assert_eq!(&kind.format(), format, "format of {mnemonic}");
+            assert_eq!(kind.funct(), funct.map(Bits::new), "funct of {mnemonic}");
+        }
+
+        // the fixed rs, rt and shamt fields of the instructions that have them
+        let selectors = [
+            ("b", Some(0b00000), Some(0b00000), None),
+            ("bal", Some(0b00000), Some(0b10001), None),
+            ("bltz", None, Some(0b00000), None),
+            ("bgez", None, Some(0b00001), None),
+            ("bltzal", None, Some(0b10000), None),
+            ("bgezal", None, Some(0b10001), None),
+            ("tgei", None, Some(0b01000), None),
+            ("tgeiu", None, Some(0b01001), None),
+            ("tlti", None, Some(0b01010), None),
+            ("tltiu", None, Some(0b01011), None),
+            ("teqi", None, Some(0b01100), None),
+            ("tnei", None, Some(0b01110), None),
+            ("ssnop", None, None, Some(0b00001)),
+            ("movf", None, Some(0b00000), None),
+            ("movt", None, Some(0b00001), None),
+            ("mfc0", Some(0b00000), None, None),
+            ("mtc0", Some(0b00100), None, None),
+            ("eret", Some(0b10000), None, None),
+            ("deret", Some(0b10000), None, None),
+            ("wait", Some(0b10000), None, None),
+            ("tlbp", Some(0b10000), None, None),
+            ("tlbr", Some(0b10000), None, None),
+            ("tlbwi", Some(0b10000), None, None),
+            ("tlbwr", Some(0b10000), None, None),
+            ("mfc1", Some(0b00000), None, None),
+            ("mtc1", Some(0b00100), None, None),
+            ("bc1f", Some(0b01000), Some(0b00000), None),
+            ("bc1t", Some(0b01000), Some(0b00001), None),
+            ("cvt.s.d", Some(0b10001), None, None),
+            ("cvt.s.w", Some(0b10100), None, None),
+            ("cvt.d.s", Some(0b10000), None, None),
+            ("cvt.d.w", Some(0b10100), None, None),
+            ("cvt.w.s", Some(0b10000), None, None),
+            ("cvt.w.d", Some(0b10001), None, None),
+        ];
+        for kind in InstructionKind::iter() {
+            let mnemonic = kind.to_string();
+            // the other floating point instructions are selected by their format
+            let fmt = match mnemonic.rsplit_once('.') {
+                Some((_, "s")) => Some(0b10000),
+                Some((_, "d")) => Some(0b10001),
+                _ => None,
+            };
+            let (rs, rt, shamt) = selectors
+                .iter()
+                .find(|(m, ..)| *m == mnemonic)
+                .map_or((fmt, None, None), |(_, rs, rt, shamt)| (*rs, *rt, *shamt));
+            assert_eq!(kind.rs(), rs.map(Bits::new), "rs of {mnemonic}");
+            assert_eq!(kind.rt(), rt.map(Bits::new), "rt of {mnemonic}");
+            assert_eq!(kind.rd(), None, "rd of {mnemonic}");
+            assert_eq!(kind.shamt(), shamt.map(Bits::new), "shamt of {mnemonic}");
+
+            assert!(
+                table.iter().any(|(m, ..)| *m == mnemonic),
+                "{mnemonic} is missing from the table"
+            );
+        }
+    }
 }
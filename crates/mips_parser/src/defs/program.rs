@@ -1,35 +1,424 @@
+use std::ops::Range;
+use strum::{Display, EnumString};
+
+use super::{
+    directive::Directive,
+    expression::{EvalError, EvalErrorKind, Expr},
+    instruction::InstructionKind,
+    pseudo::PseudoKind,
+    register::Register,
+    symbols::SymbolTable,
+};
+
 /// represents a parsed MIPS program
+#[derive(Debug, Default, PartialEq, Eq)]
 pub struct Program {
-    segments: Vec<Segment>,
-    labels: Vec<String>,
-    macros: Vec<String>,
+    pub segments: Vec<Segment>,
+    /// The labels defined in the program, filled in when the program is laid out in memory
+    pub symbols: SymbolTable,
+    pub macros: Vec<String>,
 }
 
-enum StorableData {
-    Double(u32),
-    Float(u32),
-    Int(u32),
-    Word(u32),
+impl Program {
+    /// Returns the segment of the given kind, creating it if it wasn't opened yet.
+    /// Opening the same kind of segment twice continues the previous one, like MARS does.
+    pub(crate) fn segment_mut(&mut self, kind: SegmentKind) -> &mut Segment {
+        let idx = match self.segments.iter().position(|s| s.kind == kind) {
+            Some(idx) => idx,
+            None => {
+                self.segments.push(Segment {
+                    kind,
+                    address: kind.base_address(),
+                    stmts: Vec::new(),
+                    data: Vec::new(),
+                    relocations: Vec::new(),
+                });
+                self.segments.len() - 1
+            }
+        };
+        &mut self.segments[idx]
+    }
+}
+
+/// A value stored in a data segment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorableData {
+    Byte(u8),
     Half(u16),
+    Word(u32),
+    /// The bits of a single precision floating point number
+    Float(u32),
+    /// The bits of a double precision floating point number
+    Double(u64),
+    /// The characters of a string, including the null terminator of `.asciiz`
+    Ascii(Vec<u8>),
+    /// A number of zero bytes, reserved by `.space` or added to align the next value
+    Space(u32),
+    /// A value stored a number of times in a row, like the `0:1000` of `.word 0:1000`
+    Repeat(Box<StorableData>, u32),
+}
+
+impl StorableData {
+    /// Returns the number of bytes the value takes in memory
+    pub fn size(&self) -> u32 {
+        match self {
+            StorableData::Byte(_) => 1,
+            StorableData::Half(_) => 2,
+            StorableData::Word(_) | StorableData::Float(_) => 4,
+            StorableData::Double(_) => 8,
+            StorableData::Ascii(chars) => chars.len() as u32,
+            StorableData::Space(size) => *size,
+            StorableData::Repeat(value, count) => value.size().saturating_mul(*count),
+        }
+    }
+
+    /// Returns the bytes of the value in the order they are stored in memory
+    pub fn to_bytes(&self, endianness: Endianness) -> Vec<u8> {
+        macro_rules! bytes {
+            ($value:expr) => {
+                match endianness {
+                    Endianness::Little => $value.to_le_bytes().to_vec(),
+                    Endianness::Big => $value.to_be_bytes().to_vec(),
+                }
+            };
+        }
+        match self {
+            StorableData::Byte(value) => vec![*value],
+            StorableData::Half(value) => bytes!(value),
+            StorableData::Word(value) | StorableData::Float(value) => bytes!(value),
+            StorableData::Double(value) => bytes!(value),
+            StorableData::Ascii(chars) => chars.clone(),
+            StorableData::Space(size) => vec![0; *size as usize],
+            StorableData::Repeat(value, count) => {
+                value.to_bytes(endianness).repeat(*count as usize)
+            }
+        }
+    }
+}
+
+/// The order of the bytes of a value in memory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Endianness {
+    /// The least significant byte comes first, like in MARS and SPIM
+    #[default]
+    Little,
+    Big,
 }
 
-struct Instruction {
-    //TODO: instrction repr
+/// An instruction with its operands as they appear in the source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub kind: InstructionKind,
+    pub operands: Vec<Operand>,
+    pub span: Range<usize>,
 }
 
-enum Statement {
+/// A pseudo-instruction with its operands as they appear in the source, before it's expanded
+/// into machine instructions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PseudoInstruction {
+    pub kind: PseudoKind,
+    pub operands: Vec<Operand>,
+    pub span: Range<usize>,
+}
+
+/// A directive with its operands as they appear in the source
+#[derive(Debug, PartialEq, Eq)]
+pub struct DirectiveStatement {
+    pub directive: Directive,
+    pub operands: Vec<Operand>,
+    pub span: Range<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operand {
+    pub kind: OperandKind,
+    pub span: Range<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OperandKind {
+    Register(Register),
+    Immediate(Immediate),
+    /// A reference to a label
+    Label(String),
+    String(Vec<u8>),
+    Address(Address),
+    /// A floating point number, stored as the bits of an `f64` so that it can be compared
+    Float(u64),
+    /// A value repeated a number of times in a data directive, like `0:100`
+    Repeat(Box<OperandKind>, u32),
+}
+
+impl OperandKind {
+    /// Returns the labels the operand depends on
+    pub fn labels(&self) -> Vec<&str> {
+        match self {
+            OperandKind::Label(label) => vec![label],
+            OperandKind::Immediate(imm) => imm.labels(),
+            OperandKind::Address(address) => address.offset.labels(),
+            OperandKind::Repeat(kind, _) => kind.labels(),
+            OperandKind::Register(_) | OperandKind::String(_) | OperandKind::Float(_) => Vec::new(),
+        }
+    }
+
+    /// Returns the constant value of an immediate, or the offset of an address, if it doesn't
+    /// depend on the address of a label
+    pub fn number(&self) -> Option<i64> {
+        match self {
+            OperandKind::Immediate(Immediate::Number(num))
+            | OperandKind::Address(Address {
+                offset: Immediate::Number(num),
+                ..
+            }) => Some(*num),
+            _ => None,
+        }
+    }
+}
+
+/// A constant value, which can depend on the address of a label
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Immediate {
+    Number(i64),
+    /// The address of a label plus a constant, like `label+8`
+    Label(String, i64),
+    /// An expression that depends on the address of labels, like `end - start`, that is
+    /// computed once the program is laid out in memory
+    Expr(Expr),
+    /// The upper 16 bits of a value, adjusted so that adding the sign extended lower 16 bits
+    /// gives back the value
+    Hi(Box<Immediate>),
+    /// The lower 16 bits of a value
+    Lo(Box<Immediate>),
+    /// The offset of an address from the global pointer, written `%gp_rel(label)`
+    GpRel(Box<Immediate>),
+}
+
+impl Immediate {
+    /// Returns the labels the value depends on
+    pub fn labels(&self) -> Vec<&str> {
+        match self {
+            Immediate::Number(_) => Vec::new(),
+            Immediate::Label(label, _) => vec![label],
+            Immediate::Expr(expr) => expr.symbols(),
+            Immediate::Hi(imm) | Immediate::Lo(imm) | Immediate::GpRel(imm) => imm.labels(),
+        }
+    }
+
+    /// Computes the value using the addresses in the symbol table. `span` is the span of the
+    /// operand, used in the error when the value depends on a label that is not defined.
+    pub fn resolve(&self, symbols: &SymbolTable, span: &Range<usize>) -> Result<i64, EvalError> {
+        let lookup = |name: &str| symbols.get(name).map(|s| s.address as i64);
+        match self {
+            Immediate::Number(num) => Ok(*num),
+            Immediate::Label(label, offset) => match lookup(label) {
+                Some(address) => Ok(address + offset),
+                None => Err(EvalError {
+                    kind: EvalErrorKind::UndefinedSymbol(label.clone()),
+                    span: span.clone(),
+                }),
+            },
+            Immediate::Expr(expr) => expr.evaluate(&lookup),
+            Immediate::Hi(imm) => Ok(((imm.resolve(symbols, span)? + 0x8000) >> 16) & 0xffff),
+            Immediate::Lo(imm) => Ok(imm.resolve(symbols, span)? as i16 as i64),
+            Immediate::GpRel(imm) => Ok(imm.resolve(symbols, span)? - symbols.gp() as i64),
+        }
+    }
+
+    /// Returns the relocation an object file would need for the value, along with the value it
+    /// applies to, if the value is a relocation operator like `%hi`
+    pub fn relocation(&self) -> Option<(RelocationKind, &Immediate)> {
+        match self {
+            Immediate::Hi(imm) => Some((RelocationKind::Hi16, imm)),
+            Immediate::Lo(imm) => Some((RelocationKind::Lo16, imm)),
+            Immediate::GpRel(imm) => Some((RelocationKind::GpRel16, imm)),
+            Immediate::Number(_) | Immediate::Label(..) | Immediate::Expr(_) => None,
+        }
+    }
+}
+
+/// The operators that select part of an address, written like `%hi(label)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display)]
+pub enum RelocationKind {
+    /// The upper 16 bits, adjusted for the carry of the sign extended lower bits
+    #[strum(serialize = "hi")]
+    Hi16,
+    /// The lower 16 bits
+    #[strum(serialize = "lo")]
+    Lo16,
+    /// The 16 bit offset from the global pointer
+    #[strum(serialize = "gp_rel")]
+    GpRel16,
+}
+
+/// A field of an instruction that depends on the address of a label, which a relocatable
+/// object would leave for the linker to fill in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    pub kind: RelocationKind,
+    /// The address of the instruction
+    pub address: u32,
+    pub symbol: String,
+    /// The constant added to the address of the symbol
+    pub addend: i64,
+}
+
+/// A memory address in one of the forms `offset($base)`, `($base)`, `label`, `label+offset`,
+/// `label($base)` or `label+offset($base)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Address {
+    pub offset: Immediate,
+    pub base: Option<Register>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Statement {
+    /// The definition of a label, which points to the statement that follows it
+    Label {
+        name: String,
+        span: Range<usize>,
+    },
     Instruction(Instruction),
-    Data(StorableData),
+    /// A directive that stores data, like `.word` or `.asciiz`
+    Data(DirectiveStatement),
+    /// Any other directive, like `.globl` or `.eqv`
+    Directive(DirectiveStatement),
+}
+
+impl Statement {
+    /// Returns the alignment of the statement in bytes. Values are aligned to their size
+    /// automatically, like MARS does.
+    pub fn alignment(&self) -> u32 {
+        match self {
+            Statement::Instruction(_) => 4,
+            Statement::Data(stmt) => match stmt.directive {
+                Directive::Half => 2,
+                Directive::Word | Directive::Float => 4,
+                Directive::Double => 8,
+                _ => 1,
+            },
+            Statement::Label { .. } | Statement::Directive(_) => 1,
+        }
+    }
+
+    /// Returns the number of bytes the statement takes in memory when placed at `address`,
+    /// which must be aligned. Instructions are expanded while parsing, so each one is a single
+    /// word. Returns `None` if the size doesn't fit in 32 bits.
+    pub fn size(&self, address: u32) -> Option<u32> {
+        match self {
+            Statement::Instruction(_) => Some(4),
+            Statement::Data(stmt) => stmt.size(address),
+            Statement::Label { .. } | Statement::Directive(_) => Some(0),
+        }
+    }
+
+    pub fn span(&self) -> &Range<usize> {
+        match self {
+            Statement::Label { span, .. } => span,
+            Statement::Instruction(instruction) => &instruction.span,
+            Statement::Data(stmt) | Statement::Directive(stmt) => &stmt.span,
+        }
+    }
 }
 
-enum SegmentKind {
+impl DirectiveStatement {
+    /// Returns the number of bytes stored or reserved by a data directive placed at `address`,
+    /// or `None` if it doesn't fit in 32 bits. The operands of `.space` and `.align` are
+    /// checked by the parser.
+    pub fn size(&self, address: u32) -> Option<u32> {
+        let count = self.operands.iter().try_fold(0u32, |sum, o| match o.kind {
+            OperandKind::Repeat(_, count) => sum.checked_add(count),
+            _ => sum.checked_add(1),
+        });
+        let number = || match self.operands.first().map(|o| &o.kind) {
+            Some(OperandKind::Immediate(Immediate::Number(num))) => (*num).max(0) as u32,
+            _ => 0,
+        };
+        let string_len = || {
+            self.operands
+                .iter()
+                .map(|o| match &o.kind {
+                    OperandKind::String(string) => string.len() as u32,
+                    _ => 0,
+                })
+                .sum::<u32>()
+        };
+        match self.directive {
+            Directive::Byte => count,
+            Directive::Half => count?.checked_mul(2),
+            Directive::Word | Directive::Float => count?.checked_mul(4),
+            Directive::Double => count?.checked_mul(8),
+            Directive::Ascii => Some(string_len()),
+            Directive::Asciiz => Some(string_len() + self.operands.len() as u32),
+            Directive::Space => Some(number()),
+            Directive::Align => {
+                let alignment = 1u32 << number().min(31);
+                Some(address.checked_next_multiple_of(alignment)? - address)
+            }
+            _ => Some(0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum SegmentKind {
     Data,
     Text,
     Kdata,
     Ktext,
     Macro,
 }
-struct Segment {
-    kind: SegmentKind,
-    stmts: Vec<Statement>,
+
+impl SegmentKind {
+    /// Returns true if the segment stores data rather than instructions
+    pub fn is_data(&self) -> bool {
+        matches!(self, SegmentKind::Data | SegmentKind::Kdata)
+    }
+
+    /// Returns the address where the address space of the segment ends, the start of the
+    /// next region in the memory map of MARS
+    pub fn end_address(&self) -> u32 {
+        match self {
+            SegmentKind::Text | SegmentKind::Macro => 0x1000_0000,
+            SegmentKind::Data => 0x8000_0000,
+            SegmentKind::Ktext => 0x9000_0000,
+            // the memory mapped devices start here
+            SegmentKind::Kdata => 0xffff_0000,
+        }
+    }
+
+    /// Returns the address the segment starts at by default, the same as in MARS
+    pub fn base_address(&self) -> u32 {
+        match self {
+            SegmentKind::Text | SegmentKind::Macro => 0x0040_0000,
+            SegmentKind::Data => 0x1001_0000,
+            SegmentKind::Ktext => 0x8000_0180,
+            SegmentKind::Kdata => 0x9000_0000,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Segment {
+    pub kind: SegmentKind,
+    /// The address of the first statement of the segment
+    pub address: u32,
+    pub stmts: Vec<Statement>,
+    /// The values stored in the segment starting from its address, filled in when the program
+    /// is laid out in memory. A text segment holds the machine word of each instruction.
+    pub data: Vec<StorableData>,
+    /// The relocations of the instructions of the segment, filled in when the program is laid
+    /// out in memory. The values are resolved anyway, an object writer can emit these instead.
+    pub relocations: Vec<Relocation>,
+}
+
+impl Segment {
+    /// Returns the contents of the segment as they are stored in memory
+    pub fn data_bytes(&self, endianness: Endianness) -> Vec<u8> {
+        self.data
+            .iter()
+            .flat_map(|d| d.to_bytes(endianness))
+            .collect()
+    }
 }
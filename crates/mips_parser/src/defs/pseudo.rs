@@ -0,0 +1,154 @@
+use strum::{Display, EnumIter, EnumString};
+
+use super::instruction::{describe_signatures, find_signature, OperandShape};
+use super::program::Operand;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, EnumIter, Display)]
+#[strum(serialize_all = "lowercase")]
+/// The pseudo-instructions, which are expanded into one or more machine instructions. The ones
+/// that need a temporary register use `$at`.
+///
+/// Not every dialect has all of them, see [`Dialect::supports_pseudo`](super::dialect::Dialect::supports_pseudo).
+///
+/// `mul`, `div` and `divu` are also machine instructions: the pseudo-instruction is the form
+/// with operands the machine instruction doesn't accept, like `div $t0, $t1, $t2`.
+pub enum PseudoKind {
+    /***** LOADS AND MOVES *****/
+    /// Load Immediate
+    Li,
+    /// Load Address
+    La,
+    /// Move
+    Move,
+
+    /***** BRANCHES *****/
+    /// Branch on Less Than
+    Blt,
+    /// Branch on Greater Than
+    Bgt,
+    /// Branch on Less or Equal
+    Ble,
+    /// Branch on Greater or Equal
+    Bge,
+    /// Branch on Less Than Unsigned
+    Bltu,
+    /// Branch on Greater Than Unsigned
+    Bgtu,
+    /// Branch on Less or Equal Unsigned
+    Bleu,
+    /// Branch on Greater or Equal Unsigned
+    Bgeu,
+    /// Branch on Equal to Zero
+    Beqz,
+    /// Branch on Not Equal to Zero
+    Bnez,
+
+    /***** ARITHMETIC AND LOGIC *****/
+    /// Bitwise Not
+    Not,
+    /// Negate
+    Neg,
+    /// Absolute Value
+    Abs,
+    /// Subtract Immediate
+    Subi,
+    /// Subtract Immediate Unsigned, without the overflow trap
+    Subiu,
+    /// Multiply by an immediate
+    Mul,
+    /// Divide, the quotient is written to the destination
+    Div,
+    /// Divide Unsigned, the quotient is written to the destination
+    Divu,
+    /// Remainder
+    Rem,
+    /// Remainder Unsigned
+    Remu,
+
+    /***** COMPARISONS *****/
+    /// Set on Equal
+    Seq,
+    /// Set on Not Equal
+    Sne,
+    /// Set on Greater or Equal
+    Sge,
+    /// Set on Greater or Equal Unsigned
+    Sgeu,
+    /// Set on Greater Than
+    Sgt,
+    /// Set on Greater Than Unsigned
+    Sgtu,
+
+    /***** UNALIGNED MEMORY ACCESS *****/
+    /// Unaligned Load Word
+    Ulw,
+    /// Unaligned Store Word
+    Usw,
+}
+
+impl PseudoKind {
+    /// Returns all the accepted operand lists of the pseudo-instruction, the first one is the
+    /// most common.
+    pub fn signatures(&self) -> &'static [&'static [OperandShape]] {
+        use OperandShape::*;
+        use PseudoKind::*;
+        match self {
+            Li => &[&[Rt, Imm32]],
+            La | Ulw | Usw => &[&[Rt, Address]],
+            Move | Not | Neg | Abs => &[&[Rd, Rs]],
+            Subi | Subiu => &[&[Rt, Rs, Imm32]],
+            Blt | Bgt | Ble | Bge | Bltu | Bgtu | Bleu | Bgeu => {
+                &[&[Rs, Rt, Label], &[Rs, Imm32, Label]]
+            }
+            Beqz | Bnez => &[&[Rs, Label]],
+            // `mul $rd, $rs, $rt` is a machine instruction
+            Mul => &[&[Rd, Rs, Imm32]],
+            Div | Divu | Rem | Remu | Seq | Sne | Sge | Sgeu | Sgt | Sgtu => {
+                &[&[Rd, Rs, Rt], &[Rd, Rs, Imm32]]
+            }
+        }
+    }
+
+    /// Returns the operand holding the register the pseudo-instruction writes to, if any
+    pub fn destination(&self) -> Option<OperandShape> {
+        use PseudoKind::*;
+        match self {
+            Li | La | Ulw | Subi | Subiu => Some(OperandShape::Rt),
+            Blt | Bgt | Ble | Bge | Bltu | Bgtu | Bleu | Bgeu | Beqz | Bnez | Usw => None,
+            _ => Some(OperandShape::Rd),
+        }
+    }
+
+    /// Returns the signature that accepts the operands, if there is one
+    pub fn matching_signature(&self, operands: &[Operand]) -> Option<&'static [OperandShape]> {
+        find_signature(self.signatures(), operands)
+    }
+
+    /// Describes the accepted operands, used in error messages
+    pub fn usage(&self) -> String {
+        describe_signatures(self, self.signatures())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use super::*;
+    use crate::defs::instruction::InstructionKind;
+
+    #[test]
+    fn shared_mnemonics() {
+        // the mnemonics of both tables only differ in their operands
+        let shared: Vec<_> = PseudoKind::iter()
+            .filter(|kind| kind.to_string().parse::<InstructionKind>().is_ok())
+            .collect();
+        assert_eq!(shared, [PseudoKind::Mul, PseudoKind::Div, PseudoKind::Divu]);
+        for kind in shared {
+            let real: InstructionKind = kind.to_string().parse().unwrap();
+            for sig in kind.signatures() {
+                assert!(!real.signatures().contains(sig), "{kind} {sig:?}");
+            }
+        }
+    }
+}
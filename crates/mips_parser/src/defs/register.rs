@@ -1,11 +1,11 @@
 use std::str::FromStr;
-use strum::EnumString;
+use strum::{Display, EnumString};
 use thiserror::Error;
 
 use crate::errors::AriadneError;
 
 const REG_MUST_BE: &str =
-    "$0-$31 or $a0-$a3,$t0-$t9,$s0-$s7,$k0-$k1,$v0-$v1 or $ra,$at,$gp,$sp,$fp";
+    "$0-$31 or $a0-$a3,$t0-$t9,$s0-$s7,$k0-$k1,$v0-$v1 or $zero,$ra,$at,$gp,$sp,$fp or $f0-$f31 for floating point";
 
 #[derive(Debug, Error, Eq, PartialEq)]
 pub enum RegisterParseError {
@@ -35,31 +35,85 @@ impl AriadneError for RegisterParseError {
     fn note(&self) -> Option<String> {
         match self {
             RegisterParseError::InvalidPrefix(_) => {
-                Some(format!("Prefix must be one of 'v','a','t','s','k' so that the register name is one of {REG_MUST_BE}."))
+                Some(format!("Prefix must be one of 'v','a','t','s','k','f' so that the register name is one of {REG_MUST_BE}."))
             }
             _ => Some(format!("Register must be one of {REG_MUST_BE}.")),
         }
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 /// Identifies a valid register in the CPU
-pub(crate) enum Register {
+pub enum Register {
     /// Register Identified directly by number
     Number(u8),
     /// Register Identified by letter+number like `$s1` or `$t1`
     PrefixedNumber(RegisterPrefixedName),
     /// Registers Identified by name like as `$ra`
     Name(RegisterName),
+    /// Floating point register of coprocessor 1, like `$f12`
+    Float(u8),
+}
+
+impl Register {
+    /// Returns the number of the register, between 0 and 31
+    pub fn number(&self) -> u8 {
+        match self {
+            Register::Number(num) | Register::Float(num) => *num,
+            Register::PrefixedNumber(RegisterPrefixedName { prefix, index }) => match prefix {
+                'v' => 2 + index,
+                'a' => 4 + index,
+                't' if *index < 8 => 8 + index,
+                't' => 24 + index - 8,
+                's' => 16 + index,
+                'k' => 26 + index,
+                _ => unreachable!("Register prefixes are checked when parsing"),
+            },
+            Register::Name(name) => match name {
+                RegisterName::Zero => 0,
+                RegisterName::At => 1,
+                RegisterName::Gp => 28,
+                RegisterName::Sp => 29,
+                RegisterName::Fp => 30,
+                RegisterName::Ra => 31,
+            },
+        }
+    }
+
+    /// Returns true for the registers of the floating point unit
+    pub fn is_float(&self) -> bool {
+        matches!(self, Register::Float(_))
+    }
+}
+
+impl std::fmt::Display for Register {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Register::Number(num) => write!(f, "${num}"),
+            Register::PrefixedNumber(RegisterPrefixedName { prefix, index }) => {
+                write!(f, "${prefix}{index}")
+            }
+            Register::Name(name) => write!(f, "${name}"),
+            Register::Float(num) => write!(f, "$f{num}"),
+        }
+    }
 }
 
 impl TryFrom<&[char]> for Register {
     type Error = RegisterParseError;
 
     fn try_from(value: &[char]) -> Result<Self, Self::Error> {
-        let reg_string = String::from_iter(value);
+        Register::try_from(String::from_iter(value).as_str())
+    }
+}
+
+impl TryFrom<&str> for Register {
+    type Error = RegisterParseError;
+
+    /// Parses the name of a register without the `$`
+    fn try_from(reg_string: &str) -> Result<Self, Self::Error> {
         // try to parse the register from name
-        if let Ok(name) = RegisterName::from_str(&reg_string) {
+        if let Ok(name) = RegisterName::from_str(reg_string) {
             return Ok(Register::Name(name));
         }
         // try to parse the register as a number $0-$31
@@ -69,9 +123,19 @@ impl TryFrom<&[char]> for Register {
             }
             return Ok(Register::Number(num));
         }
-        if value.len() == 2 {
+        // try to parse the register as a floating point one $f0-$f31
+        if let Some(index) = reg_string.strip_prefix('f') {
+            if let Ok(num) = index.parse::<u8>() {
+                if num >= 32 {
+                    return Err(RegisterParseError::OutOfRange(num));
+                }
+                return Ok(Register::Float(num));
+            }
+        }
+        let mut chars = reg_string.chars();
+        if let (Some(prefix), Some(index), None) = (chars.next(), chars.next(), chars.next()) {
             // try to parse the register as a prefixed alias like $v0,$s3...
-            return match RegisterPrefixedName::try_from(value) {
+            return match RegisterPrefixedName::try_from([prefix, index].as_slice()) {
                 Ok(reg) => Ok(Register::PrefixedNumber(reg)),
                 Err(err) => Err(err),
             };
@@ -80,8 +144,8 @@ impl TryFrom<&[char]> for Register {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub(crate) struct RegisterPrefixedName {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegisterPrefixedName {
     /// The prefix of the register alias, can be one of 'v','a','t','s','k'
     prefix: char,
     /// The number after the prefix
@@ -134,10 +198,11 @@ impl TryFrom<&[char]> for RegisterPrefixedName {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, EnumString)]
+#[derive(Debug, Clone, PartialEq, Eq, EnumString, Display)]
 #[strum(serialize_all = "lowercase")]
 /// register name
-pub(crate) enum RegisterName {
+pub enum RegisterName {
+    Zero,
     At,
     Gp,
     Sp,
@@ -181,6 +246,7 @@ mod tests {
     #[test]
     fn name() {
         let valids = [
+            ("zero", RegisterName::Zero),
             ("at", RegisterName::At),
             ("gp", RegisterName::Gp),
             ("sp", RegisterName::Sp),
@@ -210,6 +276,8 @@ mod tests {
             ("31", Register::Number(31)),
             ("at", Register::Name(RegisterName::At)),
             ("sp", Register::Name(RegisterName::Sp)),
+            ("f0", Register::Float(0)),
+            ("f31", Register::Float(31)),
         ];
         for (s, res) in valids {
             assert_eq!(
@@ -217,9 +285,31 @@ mod tests {
                 Ok(res)
             );
         }
-        let errs = ["s9", "sd", "Ra", "t12", "32", "-1"];
+        let errs = ["s9", "sd", "Ra", "t12", "32", "-1", "f32", "fa"];
         for s in errs {
             assert!(Register::try_from(s.chars().collect::<Vec<_>>().as_slice()).is_err());
         }
     }
+    #[test]
+    fn numbers() {
+        let regs = [
+            ("zero", 0),
+            ("at", 1),
+            ("v1", 3),
+            ("a0", 4),
+            ("t7", 15),
+            ("s0", 16),
+            ("t8", 24),
+            ("t9", 25),
+            ("k1", 27),
+            ("ra", 31),
+            ("12", 12),
+            ("f12", 12),
+        ];
+        for (s, num) in regs {
+            let reg = Register::try_from(s.chars().collect::<Vec<_>>().as_slice()).unwrap();
+            assert_eq!(reg.number(), num);
+            assert_eq!(reg.to_string(), format!("${s}"));
+        }
+    }
 }
@@ -0,0 +1,147 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+
+use super::program::SegmentKind;
+
+/// A label defined in the program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    /// The segment the label was defined in
+    pub segment: SegmentKind,
+    /// The address of the statement the label points to
+    pub address: u32,
+    /// The span of the label definition
+    pub span: Range<usize>,
+    /// Whether the label was declared with `.globl`
+    pub global: bool,
+}
+
+/// Maps every label of a program to its definition
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SymbolTable {
+    symbols: BTreeMap<String, Symbol>,
+    /// The value of `$gp` that `%gp_rel` is relative to
+    gp: u32,
+}
+
+impl SymbolTable {
+    /// Returns the value of the global pointer `$gp` the program was laid out for
+    pub fn gp(&self) -> u32 {
+        self.gp
+    }
+
+    pub(crate) fn set_gp(&mut self, gp: u32) {
+        self.gp = gp;
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.get(name)
+    }
+
+    pub(crate) fn get_mut(&mut self, name: &str) -> Option<&mut Symbol> {
+        self.symbols.get_mut(name)
+    }
+
+    /// Adds a symbol to the table. If a symbol with the same name already exists it's left
+    /// unchanged and returned as error.
+    pub(crate) fn insert(&mut self, name: String, symbol: Symbol) -> Result<(), &Symbol> {
+        match self.symbols.entry(name) {
+            std::collections::btree_map::Entry::Occupied(entry) => Err(entry.into_mut()),
+            std::collections::btree_map::Entry::Vacant(entry) => {
+                entry.insert(symbol);
+                Ok(())
+            }
+        }
+    }
+
+    /// Iterates over the symbols in alphabetical order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Symbol)> {
+        self.symbols
+            .iter()
+            .map(|(name, symbol)| (name.as_str(), symbol))
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+}
+
+/// Names the numeric local labels of GNU `as`, like `1:`, which can be defined many times.
+///
+/// Each definition gets its own name in the symbol table, made of the number and how many
+/// definitions of it came before, like `1:0`, `1:1`. A name can't start with a digit, so it
+/// never clashes with a label of the program. `1b` then refers to the last definition of `1`
+/// and `1f` to the next one.
+#[derive(Debug, Default)]
+pub(crate) struct LocalLabels {
+    /// The number of definitions of each label so far
+    defined: HashMap<u32, usize>,
+    /// The forward references, with the definition they point to and their span
+    forward_refs: Vec<(u32, usize, Range<usize>)>,
+}
+
+impl LocalLabels {
+    /// Adds a definition of the label, returning its name
+    pub fn define(&mut self, number: u32) -> String {
+        let count = self.defined.entry(number).or_default();
+        *count += 1;
+        local_name(number, *count - 1)
+    }
+
+    /// Returns the name of the last definition of the label, if there is one
+    pub fn backward(&self, number: u32) -> Option<String> {
+        self.count(number)
+            .checked_sub(1)
+            .map(|index| local_name(number, index))
+    }
+
+    /// Returns the name the next definition of the label will have. The reference is
+    /// remembered, to check that the definition exists with [`LocalLabels::unresolved`].
+    pub fn forward(&mut self, number: u32, span: Range<usize>) -> String {
+        let index = self.count(number);
+        self.forward_refs.push((number, index, span));
+        local_name(number, index)
+    }
+
+    /// Returns the number and the span of the forward references with no definition after them
+    pub fn unresolved(&self) -> impl Iterator<Item = (u32, Range<usize>)> + '_ {
+        self.forward_refs
+            .iter()
+            .filter(|(number, index, _)| *index >= self.count(*number))
+            .map(|(number, _, span)| (*number, span.clone()))
+    }
+
+    fn count(&self, number: u32) -> usize {
+        self.defined.get(&number).copied().unwrap_or(0)
+    }
+}
+
+fn local_name(number: u32, index: usize) -> String {
+    format!("{number}:{index}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_labels() {
+        let mut labels = LocalLabels::default();
+        assert_eq!(labels.backward(1), None);
+        let next = labels.forward(1, 0..2);
+        assert_eq!(labels.unresolved().collect::<Vec<_>>(), [(1, 0..2)]);
+        assert_eq!(labels.define(1), next);
+        assert_eq!(labels.unresolved().count(), 0);
+        assert_eq!(labels.backward(1), Some(next.clone()));
+        labels.forward(1, 4..6);
+        assert_eq!(labels.define(2), "2:0");
+        assert_eq!(labels.define(1), "1:1");
+        assert_eq!(labels.unresolved().count(), 0);
+        labels.forward(2, 8..10);
+        assert_eq!(labels.unresolved().collect::<Vec<_>>(), [(2, 8..10)]);
+    }
+}
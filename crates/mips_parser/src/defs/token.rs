@@ -1,41 +1,77 @@
+use std::borrow::Cow;
 use std::ops::Range;
 
-use super::{directive::Directive, instruction::InstructionKind, register::Register};
+use super::{
+    directive::Directive, instruction::InstructionKind, program::RelocationKind,
+    pseudo::PseudoKind, register::Register,
+};
 
-#[derive(Debug, PartialEq, Eq)]
-pub(crate) struct Token {
-    pub kind: TokenKind,
+/// A token that borrows its text from the input it was read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub kind: TokenKind<'a>,
     pub span: Range<usize>,
 }
 
-impl Token {
-    pub(crate) fn new(kind: TokenKind, span: Range<usize>) -> Self {
+impl<'a> Token<'a> {
+    pub(crate) fn new(kind: TokenKind<'a>, span: Range<usize>) -> Self {
         Self { kind, span }
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub(crate) enum TokenKind {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenKind<'a> {
     Eof,
     Newline,
+    /// Stands for the text the lexer couldn't read, the error itself is reported by the lexer
+    Error,
     Whitespace,
+    /// A `#` comment, or a `/* */` one in the dialects that have them. Only read when the lexer
+    /// keeps the trivia, otherwise comments are skipped.
+    Comment,
 
     // parenthesis
     LParen, // (
     RParen, // )
 
     // punctuation and operators
-    SingleQuote, // \'
-    Plus,        // +
-    Minus,       // -
-    Comma,       // ,
-    Dot,         // .
-    Colon,       // :
+    Plus,      // +
+    Minus,     // -
+    Comma,     // ,
+    Dot,       // .
+    Colon,     // :
+    Star,      // *
+    Slash,     // /
+    Percent,   // %
+    Shl,       // <<
+    Shr,       // >>
+    Ampersand, // &
+    Pipe,      // |
+    Caret,     // ^
+    Tilde,     // ~
+    /// A relocation operator like `%hi`, always followed by a parenthesis
+    Relocation(RelocationKind),
 
     Register(Register),
     Instruction(InstructionKind),
+    /// A pseudo-instruction whose mnemonic is not also a machine instruction, like `li`
+    Pseudo(PseudoKind),
     Directive(Directive),
-    Ident(String),
-    String(String),
-    Number(i16),
+    Ident(&'a str),
+    /// The bytes of a string literal, with the escape sequences already replaced. They are
+    /// only copied if the string contains escape sequences.
+    String(Cow<'a, [u8]>),
+    /// A character literal like `'a'`, which is just another way to write a number
+    Char(u8),
+    Number(i64),
+    /// The definition of a numeric local label, like the `1` of `1:`
+    LocalLabel(u32),
+    /// A reference to the nearest numeric local label, like `1b` for the previous definition
+    /// of `1:` or `1f` for the next one
+    LocalRef {
+        number: u32,
+        forward: bool,
+    },
+    /// The bits of an `f64`, which can be compared unlike the number itself
+    Float(u64),
 }
@@ -0,0 +1,425 @@
+//! Turns machine words back into assembly text, to read memory dumps and the programs of
+//! other assemblers.
+
+use std::collections::HashMap;
+
+use strum::{Display, EnumString, IntoEnumIterator};
+
+use crate::defs::instruction::{
+    Fields, InstructionEncoding, InstructionFormat, InstructionKind, MachineInstruction,
+    OperandShape,
+};
+use crate::defs::symbols::SymbolTable;
+use crate::defs::Bits;
+
+/// The names of the general purpose registers, by number
+const ABI_NAMES: [&str; 32] = [
+    "zero", "at", "v0", "v1", "a0", "a1", "a2", "a3", "t0", "t1", "t2", "t3", "t4", "t5", "t6",
+    "t7", "s0", "s1", "s2", "s3", "s4", "s5", "s6", "s7", "t8", "t9", "k0", "k1", "gp", "sp", "fp",
+    "ra",
+];
+
+/// How the general purpose registers are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, EnumString, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum RegisterNames {
+    /// By the name of their use, like `$t0`
+    #[default]
+    Abi,
+    /// By number, like `$8`
+    Numeric,
+}
+
+/// Returns the instruction a machine word encodes, or `None` if it's not one of the instructions
+/// of [`InstructionKind`].
+///
+/// When more than one instruction matches, the one with the most fixed bits is chosen, so that
+/// `0x00000000` is `nop` rather than `sll $zero, $zero, 0` and `beq $zero, $zero` is `b`.
+pub fn decode(word: u32) -> Option<MachineInstruction> {
+    let kind = InstructionKind::iter()
+        .filter(|kind| {
+            let (mask, fixed) = fixed_bits(*kind);
+            word & mask == fixed
+        })
+        .max_by_key(|kind| fixed_bits(*kind).0.count_ones())?;
+    // the rt field of `clo` and `clz` must repeat rd, the others can't be written in assembly
+    if matches!(kind, InstructionKind::Clo | InstructionKind::Clz)
+        && (word >> 16) & 0x1f != (word >> 11) & 0x1f
+    {
+        return None;
+    }
+    let field = |shift: u32| Bits::new((word >> shift) & 0x1f);
+    let fields = match kind.format() {
+        InstructionFormat::R => Fields::R {
+            rs: field(21),
+            rt: field(16),
+            rd: field(11),
+            shamt: field(6),
+            funct: Bits::new(word & 0x3f),
+        },
+        InstructionFormat::I => Fields::I {
+            rs: field(21),
+            rt: field(16),
+            imm: Bits::new(word & 0xffff),
+        },
+        InstructionFormat::J => Fields::J {
+            target: Bits::new(word & 0x03ff_ffff),
+        },
+    };
+    Some(MachineInstruction { kind, fields })
+}
+
+/// Returns the mask of the bits that identify the instruction, and their value. The other bits
+/// hold its operands.
+fn fixed_bits(kind: InstructionKind) -> (u32, u32) {
+    let operands = kind
+        .signatures()
+        .iter()
+        .flat_map(|sig| sig.iter())
+        .fold(0, |mask, shape| mask | operand_mask(kind, *shape));
+    // `clo` and `clz` repeat their destination in the rt field
+    let operands = match kind {
+        InstructionKind::Clo | InstructionKind::Clz => operands | 0x1f << 16,
+        _ => operands,
+    };
+    let selector = |field: Option<Bits<5>>, shift: u32| field.map_or(0, |bits| bits.get() << shift);
+    let fixed = kind.opcode().get() << 26
+        | selector(kind.rs(), 21)
+        | selector(kind.rt(), 16)
+        | selector(kind.rd(), 11)
+        | selector(kind.shamt(), 6)
+        | kind.funct().map_or(0, |funct| funct.get());
+    (!operands, fixed & !operands)
+}
+
+/// Returns the bits of the word where an operand of the instruction is encoded
+fn operand_mask(kind: InstructionKind, shape: OperandShape) -> u32 {
+    use InstructionKind::*;
+    use OperandShape::*;
+    match shape {
+        Rd | Fs => 0x1f << 11,
+        Rs => 0x1f << 21,
+        Rt | Ft => 0x1f << 16,
+        Fd | Shamt => 0x1f << 6,
+        Imm16 | UImm16 | Label => 0xffff,
+        Address => 0x1f << 21 | 0xffff,
+        Hint if kind == Sync => 0x1f << 6,
+        Hint => 0x1f << 16,
+        Code if kind == Wait => 0x7_ffff << 6,
+        Code => 0xf_ffff << 6,
+        Cc if matches!(kind, CEqS | CEqD | CLeS | CLeD | CLtS | CLtD) => 0x7 << 8,
+        Cc => 0x7 << 18,
+        Sel => 0x7,
+        Target => 0x03ff_ffff,
+        Imm32 => unreachable!("Only pseudo-instructions take 32 bit immediates"),
+    }
+}
+
+/// Writes instructions in the syntax the assembler reads
+#[derive(Debug, Default)]
+pub struct Disassembler<'a> {
+    registers: RegisterNames,
+    /// The label to print for each address
+    labels: HashMap<u32, &'a str>,
+}
+
+impl<'a> Disassembler<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how the general purpose registers are written, by their ABI name by default
+    pub fn with_register_names(mut self, registers: RegisterNames) -> Self {
+        self.registers = registers;
+        self
+    }
+
+    /// Writes the targets of branches and jumps as the labels of the table that point to them,
+    /// rather than as addresses
+    pub fn with_symbols(mut self, symbols: &'a SymbolTable) -> Self {
+        for (name, symbol) in symbols.iter() {
+            // the names of local labels like `1:0` can't be written back
+            if !name.contains(':') {
+                self.labels.entry(symbol.address).or_insert(name);
+            }
+        }
+        self
+    }
+
+    /// Returns the label that points to the address, if there is one
+    pub fn label(&self, address: u32) -> Option<&'a str> {
+        self.labels.get(&address).copied()
+    }
+
+    /// Returns the text of the instruction encoded by `word` placed at `address`, or `None` if
+    /// the word is not an instruction
+    pub fn disassemble(&self, word: u32, address: u32) -> Option<String> {
+        decode(word).map(|instruction| self.format(&instruction, address))
+    }
+
+    /// Returns the text of the instruction placed at `address`. Of the accepted operand lists,
+    /// the first one that can express the instruction is used, so the operands that have their
+    /// default value are left out, like the `$ra` of `jalr $t9`.
+    pub fn format(&self, instruction: &MachineInstruction, address: u32) -> String {
+        use InstructionKind::*;
+        use OperandShape::*;
+
+        let kind = instruction.kind;
+        let word = instruction.encode();
+        let field = |shift: u32| (word >> shift) & 0x1f;
+        let (rs, rt, rd, shamt) = (field(21), field(16), field(11), field(6));
+        let imm = word & 0xffff;
+        let is_compare = matches!(kind, CEqS | CEqD | CLeS | CLeD | CLtS | CLtD);
+        // the value of every operand, and whether it can be left out
+        let operand = |shape: OperandShape| -> (String, bool) {
+            match shape {
+                // the registers of coprocessor 0 have no names
+                Rd if matches!(kind, Mfc0 | Mtc0) => (format!("${rd}"), false),
+                Rd if kind == Jalr => (self.register(rd), rd == 31),
+                Rd => (self.register(rd), false),
+                Rs => (self.register(rs), false),
+                Rt => (self.register(rt), false),
+                Fd => (format!("$f{shamt}"), false),
+                Fs => (format!("$f{rd}"), false),
+                Ft => (format!("$f{rt}"), false),
+                Imm16 => ((imm as i16).to_string(), false),
+                UImm16 => (format!("{imm:#x}"), false),
+                Imm32 => unreachable!("Only pseudo-instructions take 32 bit immediates"),
+                Shamt => (shamt.to_string(), false),
+                Hint if kind == Sync => (shamt.to_string(), shamt == 0),
+                Hint => (rt.to_string(), false),
+                Code => {
+                    let mask = if kind == Wait { 0x7_ffff } else { 0xf_ffff };
+                    let code = (word >> 6) & mask;
+                    (code.to_string(), code == 0)
+                }
+                Cc => {
+                    let cc = if is_compare { shamt >> 2 } else { rt >> 2 };
+                    (cc.to_string(), cc == 0)
+                }
+                Sel => ((word & 0x7).to_string(), word & 0x7 == 0),
+                Address => (format!("{}({})", imm as i16, self.register(rs)), false),
+                Label => {
+                    let offset = (imm as i16 as i32) << 2;
+                    let target = address.wrapping_add(4).wrapping_add(offset as u32);
+                    (self.target(target), false)
+                }
+                Target => {
+                    let region = address.wrapping_add(4) & 0xf000_0000;
+                    (self.target(region | (word & 0x03ff_ffff) << 2), false)
+                }
+            }
+        };
+        let full = kind
+            .signatures()
+            .iter()
+            .max_by_key(|sig| sig.len())
+            .expect("Every instruction has a signature");
+        let signature = kind
+            .signatures()
+            .iter()
+            .find(|sig| {
+                full.iter()
+                    .filter(|shape| !sig.contains(shape))
+                    .all(|shape| operand(*shape).1)
+            })
+            .unwrap_or(full);
+        let operands = signature
+            .iter()
+            .map(|shape| operand(*shape).0)
+            .collect::<Vec<_>>();
+        if operands.is_empty() {
+            kind.to_string()
+        } else {
+            format!("{kind} {}", operands.join(", "))
+        }
+    }
+
+    fn register(&self, number: u32) -> String {
+        match self.registers {
+            RegisterNames::Abi => format!("${}", ABI_NAMES[number as usize]),
+            RegisterNames::Numeric => format!("${number}"),
+        }
+    }
+
+    /// Returns the label of the target of a branch or jump, or its address
+    fn target(&self, address: u32) -> String {
+        match self.label(address) {
+            Some(label) => label.to_owned(),
+            None => format!("{address:#010x}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assembler::assemble;
+    use crate::defs::dialect::Dialect;
+    use crate::defs::program::{Program, SegmentKind, StorableData};
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    use super::*;
+
+    /// Assembles the input, which must have no errors, returning the program and the words of
+    /// its text segment
+    fn assemble_str(input: &str) -> (Program, Vec<u32>) {
+        let tokens = Lexer::new(input).lex().unwrap();
+        let (mut program, _) = Parser::new(tokens.into_iter()).parse().unwrap();
+        assemble(&mut program, Dialect::Mars).unwrap();
+        let words = program
+            .segments
+            .iter()
+            .find(|s| s.kind == SegmentKind::Text)
+            .map_or(Vec::new(), |s| {
+                s.data
+                    .iter()
+                    .map(|d| match d {
+                        StorableData::Word(word) => *word,
+                        _ => panic!("Instructions should be stored as words"),
+                    })
+                    .collect()
+            });
+        (program, words)
+    }
+
+    #[test]
+    fn disassemble() {
+        let disassembler = Disassembler::new();
+        let cases = [
+            (0x0000_0000, "nop"),
+            (0x0000_0040, "ssnop"),
+            (0x0009_4100, "sll $t0, $t1, 4"),
+            (0x012a_4020, "add $t0, $t1, $t2"),
+            (0x2128_ffff, "addi $t0, $t1, -1"),
+            (0x3c08_1001, "lui $t0, 0x1001"),
+            (0x8fa8_0004, "lw $t0, 4($sp)"),
+            (0x0320_f809, "jalr $t9"),
+            (0x0320_4009, "jalr $t0, $t9"),
+            (0x0000_000f, "sync"),
+            (0x0000_004d, "break 1"),
+            (0x7128_4020, "clz $t0, $t1"),
+            (0x401a_6800, "mfc0 $k0, $13"),
+            (0x4008_6001, "mfc0 $t0, $12, 1"),
+            (0x4602_0800, "add.s $f0, $f1, $f2"),
+            (0x4624_1032, "c.eq.d $f2, $f4"),
+            (0x4624_1232, "c.eq.d 2, $f2, $f4"),
+            (0x0125_4001, "movt $t0, $t1, 1"),
+            (0x1000_ffff, "b 0x00400000"),
+            (0x0810_0000, "j 0x00400000"),
+        ];
+        for (word, text) in cases {
+            assert_eq!(
+                disassembler.disassemble(word, 0x0040_0000).as_deref(),
+                Some(text),
+                "{word:#010x}"
+            );
+        }
+        // a COP1 word with an unknown format
+        assert_eq!(disassembler.disassemble(0x4662_0800, 0x0040_0000), None);
+        // an unused opcode
+        assert_eq!(disassembler.disassemble(0xec00_0000, 0x0040_0000), None);
+        // a `clo` whose rt field is not its destination
+        assert_eq!(disassembler.disassemble(0x72e6_d021, 0x0040_0000), None);
+
+        let numeric = Disassembler::new().with_register_names(RegisterNames::Numeric);
+        assert_eq!(
+            numeric.disassemble(0x8fa8_0004, 0).as_deref(),
+            Some("lw $8, 4($29)")
+        );
+    }
+
+    #[test]
+    fn decode_every_instruction() {
+        // every instruction is identified by its fixed bits alone
+        for kind in InstructionKind::iter() {
+            let (mask, fixed) = fixed_bits(kind);
+            for other in InstructionKind::iter().filter(|other| *other != kind) {
+                assert_ne!(fixed_bits(other), (mask, fixed), "{kind} and {other}");
+            }
+            // and decoded with its operands set to 1
+            let word = fixed | (!mask & 0x0842_1084);
+            assert_eq!(decode(word).map(|i| i.kind), Some(kind), "{kind}");
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let input = "main: add $t0, $t1, $t2
+    addiu $sp, $sp, -16
+    ori $t0, $t0, 0xff
+    lui $at, 0x1001
+    lw $t0, 4($sp)
+    sw $ra, -8($sp)
+    lwc1 $f2, 8($t0)
+    sra $t0, $t1, 31
+    jalr $t9
+    jalr $t0, $t9
+    mfhi $t0
+    mult $t0, $t1
+    madd $t0, $t1
+    movn $t0, $t1, $t2
+    movf $t0, $t1, 3
+    teqi $t0, -5
+    tne $t0, $t1
+    syscall
+    break 7
+    sdbbp
+    wait
+    eret
+    mtc0 $t0, $12, 2
+    cache 1, -4($t0)
+    pref 3, 0($sp)
+    sync 1
+    cvt.s.d $f0, $f2
+    c.lt.s 5, $f1, $f2
+    sqrt.d $f0, $f2
+    mtc1 $t0, $f4
+    sdc1 $f2, 16($sp)
+loop: beq $t0, $t1, end
+    bne $t0, $zero, loop
+    bgezal $t0, loop
+    bltzal $t0, end
+    bc1f 1, loop
+    bal end
+    b loop
+    jal main
+end: j loop";
+        let (program, words) = assemble_str(input);
+        let disassembler = Disassembler::new().with_symbols(&program.symbols);
+        let mut listing = String::new();
+        for (i, word) in words.iter().enumerate() {
+            let address = 0x0040_0000 + 4 * i as u32;
+            if let Some(label) = disassembler.label(address) {
+                listing.push_str(&format!("{label}:\n"));
+            }
+            let text = disassembler.disassemble(*word, address).unwrap();
+            listing.push_str(&format!("{text}\n"));
+        }
+        assert!(listing.contains("beq $t0, $t1, end\n"));
+        assert!(listing.contains("movf $t0, $t1, 3\n"));
+        assert_eq!(assemble_str(&listing).1, words, "{listing}");
+    }
+
+    #[test]
+    fn round_trip_without_symbols() {
+        // every instruction, with the targets of branches and jumps written as addresses
+        let words = InstructionKind::iter()
+            .map(|kind| {
+                let (mask, fixed) = fixed_bits(kind);
+                fixed | (!mask & 0x0842_1084)
+            })
+            .collect::<Vec<_>>();
+        let disassembler = Disassembler::new();
+        let listing = words
+            .iter()
+            .enumerate()
+            .map(|(i, word)| {
+                let address = 0x0040_0000 + 4 * i as u32;
+                disassembler.disassemble(*word, address).unwrap() + "\n"
+            })
+            .collect::<String>();
+        assert!(listing.contains("beq $v0, $v0, 0x00404270\n"), "{listing}");
+        assert_eq!(assemble_str(&listing).1, words, "{listing}");
+    }
+}
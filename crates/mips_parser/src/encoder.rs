@@ -0,0 +1,232 @@
+use std::ops::RangeInclusive;
+
+use crate::defs::instruction::{
+    Fields, InstructionEncoding, InstructionFormat, InstructionKind, MachineInstruction,
+    OperandShape,
+};
+use crate::defs::program::{Immediate, Instruction, Operand, OperandKind};
+use crate::defs::symbols::SymbolTable;
+use crate::defs::Bits;
+use crate::errors::{AssemblerError, AssemblerErrorKind};
+
+/// Resolves the operands of an instruction placed at `address` to the values of its fields.
+///
+/// The instruction must be expanded, so that its addresses are in the `offset($base)` form, and
+/// its operands must match one of its signatures. The values that don't fit in their field,
+/// like a branch to a label that is too far, are an error.
+pub(crate) fn resolve(
+    instruction: &Instruction,
+    address: u32,
+    symbols: &SymbolTable,
+) -> Result<MachineInstruction, AssemblerError> {
+    use InstructionKind::*;
+    use OperandShape::*;
+
+    let kind = instruction.kind;
+    let signature = kind
+        .matching_signature(&instruction.operands)
+        .expect("The instruction should be checked before being encoded");
+    // the selectors are the fixed bits of the fields, the operands fill in the others
+    let selector = |field: Option<Bits<5>>| field.map_or(0, |bits| bits.get());
+    let mut rs = selector(kind.rs());
+    let mut rt = selector(kind.rt());
+    let mut rd = selector(kind.rd());
+    let mut shamt = selector(kind.shamt());
+    let mut funct = kind.funct().map_or(0, |bits| bits.get());
+    let mut imm = Bits::new(0);
+    let mut target = Bits::new(0);
+    for (shape, operand) in signature.iter().zip(&instruction.operands) {
+        match (shape, &operand.kind) {
+            (Rd | Fs, OperandKind::Register(reg)) => rd = reg.number() as u32,
+            (Rs, OperandKind::Register(reg)) => rs = reg.number() as u32,
+            (Rt | Ft, OperandKind::Register(reg)) => rt = reg.number() as u32,
+            (Fd, OperandKind::Register(reg)) => shamt = reg.number() as u32,
+            (Imm16 | UImm16, OperandKind::Immediate(value)) => {
+                imm = immediate(instruction, operand, *shape, value, symbols)?;
+            }
+            (Address, OperandKind::Address(memory)) => {
+                imm = immediate(instruction, operand, *shape, &memory.offset, symbols)?;
+                rs = memory.base.as_ref().map_or(0, |base| base.number() as u32);
+            }
+            (Shamt, _) => shamt = field(instruction, operand, *shape, symbols)?,
+            // the type of synchronization of `sync` is in the shamt field
+            (Hint, _) if kind == Sync => shamt = field(instruction, operand, *shape, symbols)?,
+            (Hint, _) => rt = field(instruction, operand, *shape, symbols)?,
+            (Code, _) => {
+                let code = field(instruction, operand, *shape, symbols)?;
+                // the code of `wait` can't use the upper bit, which is part of the selector
+                if kind == Wait && code >> 19 != 0 {
+                    return Err(out_of_range(
+                        operand,
+                        0..=0x7_ffff,
+                        "`wait` expects a 19 bit code here".into(),
+                    ));
+                }
+                rs |= code >> 15;
+                rt = (code >> 10) & 0x1f;
+                rd = (code >> 5) & 0x1f;
+                shamt = code & 0x1f;
+            }
+            // the condition code is in the upper 3 bits of a 5 bit field
+            (Cc, _) => {
+                let cc = field(instruction, operand, *shape, symbols)? << 2;
+                match kind {
+                    CEqS | CEqD | CLeS | CLeD | CLtS | CLtD => shamt |= cc,
+                    _ => rt |= cc,
+                }
+            }
+            (Sel, _) => funct |= field(instruction, operand, *shape, symbols)?,
+            (Label, _) => imm = branch_offset(instruction, operand, address, symbols)?,
+            (Target, _) => target = jump_target(instruction, operand, address, symbols)?,
+            _ => unreachable!("The operand should match the signature"),
+        }
+    }
+    match kind {
+        // `jalr $rs` links to `$ra`
+        Jalr if !signature.contains(&Rd) => rd = 31,
+        // the manual requires both destination fields to name the same register
+        Clo | Clz => rt = rd,
+        _ => {}
+    }
+
+    let fields = match kind.format() {
+        InstructionFormat::R => Fields::R {
+            rs: Bits::new(rs),
+            rt: Bits::new(rt),
+            rd: Bits::new(rd),
+            shamt: Bits::new(shamt),
+            funct: Bits::new(funct),
+        },
+        InstructionFormat::I => Fields::I {
+            rs: Bits::new(rs),
+            rt: Bits::new(rt),
+            imm,
+        },
+        InstructionFormat::J => Fields::J { target },
+    };
+    Ok(MachineInstruction { kind, fields })
+}
+
+/// Returns the value of an operand that depends on labels
+fn value(operand: &Operand, symbols: &SymbolTable) -> Result<i64, AssemblerError> {
+    match &operand.kind {
+        OperandKind::Immediate(imm) => Ok(imm.resolve(symbols, &operand.span)?),
+        OperandKind::Address(address) => Ok(address.offset.resolve(symbols, &operand.span)?),
+        OperandKind::Label(label) => match symbols.get(label) {
+            Some(symbol) => Ok(symbol.address as i64),
+            None => Err(AssemblerError::new(
+                AssemblerErrorKind::UndefinedLabel(label.clone()),
+                operand.span.clone(),
+            )),
+        },
+        _ => unreachable!("The operand should be a value"),
+    }
+}
+
+/// Returns the value of an operand stored in a field smaller than 16 bits, like a shift amount,
+/// checked against the range of its shape
+fn field(
+    instruction: &Instruction,
+    operand: &Operand,
+    shape: OperandShape,
+    symbols: &SymbolTable,
+) -> Result<u32, AssemblerError> {
+    let value = value(operand, symbols)?;
+    let range = shape.range().expect("The shape should be a constant");
+    if range.contains(&value) {
+        Ok(value as u32)
+    } else {
+        let expected = format!("`{}` expects {} here", instruction.kind, shape.width());
+        Err(out_of_range(operand, range, expected))
+    }
+}
+
+/// Returns the bits of a 16 bit immediate or address offset. `%hi` and `%lo` select 16 bits of
+/// a value so they always fit.
+fn immediate(
+    instruction: &Instruction,
+    operand: &Operand,
+    shape: OperandShape,
+    imm: &Immediate,
+    symbols: &SymbolTable,
+) -> Result<Bits<16>, AssemblerError> {
+    let value = imm.resolve(symbols, &operand.span)?;
+    let bits = match imm {
+        Immediate::Hi(_) | Immediate::Lo(_) => Bits::try_new(value as u32 & 0xffff),
+        _ if shape == OperandShape::UImm16 => u32::try_from(value).ok().and_then(Bits::try_new),
+        _ => Bits::from_signed(value),
+    };
+    bits.ok_or_else(|| {
+        let range = match shape {
+            OperandShape::UImm16 => 0..=u16::MAX as i64,
+            _ => i16::MIN as i64..=i16::MAX as i64,
+        };
+        let width = match shape {
+            OperandShape::Address => "a signed 16 bit offset",
+            _ => shape.width(),
+        };
+        let expected = format!("`{}` expects {width} here", instruction.kind);
+        out_of_range(operand, range, expected)
+    })
+}
+
+/// Returns the offset of a branch to a label, counted in instructions from the one after the
+/// branch
+fn branch_offset(
+    instruction: &Instruction,
+    operand: &Operand,
+    address: u32,
+    symbols: &SymbolTable,
+) -> Result<Bits<16>, AssemblerError> {
+    let target = value(operand, symbols)?;
+    check_alignment(operand, target)?;
+    let distance = target - (address as i64 + 4);
+    Bits::from_signed(distance / 4).ok_or_else(|| {
+        let expected = format!(
+            "`{}` expects a label within 128 KiB of the instruction after it",
+            instruction.kind
+        );
+        out_of_range(operand, -0x2_0000..=0x1_fffc, expected)
+    })
+}
+
+/// Returns the field of a jump to an address, which holds the address without its upper 4 bits
+/// and its lower 2 bits. The upper bits are the ones of the instruction after the jump.
+fn jump_target(
+    instruction: &Instruction,
+    operand: &Operand,
+    address: u32,
+    symbols: &SymbolTable,
+) -> Result<Bits<26>, AssemblerError> {
+    let target = value(operand, symbols)?;
+    check_alignment(operand, target)?;
+    let region = (address.wrapping_add(4) & 0xf000_0000) as i64;
+    let range = region..=region + 0x0fff_fffc;
+    if !range.contains(&target) {
+        let expected = format!(
+            "`{}` expects an address in the same 256 MiB region as the instruction after it",
+            instruction.kind
+        );
+        return Err(out_of_range(operand, range, expected));
+    }
+    Ok(Bits::new((target as u32 & 0x0fff_ffff) >> 2))
+}
+
+/// Checks that the target of a branch or jump is the address of an instruction
+fn check_alignment(operand: &Operand, target: i64) -> Result<(), AssemblerError> {
+    if target % 4 == 0 {
+        Ok(())
+    } else {
+        Err(AssemblerError::new(
+            AssemblerErrorKind::MisalignedTarget(target),
+            operand.span.clone(),
+        ))
+    }
+}
+
+fn out_of_range(operand: &Operand, range: RangeInclusive<i64>, expected: String) -> AssemblerError {
+    AssemblerError::new(
+        AssemblerErrorKind::ValueOutOfRange { range, expected },
+        operand.span.clone(),
+    )
+}
@@ -1,7 +1,14 @@
-use std::ops::Range;
+use std::ops::{Range, RangeInclusive};
 
-use crate::defs::register::RegisterParseError;
+use crate::defs::dialect::Dialect;
+use crate::defs::directive::Directive;
+use crate::defs::expression::{EvalError, EvalErrorKind};
+use crate::defs::program::SegmentKind;
+use crate::defs::pseudo::PseudoKind;
+use crate::defs::register::{Register, RegisterParseError};
+use crate::source::SourceDb;
 use ariadne::{sources, Config, IndexType, Label, Report, ReportKind};
+use strum::{Display, EnumDiscriminants, EnumString};
 use thiserror::Error;
 
 /// This trait implements functions that define how an error is displayed with [`ariadne`].
@@ -15,60 +22,111 @@ pub(crate) trait AriadneError {
     fn note(&self) -> Option<String> {
         None
     }
+    /// Other spans related to the error, with the text of their labels
+    fn secondary_labels(&self) -> Vec<(Range<usize>, String)> {
+        Vec::new()
+    }
 }
 
+/// All the errors found while compiling a file
 #[derive(Debug, Error)]
-pub enum CompileError {
-    #[error("Syntax error {0}")]
-    Lexer(#[from] LexerError),
-    // #[error("Parsing error: {0}")]
-    // Parser(#[from] Parser Error)
+#[error("{} error(s) found", errors.len())]
+pub struct CompileError {
+    pub errors: Vec<SourceError>,
+}
+
+impl<E: Into<SourceError>> From<Vec<E>> for CompileError {
+    fn from(errors: Vec<E>) -> Self {
+        Self {
+            errors: errors.into_iter().map(Into::into).collect(),
+        }
+    }
 }
 
 impl CompileError {
-    pub fn display_formatted(&self, file_name: String, file_content: &str) -> std::io::Result<()> {
-        let mut report = Report::build(
-            ReportKind::Error,
-            file_name.clone(),
-            self.get_span()
-                .next()
-                .expect("Error span should be pointing to at least one byte."),
-        )
-        .with_config(Config::default().with_index_type(IndexType::Byte))
-        .with_message(self.general_message());
-        report.add_label(
-            Label::new((file_name.clone(), self.get_span())).with_message(self.label_message()),
-        );
-        if let Some(note) = self.get_note() {
-            report.set_note(note);
+    /// Prints a report for every error, in the order they appear in the files
+    pub fn display_formatted(&self, sources: &SourceDb) -> std::io::Result<()> {
+        let mut errors = self.errors.iter().collect::<Vec<_>>();
+        errors.sort_by_key(|err| err.get_span().start);
+        for err in errors {
+            err.display_formatted(sources)?;
         }
-        report
-            .finish()
-            .eprint(sources(vec![(file_name, file_content)]))?;
         Ok(())
     }
+}
 
-    fn get_note(&self) -> Option<String> {
-        match self {
-            CompileError::Lexer(err) => err.kind.note(),
-        }
+/// An error that points to a span of the source
+#[derive(Debug, Error)]
+pub enum SourceError {
+    #[error("Syntax error {0}")]
+    Lexer(#[from] LexerError),
+    #[error("Parsing error: {0}")]
+    Parser(#[from] ParserError),
+    #[error("Assembler error: {0}")]
+    Assembler(#[from] AssemblerError),
+    /// A warning treated as an error
+    #[error("Warning: {0}")]
+    Warning(#[from] Warning),
+}
+
+/// Prints a report with [`ariadne`] that points to `span`, the spans are resolved to the file
+/// they point into
+fn print_report(
+    kind: ReportKind,
+    span: Range<usize>,
+    err: &dyn AriadneError,
+    source_db: &SourceDb,
+) -> std::io::Result<()> {
+    let label = |span: &Range<usize>| {
+        let (file, span) = source_db.resolve(span);
+        // the end of file is past the last byte of the file, point to the last byte instead
+        let len = source_db.content(file).len();
+        let span = if span.start >= len {
+            len.saturating_sub(1)..len
+        } else {
+            span.start..span.end.min(len)
+        };
+        (source_db.name(file).to_owned(), span)
+    };
+    let (file_name, local_span) = label(&span);
+    let mut report = Report::build(kind, file_name, local_span.start)
+        .with_config(Config::default().with_index_type(IndexType::Byte))
+        .with_message(err.general_message());
+    report.add_label(Label::new(label(&span)).with_message(err.label()));
+    for (span, text) in err.secondary_labels() {
+        report.add_label(Label::new(label(&span)).with_message(text));
     }
+    if let Some(note) = err.note() {
+        report.set_note(note);
+    }
+    let files = source_db
+        .files()
+        .map(|file| (source_db.name(file).to_owned(), source_db.content(file)));
+    report.finish().eprint(sources(files))?;
+    Ok(())
+}
 
-    fn general_message(&self) -> String {
-        match self {
-            CompileError::Lexer(err) => err.kind.general_message(),
-        }
+impl SourceError {
+    pub fn display_formatted(&self, sources: &SourceDb) -> std::io::Result<()> {
+        print_report(ReportKind::Error, self.get_span(), self.kind(), sources)
     }
 
-    fn get_span(&self) -> Range<usize> {
+    fn kind(&self) -> &dyn AriadneError {
         match self {
-            CompileError::Lexer(err) => err.span.clone(),
+            SourceError::Lexer(err) => &err.kind,
+            SourceError::Parser(err) => &err.kind,
+            SourceError::Assembler(err) => &err.kind,
+            SourceError::Warning(warn) => &warn.kind,
         }
     }
 
-    fn label_message(&self) -> String {
+    /// Returns the span the error points to, which can be resolved to a file by the [`SourceDb`]
+    pub fn get_span(&self) -> Range<usize> {
         match self {
-            CompileError::Lexer(err) => err.kind.label(),
+            SourceError::Lexer(err) => err.span.clone(),
+            SourceError::Parser(err) => err.span.clone(),
+            SourceError::Assembler(err) => err.span.clone(),
+            SourceError::Warning(warn) => warn.span.clone(),
         }
     }
 }
@@ -101,6 +159,16 @@ pub enum LexerErrorKind {
     NumberParseError,
     #[error("Number out of range.")]
     NumberOutOfRange,
+    #[error("Invalid escape sequence.")]
+    InvalidEscape,
+    #[error("Expected character literal closing delimiter.")]
+    ExpectedCharEnd,
+    #[error("Invalid character literal.")]
+    InvalidCharLiteral,
+    #[error("`{name}` is not available in the {dialect} dialect.")]
+    NotInDialect { name: String, dialect: Dialect },
+    #[error("Expected the end of the comment.")]
+    ExpectedCommentEnd,
 }
 
 impl AriadneError for LexerErrorKind {
@@ -115,6 +183,13 @@ impl AriadneError for LexerErrorKind {
             LexerErrorKind::ExpectedStringEnd => "The string should be closed".into(),
             LexerErrorKind::NumberParseError => "This number/address is not valid".into(),
             LexerErrorKind::NumberOutOfRange => "This number is out of range".into(),
+            LexerErrorKind::InvalidEscape => "This escape sequence is not valid".into(),
+            LexerErrorKind::ExpectedCharEnd => "The character literal should be closed".into(),
+            LexerErrorKind::InvalidCharLiteral => {
+                "This should contain exactly one character".into()
+            }
+            LexerErrorKind::NotInDialect { .. } => "This belongs to another dialect".into(),
+            LexerErrorKind::ExpectedCommentEnd => "This comment is never closed".into(),
         }
     }
     fn note(&self) -> Option<String> {
@@ -123,8 +198,388 @@ impl AriadneError for LexerErrorKind {
             LexerErrorKind::ExpectedStringEnd => {
                 Some("The quote that should close the string is missing.".into())
             }
-            LexerErrorKind::NumberOutOfRange => Some("The number is represented with 16 bits, therefore it must be between -32768 and 32767".into()),
+            LexerErrorKind::NumberOutOfRange => {
+                Some("Integers must fit in 64 bits and floating point numbers in a double.".into())
+            }
+            LexerErrorKind::InvalidEscape => Some(
+                r#"Valid escapes are \n \t \r \0 \\ \" \' \a \b \f \v, \x with one or two hex digits, and up to three octal digits below \400."#.into(),
+            ),
+            LexerErrorKind::InvalidCharLiteral => Some(
+                "Use a string with `.ascii` or `.asciiz` for more than one character.".into(),
+            ),
+            LexerErrorKind::NotInDialect { .. } => Some(
+                "The dialects `mars`, `spim` and `gnu` each accept the syntax of their assembler.".into(),
+            ),
+            LexerErrorKind::ExpectedCommentEnd => {
+                Some("A comment that starts with `/*` must end with `*/`.".into())
+            }
             _ => None,
         }
     }
 }
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("{kind}")]
+pub struct ParserError {
+    pub kind: ParserErrorKind,
+    // The span of bytes that caused the error
+    pub span: Range<usize>,
+}
+
+impl ParserError {
+    pub fn new(kind: ParserErrorKind, span: Range<usize>) -> Self {
+        Self { kind, span }
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ParserErrorKind {
+    #[error("Unexpected token, expected {0}.")]
+    UnexpectedToken(String),
+    #[error("Missing comma between operands.")]
+    MissingComma,
+    #[error("Wrong number of operands: found {found}.")]
+    WrongOperandCount {
+        found: usize,
+        /// Describes the operands that were expected
+        usage: String,
+    },
+    #[error("Wrong kind of operand.")]
+    WrongOperandKind {
+        /// Describes the operands that were expected
+        usage: String,
+    },
+    #[error("Directive `.{directive}` is not allowed in the {segment} segment.")]
+    DirectiveOutsideSegment {
+        directive: Directive,
+        segment: SegmentKind,
+    },
+    #[error("Instruction in the {0} segment.")]
+    InstructionInDataSegment(SegmentKind),
+    #[error("Division by zero.")]
+    DivisionByZero,
+    #[error("Shift by {0} bits.")]
+    ShiftOutOfRange(i64),
+    #[error("Arithmetic overflow.")]
+    Overflow,
+    #[error("Value out of range.")]
+    ValueOutOfRange {
+        range: RangeInclusive<i64>,
+        /// Describes the value expected in the position of the operand
+        expected: String,
+    },
+    #[error("Expected a constant.")]
+    ExpectedConstant,
+    #[error("Value out of range for `.float`.")]
+    FloatOutOfRange,
+    #[error("Couldn't include `{path}`: {reason}.")]
+    IncludeFailed { path: String, reason: String },
+    #[error("`{0}` includes itself.")]
+    RecursiveInclude(String),
+    #[error("`$at` is needed to expand this operand after `.set noat`.")]
+    AtUnavailable,
+    #[error("`{0}` is expanded using `$at`, which is not available after `.set noat`.")]
+    PseudoNeedsAt(PseudoKind),
+    #[error("The {0} segment already has statements.")]
+    SegmentAlreadyStarted(SegmentKind),
+    #[error("No local label `{number}:` is defined {} this reference.", if *forward { "after" } else { "before" })]
+    UndefinedLocalLabel { number: u32, forward: bool },
+}
+
+impl AriadneError for ParserErrorKind {
+    fn general_message(&self) -> String {
+        format!("{self}")
+    }
+    fn label(&self) -> String {
+        match self {
+            ParserErrorKind::UnexpectedToken(expected) => format!("Expected {expected} here"),
+            ParserErrorKind::MissingComma => "Expected a comma before this operand".into(),
+            ParserErrorKind::WrongOperandCount { .. } => {
+                "This statement has the wrong number of operands".into()
+            }
+            ParserErrorKind::WrongOperandKind { .. } => {
+                "This operand is not valid in this position".into()
+            }
+            ParserErrorKind::DirectiveOutsideSegment { .. } => {
+                "This directive can't be used here".into()
+            }
+            ParserErrorKind::InstructionInDataSegment(_) => {
+                "Instructions can only be placed in a text segment".into()
+            }
+            ParserErrorKind::DivisionByZero => "This expression is zero".into(),
+            ParserErrorKind::ShiftOutOfRange(_) => "This shift amount is out of range".into(),
+            ParserErrorKind::Overflow => {
+                "The result of this expression doesn't fit in 64 bits".into()
+            }
+            ParserErrorKind::ValueOutOfRange { .. } => {
+                "This value doesn't fit in the operand".into()
+            }
+            ParserErrorKind::ExpectedConstant => {
+                "This value depends on the address of a label".into()
+            }
+            ParserErrorKind::FloatOutOfRange => {
+                "This number is too large for a single precision float".into()
+            }
+            ParserErrorKind::IncludeFailed { .. } => "This file can't be read".into(),
+            ParserErrorKind::RecursiveInclude(_) => "This file is already being included".into(),
+            ParserErrorKind::AtUnavailable => "This address doesn't fit in an offset".into(),
+            ParserErrorKind::PseudoNeedsAt(_) => {
+                "This pseudo-instruction needs a temporary register".into()
+            }
+            ParserErrorKind::SegmentAlreadyStarted(_) => {
+                "This address would move the statements already in the segment".into()
+            }
+            ParserErrorKind::UndefinedLocalLabel { number, forward } => {
+                let direction = if *forward { "next" } else { "previous" };
+                format!("This refers to the {direction} `{number}:`")
+            }
+        }
+    }
+    fn note(&self) -> Option<String> {
+        match self {
+            ParserErrorKind::UnexpectedToken(_) => Some(
+                "Each line contains optional label definitions followed by an instruction or a directive and its operands, like `loop: addi $t0, $t0, 1`.".into(),
+            ),
+            ParserErrorKind::MissingComma => {
+                Some("Operands must be separated by commas, like `add $t0, $t1, $t2`.".into())
+            }
+            ParserErrorKind::WrongOperandCount { usage, .. }
+            | ParserErrorKind::WrongOperandKind { usage } => Some(format!("{usage}.")),
+            ParserErrorKind::DirectiveOutsideSegment { .. } => Some(
+                "Data can only be stored in the data segments, switch to one with `.data` or `.kdata` first.".into(),
+            ),
+            ParserErrorKind::InstructionInDataSegment(_) => Some(
+                "Switch to a text segment with `.text` or `.ktext` before writing instructions.".into(),
+            ),
+            ParserErrorKind::DivisionByZero | ParserErrorKind::Overflow => None,
+            ParserErrorKind::ShiftOutOfRange(_) => Some(SHIFT_NOTE.into()),
+            ParserErrorKind::ValueOutOfRange { range, expected } => {
+                Some(range_note(range, expected))
+            }
+            ParserErrorKind::ExpectedConstant => Some(
+                "The value must be known before the program is laid out in memory.".into(),
+            ),
+            ParserErrorKind::FloatOutOfRange => Some(
+                "The largest single precision value is about 3.4e38, use `.double` for larger numbers.".into(),
+            ),
+            ParserErrorKind::IncludeFailed { .. } => Some(
+                "The path is relative to the directory of the file with the `.include`.".into(),
+            ),
+            ParserErrorKind::RecursiveInclude(_) => None,
+            ParserErrorKind::AtUnavailable => Some(
+                "Compute the address in a register first, or allow the assembler to use `$at` with `.set at`.".into(),
+            ),
+            ParserErrorKind::PseudoNeedsAt(_) => Some(
+                "Write the instructions it stands for with another register, or allow the assembler to use `$at` with `.set at`.".into(),
+            ),
+            ParserErrorKind::SegmentAlreadyStarted(segment) => Some(format!(
+                "The start address of a segment can only be given before its first statement, write `.{segment}` alone to continue it."
+            )),
+            ParserErrorKind::UndefinedLocalLabel { .. } => Some(
+                "`1b` refers to the closest `1:` before it, and `1f` to the closest one after it.".into(),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("{kind}")]
+pub struct AssemblerError {
+    pub kind: AssemblerErrorKind,
+    // The span of bytes that caused the error
+    pub span: Range<usize>,
+}
+
+impl AssemblerError {
+    pub fn new(kind: AssemblerErrorKind, span: Range<usize>) -> Self {
+        Self { kind, span }
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum AssemblerErrorKind {
+    #[error("Label `{name}` is defined more than once.")]
+    DuplicateLabel {
+        name: String,
+        /// The span of the first definition
+        first: Range<usize>,
+    },
+    #[error("Label `{0}` is not defined.")]
+    UndefinedLabel(String),
+    #[error("Division by zero.")]
+    DivisionByZero,
+    #[error("Shift by {0} bits.")]
+    ShiftOutOfRange(i64),
+    #[error("Arithmetic overflow.")]
+    Overflow,
+    #[error("Value out of range.")]
+    ValueOutOfRange {
+        range: RangeInclusive<i64>,
+        /// Describes the value expected in the position of the operand
+        expected: String,
+    },
+    #[error("Misaligned target.")]
+    MisalignedTarget(i64),
+    #[error("The {0} segment is too large.")]
+    SegmentOverflow(SegmentKind),
+}
+
+impl AriadneError for AssemblerErrorKind {
+    fn general_message(&self) -> String {
+        format!("{self}")
+    }
+    fn label(&self) -> String {
+        match self {
+            AssemblerErrorKind::DuplicateLabel { .. } => "Defined again here".into(),
+            AssemblerErrorKind::UndefinedLabel(_) => "This label doesn't exist".into(),
+            AssemblerErrorKind::DivisionByZero => "This expression is zero".into(),
+            AssemblerErrorKind::ShiftOutOfRange(_) => "This shift amount is out of range".into(),
+            AssemblerErrorKind::Overflow => {
+                "The result of this expression doesn't fit in 64 bits".into()
+            }
+            AssemblerErrorKind::ValueOutOfRange { .. } => {
+                "This value doesn't fit in the operand".into()
+            }
+            AssemblerErrorKind::MisalignedTarget(_) => "This address is not a multiple of 4".into(),
+            AssemblerErrorKind::SegmentOverflow(_) => {
+                "This goes past the end of the segment".into()
+            }
+        }
+    }
+    fn note(&self) -> Option<String> {
+        match self {
+            AssemblerErrorKind::DuplicateLabel { .. } => {
+                Some("Every label must have a unique name, rename one of them.".into())
+            }
+            AssemblerErrorKind::ValueOutOfRange { range, expected } => {
+                Some(range_note(range, expected))
+            }
+            AssemblerErrorKind::MisalignedTarget(target) => Some(format!(
+                "Instructions are 4 bytes long, so the target of a branch or jump must be a multiple of 4, not {target:#x}."
+            )),
+            AssemblerErrorKind::SegmentOverflow(segment) => Some(format!(
+                "The {segment} segment starts at {:#010x} and must end before {:#010x}.",
+                segment.base_address(),
+                segment.end_address()
+            )),
+            AssemblerErrorKind::ShiftOutOfRange(_) => Some(SHIFT_NOTE.into()),
+            AssemblerErrorKind::UndefinedLabel(_)
+            | AssemblerErrorKind::DivisionByZero
+            | AssemblerErrorKind::Overflow => None,
+        }
+    }
+    fn secondary_labels(&self) -> Vec<(Range<usize>, String)> {
+        match self {
+            AssemblerErrorKind::DuplicateLabel { first, .. } => {
+                vec![(first.clone(), "First defined here".into())]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl From<EvalError> for AssemblerError {
+    fn from(err: EvalError) -> Self {
+        let kind = match err.kind {
+            EvalErrorKind::DivisionByZero => AssemblerErrorKind::DivisionByZero,
+            EvalErrorKind::ShiftOutOfRange(amount) => AssemblerErrorKind::ShiftOutOfRange(amount),
+            EvalErrorKind::Overflow => AssemblerErrorKind::Overflow,
+            EvalErrorKind::UndefinedSymbol(name) => AssemblerErrorKind::UndefinedLabel(name),
+        };
+        Self::new(kind, err.span)
+    }
+}
+
+/// Explains the shift amounts expressions accept
+const SHIFT_NOTE: &str =
+    "Expressions are computed with 64 bits, so values can only be shifted by 0 to 63 bits.";
+
+/// Describes the values an operand can have
+fn range_note(range: &RangeInclusive<i64>, expected: &str) -> String {
+    format!("{expected}, between {} and {}.", range.start(), range.end())
+}
+
+/// A statement that is valid but suspicious
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("{kind}")]
+pub struct Warning {
+    pub kind: WarningKind,
+    // The span of bytes the warning refers to
+    pub span: Range<usize>,
+}
+
+impl Warning {
+    pub fn new(kind: WarningKind, span: Range<usize>) -> Self {
+        Self { kind, span }
+    }
+
+    /// Returns the name used to refer to this kind of warning, like `unused-label`
+    pub fn name(&self) -> WarningName {
+        (&self.kind).into()
+    }
+
+    pub fn display_formatted(&self, sources: &SourceDb) -> std::io::Result<()> {
+        print_report(ReportKind::Warning, self.span.clone(), &self.kind, sources)
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq, EnumDiscriminants)]
+#[strum_discriminants(
+    name(WarningName),
+    derive(EnumString, Display),
+    strum(serialize_all = "kebab-case")
+)]
+pub enum WarningKind {
+    #[error("Write to `$zero`.")]
+    WriteToZero,
+    #[error("Use of the reserved register `{0}`.")]
+    ReservedRegister(Register),
+    #[error("Label `{0}` is never used.")]
+    UnusedLabel(String),
+    #[error("Value doesn't fit in `.{0}`.")]
+    TruncatedValue(Directive),
+    #[error("Unreachable instruction.")]
+    UnreachableCode,
+}
+
+impl AriadneError for WarningKind {
+    fn general_message(&self) -> String {
+        format!("{self}")
+    }
+    fn label(&self) -> String {
+        match self {
+            WarningKind::WriteToZero => "The result of this instruction is discarded".into(),
+            WarningKind::ReservedRegister(_) => "This register is reserved".into(),
+            WarningKind::UnusedLabel(_) => "This label is defined but never referenced".into(),
+            WarningKind::TruncatedValue(_) => "This value will be truncated".into(),
+            WarningKind::UnreachableCode => "This instruction is never executed".into(),
+        }
+    }
+    fn note(&self) -> Option<String> {
+        match self {
+            WarningKind::WriteToZero => {
+                Some("`$zero` always reads as 0, writes to it are ignored.".into())
+            }
+            WarningKind::ReservedRegister(reg) if reg.number() == 1 => Some(
+                "`$at` is used by the assembler to expand pseudo-instructions and label addresses, its value can change between any two instructions.".into(),
+            ),
+            WarningKind::ReservedRegister(_) => Some(
+                "`$k0` and `$k1` are reserved for the kernel, the exception handler can overwrite them at any time.".into(),
+            ),
+            WarningKind::UnusedLabel(_) => None,
+            WarningKind::TruncatedValue(directive) => {
+                let range = match directive {
+                    Directive::Byte => "-128 and 255",
+                    Directive::Word => "-2147483648 and 4294967295",
+                    _ => "-32768 and 65535",
+                };
+                Some(format!(
+                    "Only the lowest bits are stored, the value should be between {range}."
+                ))
+            }
+            WarningKind::UnreachableCode => Some(
+                "The previous instruction always jumps, add a label if this instruction is the target of a jump.".into(),
+            ),
+        }
+    }
+}
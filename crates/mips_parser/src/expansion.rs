@@ -0,0 +1,429 @@
+use std::ops::Range;
+
+use crate::defs::instruction::{InstructionKind, OperandShape};
+use crate::defs::program::{
+    Address, Immediate, Instruction, Operand, OperandKind, PseudoInstruction,
+};
+use crate::defs::pseudo::PseudoKind;
+use crate::defs::register::{Register, RegisterName};
+use crate::errors::{ParserError, ParserErrorKind};
+
+/// Expands a checked instruction into the machine instructions that implement it.
+///
+/// Memory operands are normalized to the `offset($base)` form. The ones that depend on the
+/// address of a label, or whose offset doesn't fit in 16 bits, compute the address in `$at`
+/// first, like MARS does, so `lw $t0, label+4($t1)` becomes `lui $at, %hi(label+4)`,
+/// `addu $at, $at, $t1`, `lw $t0, %lo(label+4)($at)`.
+/// Every generated instruction keeps the span of the original one. If `$at` is not available,
+/// because of a `.set noat`, the operands that need it are an error.
+pub(crate) fn expand(
+    mut instruction: Instruction,
+    at_available: bool,
+) -> Result<Vec<Instruction>, ParserError> {
+    let mut res = Vec::new();
+    let signature = instruction
+        .kind
+        .matching_signature(&instruction.operands)
+        .expect("The instruction should be checked before being expanded");
+    let span = instruction.span.clone();
+    for (shape, operand) in signature.iter().zip(instruction.operands.iter_mut()) {
+        if *shape != OperandShape::Address {
+            continue;
+        }
+        let address = address_of(&operand.kind);
+        let at = || Operand {
+            kind: OperandKind::Register(Register::Name(RegisterName::At)),
+            span: operand.span.clone(),
+        };
+        let address = match address {
+            Address { .. } if !fits_offset(&address.offset) && !at_available => {
+                return Err(ParserError::new(
+                    ParserErrorKind::AtUnavailable,
+                    operand.span.clone(),
+                ));
+            }
+            Address { offset, base } if !fits_offset(&offset) => {
+                res.push(Instruction {
+                    kind: InstructionKind::Lui,
+                    operands: vec![
+                        at(),
+                        Operand {
+                            kind: OperandKind::Immediate(Immediate::Hi(Box::new(offset.clone()))),
+                            span: operand.span.clone(),
+                        },
+                    ],
+                    span: span.clone(),
+                });
+                if let Some(base) = base {
+                    res.push(Instruction {
+                        kind: InstructionKind::Addu,
+                        operands: vec![
+                            at(),
+                            at(),
+                            Operand {
+                                kind: OperandKind::Register(base),
+                                span: operand.span.clone(),
+                            },
+                        ],
+                        span: span.clone(),
+                    });
+                }
+                Address {
+                    offset: Immediate::Lo(Box::new(offset)),
+                    base: Some(Register::Name(RegisterName::At)),
+                }
+            }
+            // an absolute address that fits in the offset is relative to `$zero`
+            Address { offset, base: None } => Address {
+                offset,
+                base: Some(Register::Number(0)),
+            },
+            address => address,
+        };
+        operand.kind = OperandKind::Address(address);
+    }
+    res.push(instruction);
+    Ok(res)
+}
+
+/// Expands a checked pseudo-instruction into the machine instructions that implement it.
+///
+/// Constants get the shortest expansion for their value, so `li $t0, 1` becomes
+/// `addiu $t0, $zero, 1` while `li $t0, 0x12345678` becomes `lui $t0, 0x1234`,
+/// `ori $t0, $t0, 0x5678`. Values that depend on the address of a label are not known yet, so
+/// they always take a `lui` and an `addiu`. The pseudo-instructions that need a temporary
+/// register use `$at`, which is an error after `.set noat`. Every generated instruction keeps
+/// the span of the pseudo-instruction, and the operands from the source keep their own.
+pub(crate) fn expand_pseudo(
+    pseudo: PseudoInstruction,
+    at_available: bool,
+) -> Result<Vec<Instruction>, ParserError> {
+    use InstructionKind as I;
+    use PseudoKind::*;
+
+    let mut out = Expansion {
+        span: pseudo.span.clone(),
+        instructions: Vec::new(),
+        uses_at: false,
+    };
+    let zero = out.register(Register::Number(0));
+    let ops = &pseudo.operands;
+    match pseudo.kind {
+        Li => out.load(ops[0].clone(), &ops[1]),
+        La => out.address(ops[0].clone(), &ops[1]),
+        Move => out.push(I::Addu, [ops[0].clone(), zero, ops[1].clone()]),
+        Beqz => out.push(I::Beq, [ops[0].clone(), zero, ops[1].clone()]),
+        Bnez => out.push(I::Bne, [ops[0].clone(), zero, ops[1].clone()]),
+        Blt | Bgt | Ble | Bge | Bltu | Bgtu | Bleu | Bgeu => {
+            let (slt, slti) = match pseudo.kind {
+                Bltu | Bgtu | Bleu | Bgeu => (I::Sltu, I::Sltiu),
+                _ => (I::Slt, I::Slti),
+            };
+            // `blt` and `bge` test `$rs < $rt`, `bgt` and `ble` test `$rt < $rs`
+            let swapped = matches!(pseudo.kind, Bgt | Ble | Bgtu | Bleu);
+            let branch = match pseudo.kind {
+                Blt | Bgt | Bltu | Bgtu => I::Bne,
+                _ => I::Beq,
+            };
+            let at = out.at();
+            if !swapped && fits_imm16(&ops[1]) {
+                out.push(slti, [at.clone(), ops[0].clone(), ops[1].clone()]);
+            } else {
+                let rt = out.in_register(&ops[1]);
+                let (lhs, rhs) = if swapped {
+                    (rt, ops[0].clone())
+                } else {
+                    (ops[0].clone(), rt)
+                };
+                out.push(slt, [at.clone(), lhs, rhs]);
+            }
+            out.push(branch, [at, zero, ops[2].clone()]);
+        }
+        Not => out.push(I::Nor, [ops[0].clone(), ops[1].clone(), zero]),
+        Neg => out.push(I::Sub, [ops[0].clone(), zero, ops[1].clone()]),
+        Abs => {
+            // `$at` is all ones for negative values, which flips the bits and adds one
+            let at = out.at();
+            let sign = derived(&ops[1], OperandKind::Immediate(Immediate::Number(31)));
+            out.push(I::Sra, [at.clone(), ops[1].clone(), sign]);
+            out.push(I::Xor, [ops[0].clone(), ops[1].clone(), at.clone()]);
+            out.push(I::Subu, [ops[0].clone(), ops[0].clone(), at]);
+        }
+        Subi | Subiu => {
+            let sub = match pseudo.kind {
+                Subi => I::Sub,
+                _ => I::Subu,
+            };
+            let rt = out.in_register(&ops[2]);
+            out.push(sub, [ops[0].clone(), ops[1].clone(), rt]);
+        }
+        Mul => {
+            let rt = out.in_register(&ops[2]);
+            out.push(I::Mul, [ops[0].clone(), ops[1].clone(), rt]);
+        }
+        Div | Divu | Rem | Remu => {
+            let rt = out.in_register(&ops[2]);
+            let div = match pseudo.kind {
+                Div | Rem => I::Div,
+                _ => I::Divu,
+            };
+            out.push(div, [ops[1].clone(), rt]);
+            let result = match pseudo.kind {
+                Div | Divu => I::Mflo,
+                _ => I::Mfhi,
+            };
+            out.push(result, [ops[0].clone()]);
+        }
+        Seq | Sne => {
+            // the difference is zero when the values are equal
+            if fits_uimm16(&ops[2]) {
+                out.push(I::Xori, [ops[0].clone(), ops[1].clone(), ops[2].clone()]);
+            } else {
+                let rt = out.in_register(&ops[2]);
+                out.push(I::Subu, [ops[0].clone(), ops[1].clone(), rt]);
+            }
+            if pseudo.kind == Seq {
+                let one = derived(&ops[2], OperandKind::Immediate(Immediate::Number(1)));
+                out.push(I::Sltiu, [ops[0].clone(), ops[0].clone(), one]);
+            } else {
+                out.push(I::Sltu, [ops[0].clone(), zero, ops[0].clone()]);
+            }
+        }
+        Sge | Sgeu => {
+            let (slt, slti) = match pseudo.kind {
+                Sge => (I::Slt, I::Slti),
+                _ => (I::Sltu, I::Sltiu),
+            };
+            if fits_imm16(&ops[2]) {
+                out.push(slti, [ops[0].clone(), ops[1].clone(), ops[2].clone()]);
+            } else {
+                let rt = out.in_register(&ops[2]);
+                out.push(slt, [ops[0].clone(), ops[1].clone(), rt]);
+            }
+            let one = derived(&ops[2], OperandKind::Immediate(Immediate::Number(1)));
+            out.push(I::Xori, [ops[0].clone(), ops[0].clone(), one]);
+        }
+        Sgt | Sgtu => {
+            let slt = match pseudo.kind {
+                Sgt => I::Slt,
+                _ => I::Sltu,
+            };
+            let rt = out.in_register(&ops[2]);
+            out.push(slt, [ops[0].clone(), rt, ops[1].clone()]);
+        }
+        Ulw | Usw => {
+            // the bytes are in little endian order, the default of MARS and SPIM: `lwl` reads
+            // the most significant ones at the highest address
+            let Address { offset, base } = address_of(&ops[1].kind);
+            let base = base.unwrap_or(Register::Number(0));
+            let (base, offset) = match offset {
+                // `lwl` would overwrite the base before `lwr` reads it
+                Immediate::Number(num)
+                    if i16::try_from(num).is_ok()
+                        && i16::try_from(num + 3).is_ok()
+                        && !(pseudo.kind == Ulw && is_register(&ops[0], &base)) =>
+                {
+                    (base, num)
+                }
+                _ => {
+                    let at = out.at();
+                    out.address(at, &ops[1]);
+                    (Register::Name(RegisterName::At), 0)
+                }
+            };
+            let (left, right) = match pseudo.kind {
+                Ulw => (I::Lwl, I::Lwr),
+                _ => (I::Swl, I::Swr),
+            };
+            for (kind, offset) in [(left, offset + 3), (right, offset)] {
+                let address = derived(
+                    &ops[1],
+                    OperandKind::Address(Address {
+                        offset: Immediate::Number(offset),
+                        base: Some(base.clone()),
+                    }),
+                );
+                out.push(kind, [ops[0].clone(), address]);
+            }
+        }
+    }
+    if out.uses_at && !at_available {
+        return Err(ParserError::new(
+            ParserErrorKind::PseudoNeedsAt(pseudo.kind),
+            pseudo.span,
+        ));
+    }
+    Ok(out.instructions)
+}
+
+/// The machine instructions generated for a pseudo-instruction
+struct Expansion {
+    /// The span of the pseudo-instruction
+    span: Range<usize>,
+    instructions: Vec<Instruction>,
+    uses_at: bool,
+}
+
+impl Expansion {
+    fn push<const N: usize>(&mut self, kind: InstructionKind, operands: [Operand; N]) {
+        self.instructions.push(Instruction {
+            kind,
+            operands: operands.into(),
+            span: self.span.clone(),
+        });
+    }
+
+    /// Returns a register operand that is not written in the source
+    fn register(&self, reg: Register) -> Operand {
+        Operand {
+            kind: OperandKind::Register(reg),
+            span: self.span.clone(),
+        }
+    }
+
+    /// Returns `$at`, and records that the expansion needs it
+    fn at(&mut self) -> Operand {
+        self.uses_at = true;
+        self.register(Register::Name(RegisterName::At))
+    }
+
+    /// Loads an immediate in a register with the fewest instructions for its value
+    fn load(&mut self, dest: Operand, value: &Operand) {
+        let imm = |imm| derived(value, OperandKind::Immediate(imm));
+        let zero = self.register(Register::Number(0));
+        // `0xffffffff` and `-1` are the same word
+        match value.kind.number().map(|num| num as u32) {
+            Some(bits) if i16::try_from(bits as i32).is_ok() => {
+                self.push(
+                    InstructionKind::Addiu,
+                    [dest, zero, imm(Immediate::Number(bits as i32 as i64))],
+                );
+            }
+            Some(bits) if bits <= 0xffff => {
+                self.push(
+                    InstructionKind::Ori,
+                    [dest, zero, imm(Immediate::Number(bits as i64))],
+                );
+            }
+            Some(bits) => {
+                let (upper, lower) = ((bits >> 16) as i64, (bits & 0xffff) as i64);
+                self.push(
+                    InstructionKind::Lui,
+                    [dest.clone(), imm(Immediate::Number(upper))],
+                );
+                if lower != 0 {
+                    self.push(
+                        InstructionKind::Ori,
+                        [dest.clone(), dest, imm(Immediate::Number(lower))],
+                    );
+                }
+            }
+            None => {
+                let value = immediate_of(value);
+                self.push(
+                    InstructionKind::Lui,
+                    [dest.clone(), imm(Immediate::Hi(Box::new(value.clone())))],
+                );
+                self.push(
+                    InstructionKind::Addiu,
+                    [dest.clone(), dest, imm(Immediate::Lo(Box::new(value)))],
+                );
+            }
+        }
+    }
+
+    /// Computes the address of an operand accepted as an address in a register
+    fn address(&mut self, dest: Operand, source: &Operand) {
+        let Address { offset, base } = address_of(&source.kind);
+        let fits = fits_offset(&offset);
+        let offset = derived(source, OperandKind::Immediate(offset));
+        match base {
+            Some(base) if base.number() != 0 => {
+                let base = derived(source, OperandKind::Register(base));
+                if fits {
+                    self.push(InstructionKind::Addiu, [dest, base, offset]);
+                } else {
+                    let at = self.at();
+                    self.load(at.clone(), &offset);
+                    self.push(InstructionKind::Addu, [dest, at, base]);
+                }
+            }
+            _ => self.load(dest, &offset),
+        }
+    }
+
+    /// Returns the operand if it's a register, otherwise loads the immediate in `$at`
+    fn in_register(&mut self, operand: &Operand) -> Operand {
+        match operand.kind {
+            OperandKind::Register(_) => operand.clone(),
+            _ => {
+                let at = self.at();
+                self.load(at.clone(), operand);
+                at
+            }
+        }
+    }
+}
+
+/// Returns an operand generated from one of the source, which keeps its span
+fn derived(source: &Operand, kind: OperandKind) -> Operand {
+    Operand {
+        kind,
+        span: source.span.clone(),
+    }
+}
+
+/// Returns the value of an immediate operand
+fn immediate_of(operand: &Operand) -> Immediate {
+    match &operand.kind {
+        OperandKind::Immediate(imm) => imm.clone(),
+        _ => unreachable!("The operand should be an immediate"),
+    }
+}
+
+/// Returns true if the operand is a constant that fits in a sign extended 16 bit immediate
+fn fits_imm16(operand: &Operand) -> bool {
+    operand
+        .kind
+        .number()
+        .is_some_and(|num| i16::try_from(num).is_ok())
+}
+
+/// Returns true if the operand is a constant that fits in a zero extended 16 bit immediate
+fn fits_uimm16(operand: &Operand) -> bool {
+    operand
+        .kind
+        .number()
+        .is_some_and(|num| u16::try_from(num).is_ok())
+}
+
+/// Returns true if the operand is the register `reg`
+fn is_register(operand: &Operand, reg: &Register) -> bool {
+    matches!(&operand.kind, OperandKind::Register(r) if r.number() == reg.number())
+}
+
+/// Returns the operand accepted as an address in the `offset($base)` form, without a base if
+/// it's a label or a constant alone
+fn address_of(operand: &OperandKind) -> Address {
+    match operand {
+        OperandKind::Address(address) => address.clone(),
+        OperandKind::Label(label) => Address {
+            offset: Immediate::Label(label.clone(), 0),
+            base: None,
+        },
+        OperandKind::Immediate(offset) => Address {
+            offset: offset.clone(),
+            base: None,
+        },
+        _ => unreachable!("The operand should be accepted as an address"),
+    }
+}
+
+/// Returns true if the value is known to fit in the signed 16 bit offset of an address
+fn fits_offset(offset: &Immediate) -> bool {
+    match offset {
+        Immediate::Number(num) => i16::try_from(*num).is_ok(),
+        Immediate::Lo(_) | Immediate::GpRel(_) => true,
+        Immediate::Label(..) | Immediate::Expr(_) | Immediate::Hi(_) => false,
+    }
+}
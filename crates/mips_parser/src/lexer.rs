@@ -1,41 +1,146 @@
+use crate::defs::dialect::Dialect;
 use crate::defs::directive::Directive;
 use crate::defs::instruction::InstructionKind;
+use crate::defs::program::RelocationKind;
+use crate::defs::pseudo::PseudoKind;
 use crate::defs::register::Register;
 use crate::defs::token::{Token, TokenKind};
 use crate::errors::{LexerError, LexerErrorKind};
+use crate::source::{FileId, SourceDb};
+use std::borrow::Cow;
 use std::num::IntErrorKind;
+use std::ops::Range;
+use strum::IntoEnumIterator;
 
+mod stream;
 #[cfg(test)]
 mod tests;
 
+pub(crate) use stream::{include_path, TokenStream};
+
 #[derive(Debug)]
 pub(crate) struct Lexer<'a> {
     pos: usize,
+    /// The input, the text of the tokens is borrowed from it
+    text: &'a str,
     input: &'a [u8],
+    /// The number of errors after which lexing stops
+    max_errors: usize,
+    /// True if no token of the current statement has been read yet, labels excluded. Only the
+    /// first word of a statement can be a mnemonic, so that a label can be named like one.
+    statement_start: bool,
+    /// True in the operands of `.float` and `.double`, the only place where `inf` and `nan`
+    /// are numbers rather than names
+    float_operands: bool,
+    /// True once the end of file has been returned
+    finished: bool,
+    /// True if the comments are returned as tokens rather than skipped
+    trivia: bool,
+    dialect: Dialect,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
         Lexer {
             pos: 0,
+            text: input,
             input: input.as_bytes(),
+            max_errors: usize::MAX,
+            statement_start: true,
+            float_operands: false,
+            finished: false,
+            trivia: false,
+            dialect: Dialect::default(),
+        }
+    }
+
+    /// Creates a lexer for a file of the database, the spans of the tokens point into the
+    /// database rather than the file
+    pub fn for_file(sources: &'a SourceDb, file: FileId) -> Self {
+        let span = sources.span(file);
+        let mut lexer = Lexer::new(&sources.text()[..span.end]);
+        lexer.pos = span.start;
+        lexer
+    }
+
+    /// Sets the dialect the input is written in
+    pub fn with_dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
+    /// Returns the comments as [`TokenKind::Comment`] tokens instead of skipping them, so that
+    /// the spans of the tokens cover the whole input
+    pub fn with_trivia(mut self) -> Self {
+        self.trivia = true;
+        self
+    }
+
+    /// Sets the number of errors after which the lexer stops
+    pub fn with_max_errors(mut self, max_errors: usize) -> Self {
+        self.max_errors = max_errors;
+        self
+    }
+
+    /// returns a Vec of all the tokens from the input, or all the errors that were found
+    pub fn lex(&mut self) -> Result<Vec<Token<'a>>, Vec<LexerError>> {
+        let (tokens, errors) = self.lex_recovering();
+        if errors.is_empty() {
+            Ok(tokens)
+        } else {
+            Err(errors)
         }
     }
 
-    /// returns a Vec of all the tokens from the input
-    pub fn lex(&mut self) -> Result<Vec<Token>, LexerError> {
+    /// Returns all the tokens from the input along with all the errors that were found.
+    /// When there is an error the tokens of its line are dropped and lexing resumes from the
+    /// next newline, so that the following lines can still be parsed.
+    pub fn lex_recovering(&mut self) -> (Vec<Token<'a>>, Vec<LexerError>) {
         let mut res = Vec::new();
-        loop {
-            let tok = self.next_token()?;
-            if tok.kind == TokenKind::Eof {
-                res.push(tok);
-                return Ok(res);
+        let mut errors = Vec::new();
+        while let Some(tok) = self.next() {
+            match tok {
+                Ok(tok) => res.push(tok),
+                Err(err) => {
+                    errors.push(err);
+                    if errors.len() >= self.max_errors {
+                        break;
+                    }
+                    while res.last().is_some_and(|t| t.kind != TokenKind::Newline) {
+                        res.pop();
+                    }
+                }
+            }
+        }
+        (res, errors)
+    }
+
+    fn next_token(&mut self) -> Result<Token<'a>, LexerError> {
+        let tok = self.read_token()?;
+        match tok.kind {
+            TokenKind::Newline => {
+                self.statement_start = true;
+                self.float_operands = false;
             }
-            res.push(tok)
+            // a block comment over several lines ends the statement
+            TokenKind::Comment if self.text[tok.span.clone()].contains('\n') => {
+                self.statement_start = true;
+                self.float_operands = false;
+            }
+            TokenKind::Comment => {}
+            // a label definition doesn't end the start of the statement
+            TokenKind::Whitespace | TokenKind::Colon => {}
+            TokenKind::Ident(_) | TokenKind::LocalLabel(_) if self.peek() == Some(&b':') => {}
+            TokenKind::Directive(Directive::Float | Directive::Double) => {
+                self.statement_start = false;
+                self.float_operands = true;
+            }
+            _ => self.statement_start = false,
         }
+        Ok(tok)
     }
 
-    fn next_token(&mut self) -> Result<Token, LexerError> {
+    fn read_token(&mut self) -> Result<Token<'a>, LexerError> {
         // skip whitespace and return newline token if needed
         if let Some(tok) = self.skip_whitespace() {
             return Ok(tok);
@@ -43,8 +148,22 @@ impl<'a> Lexer<'a> {
         let curr = match self.peek() {
             // if the char is an # skip until the end of the line and analyze the next token
             Some(b'#') => {
-                self.skip_comment();
-                return self.next_token();
+                let start = self.pos;
+                self.skip_line();
+                if self.trivia {
+                    return Ok(Token::new(TokenKind::Comment, start..self.pos));
+                }
+                return self.read_token();
+            }
+            Some(b'/')
+                if self.dialect.c_comments() && self.input.get(self.pos + 1) == Some(&b'*') =>
+            {
+                return self.read_block_comment();
+            }
+            // statements on the same line are separated by `;`
+            Some(b';') if self.dialect.c_comments() => {
+                self.read_next();
+                return Ok(Token::new(TokenKind::Newline, self.pos - 1..self.pos));
             }
             Some(c) => c,
             None => {
@@ -55,19 +174,31 @@ impl<'a> Lexer<'a> {
             b'(' => TokenKind::LParen,
             b')' => TokenKind::RParen,
             b'"' => return self.read_string(),
-            b'\'' => TokenKind::SingleQuote,
+            b'\'' => return self.read_char(),
             b'+' => TokenKind::Plus,
             b'-' => TokenKind::Minus,
             b',' => TokenKind::Comma,
             b':' => TokenKind::Colon,
+            b'*' => TokenKind::Star,
+            b'/' => TokenKind::Slash,
+            b'%' => return Ok(self.read_percent()),
+            b'&' => TokenKind::Ampersand,
+            b'|' => TokenKind::Pipe,
+            b'^' => TokenKind::Caret,
+            b'~' => TokenKind::Tilde,
+            b'<' | b'>' => return self.read_shift(),
+            // a number like `.5`
+            b'.' if self.input.get(self.pos + 1).is_some_and(u8::is_ascii_digit) => {
+                return self.read_number()
+            }
             b'.' => {
-                if let Ok(tok) = self.read_directive() {
-                    return Ok(tok);
+                if let Some(tok) = self.read_directive() {
+                    return tok;
                 } else {
                     TokenKind::Dot
                 }
             }
-            b'a'..=b'z' | b'A'..=b'Z' => return self.read_ident_or_instruction(),
+            b'a'..=b'z' | b'A'..=b'Z' | b'_' => return self.read_ident_or_instruction(),
             b'0'..=b'9' => return self.read_number(),
             b'$' => return self.read_register(),
             c if !c.is_ascii() => {
@@ -94,13 +225,13 @@ impl<'a> Lexer<'a> {
             }
         };
         self.read_next();
-        Ok(Token { kind, span: 0..1 })
+        Ok(Token::new(kind, self.pos - 1..self.pos))
     }
 
     /// Increments the position until the next character to be read is not whitespace.
     /// If it finds a newline returns [`TokenKind::Newline`], if there is white space returns
     /// [`TokenKind::Whitespace`]. The next character will be a non-whitespace token.
-    fn skip_whitespace(&mut self) -> Option<Token> {
+    fn skip_whitespace(&mut self) -> Option<Token<'a>> {
         let start = self.pos;
         let mut found_whitespace = false;
         while let Some(curr) = self.peek() {
@@ -120,11 +251,11 @@ impl<'a> Lexer<'a> {
         None
     }
 
-    /// Increments the position until a newline
-    fn skip_comment(&mut self) {
+    /// Increments the position until a newline, which is left to be read next.
+    /// Used to skip comments and lines with errors.
+    fn skip_line(&mut self) {
         while let Some(curr) = self.peek() {
             if *curr == 0xA {
-                self.read_next();
                 return;
             }
             self.read_next();
@@ -152,29 +283,34 @@ impl<'a> Lexer<'a> {
 
     /// Reads a string, stops when a non escaped closing quote is found, returns an error if the
     /// closing delimiter doesn't exist
-    fn read_string(&mut self) -> Result<Token, LexerError> {
+    fn read_string(&mut self) -> Result<Token<'a>, LexerError> {
         let start = self.pos;
-        let mut string = String::new();
-        let mut escaped = false;
         // skip the " that starts the string
         assert_eq!(self.read_next(), Some(b'"'));
+        // the string is only copied once an escape sequence is found
+        let mut escaped: Option<Vec<u8>> = None;
         while let Some(c) = self.peek() {
-            if escaped {
-                escaped = false;
-                //TODO: special chars like \n
-                string.push(*c as char);
-                self.read_next();
-                continue;
-            } else if *c == b'"' {
-                break;
-            } else if *c == b'\\' {
-                escaped = true;
-                self.read_next();
-                continue;
+            match *c {
+                b'"' => break,
+                // a backslash at the end of the input means the string is not closed
+                b'\\' if self.pos + 1 == self.input.len() => {
+                    self.read_next();
+                }
+                b'\\' => {
+                    let string =
+                        escaped.get_or_insert_with(|| self.input[start + 1..self.pos].to_vec());
+                    let c = self.read_escape()?;
+                    string.push(c);
+                }
+                c => {
+                    if let Some(string) = &mut escaped {
+                        string.push(c);
+                    }
+                    self.read_next();
+                }
             }
-            string.push(*c as char);
-            self.read_next();
         }
+        let end = self.pos;
         // Next char should be '"' because the next token will be a closing quote,
         // if it's not the string is not closed and it's an error
         if self.read_next() != Some(b'"') {
@@ -183,71 +319,306 @@ impl<'a> Lexer<'a> {
                 start..self.pos,
             ));
         }
+        let string = match escaped {
+            Some(string) => Cow::Owned(string),
+            None => Cow::Borrowed(&self.input[start + 1..end]),
+        };
         Ok(Token::new(TokenKind::String(string), start..self.pos))
     }
 
-    /// Reads an ident, keeps going until an ascii whitespace character is found
-    fn read_ident_or_instruction(&mut self) -> Result<Token, LexerError> {
-        let mut string = String::new();
+    /// Reads a character literal like `'a'` or `'\n'`, which must contain exactly one character
+    fn read_char(&mut self) -> Result<Token<'a>, LexerError> {
+        let start = self.pos;
+        // skip the ' that starts the literal
+        assert_eq!(self.read_next(), Some(b'\''));
+        let c = match self.peek() {
+            Some(b'\\') => self.read_escape()?,
+            Some(b'\n') | None => {
+                return Err(LexerError::new(
+                    LexerErrorKind::ExpectedCharEnd,
+                    start..self.pos,
+                ))
+            }
+            Some(c) => {
+                let c = *c;
+                self.read_next();
+                c
+            }
+        };
+        if c == b'\'' && self.pos == start + 2 {
+            return Err(LexerError::new(
+                LexerErrorKind::InvalidCharLiteral,
+                start..self.pos,
+            ));
+        }
+        if self.peek() == Some(&b'\'') {
+            self.read_next();
+            return Ok(Token::new(TokenKind::Char(c), start..self.pos));
+        }
+        // if the literal is closed later on the line it has more than one character
+        let rest = &self.input[self.pos..];
+        let line = &rest[..rest.iter().position(|c| *c == b'\n').unwrap_or(rest.len())];
+        match line.iter().position(|c| *c == b'\'') {
+            Some(i) => {
+                self.pos += i + 1;
+                Err(LexerError::new(
+                    LexerErrorKind::InvalidCharLiteral,
+                    start..self.pos,
+                ))
+            }
+            None => Err(LexerError::new(
+                LexerErrorKind::ExpectedCharEnd,
+                start..self.pos,
+            )),
+        }
+    }
+
+    /// Reads an escape sequence starting with a backslash and returns the byte it stands for.
+    /// Supports the C escapes, `\xNN` with one or two hex digits and octal escapes with up to
+    /// three digits.
+    fn read_escape(&mut self) -> Result<u8, LexerError> {
+        let start = self.pos;
+        assert_eq!(self.read_next(), Some(b'\\'));
+        let byte = match self.read_next() {
+            Some(b'n') => Some(b'\n'),
+            Some(b't') => Some(b'\t'),
+            Some(b'r') => Some(b'\r'),
+            Some(b'a') => Some(0x07),
+            Some(b'b') => Some(0x08),
+            Some(b'f') => Some(0x0c),
+            Some(b'v') => Some(0x0b),
+            Some(c @ (b'\\' | b'"' | b'\'')) => Some(c),
+            Some(b'x') => self.read_escape_number(2, 16),
+            Some(b'0'..=b'7') => {
+                self.pos -= 1;
+                self.read_escape_number(3, 8)
+            }
+            // a non ascii char is not split, so that the span is still valid utf-8
+            Some(c) if !c.is_ascii() => {
+                while self.peek().is_some_and(|c| *c & 0xc0 == 0x80) {
+                    self.read_next();
+                }
+                None
+            }
+            _ => None,
+        };
+        byte.ok_or_else(|| LexerError::new(LexerErrorKind::InvalidEscape, start..self.pos))
+    }
+
+    /// Reads a number of at most `max` digits in the given radix, returns `None` if there are
+    /// no digits or the number doesn't fit in a byte
+    fn read_escape_number(&mut self, max: usize, radix: u32) -> Option<u8> {
+        let start = self.pos;
+        let mut value = 0u32;
+        while self.pos - start < max {
+            let Some(digit) = self.peek().and_then(|c| (*c as char).to_digit(radix)) else {
+                break;
+            };
+            value = value * radix + digit;
+            self.read_next();
+        }
+        if self.pos == start {
+            return None;
+        }
+        u8::try_from(value).ok()
+    }
+
+    /// Reads a shift operator, `<<` or `>>`
+    fn read_shift(&mut self) -> Result<Token<'a>, LexerError> {
+        let start = self.pos;
+        let c = self
+            .read_next()
+            .expect("The shift operator should be there");
+        if self.peek() != Some(&c) {
+            return Err(LexerError::new(
+                LexerErrorKind::InvalidToken(c as char),
+                start..self.pos,
+            ));
+        }
+        self.read_next();
+        let kind = if c == b'<' {
+            TokenKind::Shl
+        } else {
+            TokenKind::Shr
+        };
+        Ok(Token::new(kind, start..self.pos))
+    }
+
+    /// Reads a relocation operator like `%hi(`, leaving the parenthesis to be read next, or
+    /// the remainder operator
+    fn read_percent(&mut self) -> Token<'a> {
+        let start = self.pos;
+        self.read_next();
+        let end = self.input[self.pos..]
+            .iter()
+            .position(|c| !(c.is_ascii_alphanumeric() || *c == b'_'))
+            .map_or(self.input.len(), |len| self.pos + len);
+        match self.text[self.pos..end].parse::<RelocationKind>() {
+            Ok(kind) if self.input.get(end) == Some(&b'(') => {
+                self.pos = end;
+                Token::new(TokenKind::Relocation(kind), start..end)
+            }
+            _ => Token::new(TokenKind::Percent, start..self.pos),
+        }
+    }
+
+    /// Reads an identifier, which starts with a letter or `_` and can contain letters, digits,
+    /// `_`, `.` and `$`. The first word of a statement is an instruction if it's a known
+    /// mnemonic, like `add` or `cvt.w.d`, unless it's a label definition. A pseudo-instruction
+    /// of another dialect is an error.
+    fn read_ident_or_instruction(&mut self) -> Result<Token<'a>, LexerError> {
         let start = self.pos;
         while let Some(c) = self.peek() {
-            if !c.is_ascii_alphanumeric() {
+            if !(c.is_ascii_alphanumeric() || matches!(c, b'_' | b'.' | b'$')) {
                 break;
             }
-            string.push(*c as char);
             self.read_next();
         }
         let span = start..self.pos;
+        let string = &self.text[span.clone()];
+        if let Some(num) = special_float(string).filter(|_| self.float_operands) {
+            return Ok(Token::new(TokenKind::Float(num.to_bits()), span));
+        }
+        if !self.statement_start || self.peek() == Some(&b':') {
+            return Ok(Token::new(TokenKind::Ident(string), span));
+        }
         // try parsing the string as an instruction, if invalid return as ident
         if let Ok(instruction) = string.parse::<InstructionKind>() {
             Ok(Token::new(TokenKind::Instruction(instruction), span))
+        } else if let Ok(pseudo) = string.parse::<PseudoKind>() {
+            if !self.dialect.supports_pseudo(pseudo) {
+                return Err(LexerError::new(
+                    LexerErrorKind::NotInDialect {
+                        name: pseudo.to_string(),
+                        dialect: self.dialect,
+                    },
+                    span,
+                ));
+            }
+            Ok(Token::new(TokenKind::Pseudo(pseudo), span))
         } else {
             Ok(Token::new(TokenKind::Ident(string), span))
         }
     }
 
-    fn read_directive(&mut self) -> Result<Token, ()> {
-        let mut string = String::new();
+    /// Reads a directive, returns `None` if the word after the `.` is not a directive, or an
+    /// error if the directive doesn't exist in the dialect
+    fn read_directive(&mut self) -> Option<Result<Token<'a>, LexerError>> {
         let start = self.pos;
         // skip the `.`
         assert_eq!(self.read_next(), Some(b'.'));
         while let Some(c) = self.peek() {
-            if !c.is_ascii_alphanumeric() {
+            if !(c.is_ascii_alphanumeric() || *c == b'_') {
                 break;
             }
-            string.push(*c as char);
             self.read_next();
         }
-        // try parsing the string as a directive, if invalid reset the position and return error
-        if let Ok(directive) = string.parse::<Directive>() {
-            Ok(Token::new(TokenKind::Directive(directive), start..self.pos))
-        } else {
+        let span = start..self.pos;
+        // try parsing the string as a directive, if invalid reset the position
+        let Ok(directive) = self.text[start + 1..self.pos].parse::<Directive>() else {
             self.pos = start;
-            Err(())
+            return None;
+        };
+        if !self.dialect.supports_directive(directive) {
+            return Some(Err(LexerError::new(
+                LexerErrorKind::NotInDialect {
+                    name: format!(".{directive}"),
+                    dialect: self.dialect,
+                },
+                span,
+            )));
+        }
+        Some(Ok(Token::new(TokenKind::Directive(directive), span)))
+    }
+
+    /// Reads a `/* */` comment, which is whitespace unless it spans more than one line, in
+    /// which case it ends the statement like a newline. When the trivia is kept it's a comment
+    /// either way.
+    fn read_block_comment(&mut self) -> Result<Token<'a>, LexerError> {
+        let start = self.pos;
+        let Some(len) = self.text[start + 2..].find("*/") else {
+            self.pos = self.input.len();
+            return Err(LexerError::new(
+                LexerErrorKind::ExpectedCommentEnd,
+                start..start + 2,
+            ));
+        };
+        self.pos = start + 2 + len + 2;
+        let span = start..self.pos;
+        if self.trivia {
+            Ok(Token::new(TokenKind::Comment, span))
+        } else if self.text[span.clone()].contains('\n') {
+            Ok(Token::new(TokenKind::Newline, span))
+        } else {
+            Ok(Token::new(TokenKind::Whitespace, span))
         }
     }
 
-    fn read_number(&mut self) -> Result<Token, LexerError> {
+    /// Reads an integer or a floating point number like `3.14` or `1e-5`
+    fn read_number(&mut self) -> Result<Token<'a>, LexerError> {
         let start = self.pos;
-        let mut string = String::new();
+        let is_decimal = !matches!(
+            self.input.get(self.pos..self.pos + 2),
+            Some(b"0x" | b"0b" | b"0o")
+        );
+        self.read_digits(start);
+        let mut is_float = false;
+        if is_decimal {
+            if self.peek() == Some(&b'.')
+                && self.input.get(self.pos + 1).is_some_and(u8::is_ascii_digit)
+            {
+                is_float = true;
+                self.read_next();
+                self.read_digits(start);
+            }
+            if matches!(self.peek(), Some(b'e' | b'E')) {
+                is_float = true;
+                self.read_next();
+                if let Some(b'+' | b'-') = self.peek() {
+                    self.read_next();
+                }
+                self.read_digits(start);
+            }
+        }
+        // keep reading the letters so that a number like `12ab` is an error
         while let Some(c) = self.peek() {
             if !c.is_ascii_alphanumeric() {
                 break;
             }
-            string.push(*c as char);
             self.read_next();
         }
+        let span = start..self.pos;
+        let string = &self.text[span.clone()];
+        if is_float {
+            return match string.parse::<f64>() {
+                Ok(num) if num.is_infinite() => {
+                    Err(LexerError::new(LexerErrorKind::NumberOutOfRange, span))
+                }
+                Ok(num) => Ok(Token::new(TokenKind::Float(num.to_bits()), span)),
+                Err(_) => Err(LexerError::new(LexerErrorKind::NumberParseError, span)),
+            };
+        }
+        // `0b` alone is a reference to the label `0`, not a binary number
+        if is_decimal || string.len() == 2 {
+            if let Some(tok) = self.read_local_label(span.clone()) {
+                return tok;
+            }
+        }
         // parse the number with the correct radia based on the prefix
-        let res = if string.starts_with("0x") {
-            i16::from_str_radix(string.strip_prefix("0x").unwrap(), 16)
-        } else if string.starts_with("0b") {
-            i16::from_str_radix(string.strip_prefix("0b").unwrap(), 2)
-        } else if string.starts_with("0o") {
-            i16::from_str_radix(string.strip_prefix("0o").unwrap(), 8)
+        let res = if let Some(digits) = string.strip_prefix("0x") {
+            i64::from_str_radix(digits, 16)
+        } else if let Some(digits) = string.strip_prefix("0b") {
+            i64::from_str_radix(digits, 2)
+        } else if let Some(digits) = string.strip_prefix("0o") {
+            i64::from_str_radix(digits, 8)
+        } else if let Some(digits) = string
+            .strip_prefix('0')
+            .filter(|d| !d.is_empty() && self.dialect.leading_zero_octal())
+        {
+            i64::from_str_radix(digits, 8)
         } else {
-            string.parse::<i16>()
+            string.parse::<i64>()
         };
-        let span = start..self.pos;
         match res {
             Ok(num) => Ok(Token::new(TokenKind::Number(num), span)),
             Err(err) => match err.kind() {
@@ -259,23 +630,70 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    /// Reads a numeric local label, either a definition like `1:` at the start of a statement
+    /// or a reference like `1b` or `1f`. Returns `None` if the number is not one.
+    fn read_local_label(&self, span: Range<usize>) -> Option<Result<Token<'a>, LexerError>> {
+        let string = &self.text[span.clone()];
+        let (digits, kind) = match string.as_bytes()[string.len() - 1] {
+            b'b' => (&string[..string.len() - 1], Some(false)),
+            b'f' => (&string[..string.len() - 1], Some(true)),
+            _ if self.statement_start && self.peek() == Some(&b':') => (string, None),
+            _ => return None,
+        };
+        if digits.is_empty() || !digits.bytes().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let Ok(number) = digits.parse::<u32>() else {
+            return Some(Err(LexerError::new(LexerErrorKind::NumberOutOfRange, span)));
+        };
+        let kind = match kind {
+            Some(forward) => TokenKind::LocalRef { number, forward },
+            None => TokenKind::LocalLabel(number),
+        };
+        Some(Ok(Token::new(kind, span)))
+    }
+
+    /// Reads the digits of a decimal number, along with the prefix of a number in another base
+    /// if the number starting at `start` is just a zero
+    fn read_digits(&mut self, start: usize) {
+        while let Some(c) = self.peek() {
+            let is_prefix = &self.input[start..self.pos] == b"0" && matches!(c, b'x' | b'b' | b'o');
+            if !c.is_ascii_digit() && !is_prefix {
+                break;
+            }
+            self.read_next();
+        }
+    }
+
     /// Reads a register starting from a dollar sign, returning a [`Token::Register`], containing the representation of the
     /// register following the $
-    fn read_register(&mut self) -> Result<Token, LexerError> {
+    fn read_register(&mut self) -> Result<Token<'a>, LexerError> {
         let start = self.pos;
-        let mut chars = Vec::new();
         // skip the $ itself
         assert_eq!(self.read_next(), Some(b'$'));
         while let Some(c) = self.peek() {
             if !c.is_ascii_alphanumeric() {
                 break;
             }
-            chars.push(*c as char);
             self.read_next();
         }
         let span = start..self.pos;
-        let register = match Register::try_from(chars.as_slice()) {
+        let name = &self.text[start + 1..self.pos];
+        if let Some(reg) = self.dialect.register_alias(name) {
+            return Ok(Token::new(TokenKind::Register(reg), span));
+        }
+        let register = match Register::try_from(name) {
             Ok(reg) => reg,
+            // the name of a register in another dialect
+            Err(_) if Dialect::iter().any(|d| d.register_alias(name).is_some()) => {
+                return Err(LexerError::new(
+                    LexerErrorKind::NotInDialect {
+                        name: format!("${name}"),
+                        dialect: self.dialect,
+                    },
+                    span,
+                ))
+            }
             Err(err) => {
                 return Err(LexerError {
                     kind: err.into(),
@@ -286,3 +704,35 @@ impl<'a> Lexer<'a> {
         Ok(Token::new(TokenKind::Register(register), span))
     }
 }
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<Token<'a>, LexerError>;
+
+    /// Reads the tokens lazily, the last one is the end of file. After an error the rest of
+    /// the line is skipped, so the next token is the newline that ends it.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let res = self.next_token();
+        match &res {
+            Ok(tok) if tok.kind == TokenKind::Eof => self.finished = true,
+            Err(_) => self.skip_line(),
+            Ok(_) => {}
+        }
+        Some(res)
+    }
+}
+
+/// Returns the value of the names of the special floating point values, `inf`, `infinity` and
+/// `nan` in any case
+fn special_float(name: &str) -> Option<f64> {
+    let is = |special: &str| name.eq_ignore_ascii_case(special);
+    if is("inf") || is("infinity") {
+        Some(f64::INFINITY)
+    } else if is("nan") {
+        Some(f64::NAN)
+    } else {
+        None
+    }
+}
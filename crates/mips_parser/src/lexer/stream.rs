@@ -0,0 +1,169 @@
+use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
+
+use crate::defs::dialect::Dialect;
+use crate::defs::directive::Directive;
+use crate::defs::token::{Token, TokenKind};
+use crate::errors::{ParserError, ParserErrorKind, SourceError};
+use crate::source::{FileId, SourceDb};
+
+use super::Lexer;
+
+/// Reads the tokens of a file lazily, replacing the `.include` directives with the tokens of
+/// the files they include.
+///
+/// The errors of the lexer are collected, and the text that couldn't be read is replaced by a
+/// [`TokenKind::Error`] token, so that the parser can skip the statement.
+pub(crate) struct TokenStream<'a> {
+    sources: &'a SourceDb,
+    /// The lexers of the files being read, the last one is the innermost included file
+    lexers: Vec<(FileId, Lexer<'a>)>,
+    /// The file each `.include` points to, by the start of the span of its path. The files
+    /// must be added to the database before reading the tokens.
+    includes: HashMap<usize, FileId>,
+    /// Tokens that were read ahead while looking at an `.include`
+    pending: VecDeque<Token<'a>>,
+    /// The tokens that end the lines of the `.include` being read, returned once the included
+    /// files end
+    line_ends: Vec<Token<'a>>,
+    errors: Vec<SourceError>,
+    /// The number of errors after which the stream ends
+    max_errors: usize,
+    dialect: Dialect,
+}
+
+impl<'a> TokenStream<'a> {
+    pub fn new(sources: &'a SourceDb, file: FileId, includes: HashMap<usize, FileId>) -> Self {
+        Self {
+            sources,
+            lexers: vec![(file, Lexer::for_file(sources, file))],
+            includes,
+            pending: VecDeque::new(),
+            line_ends: Vec::new(),
+            errors: Vec::new(),
+            max_errors: usize::MAX,
+            dialect: Dialect::default(),
+        }
+    }
+
+    /// Sets the dialect of the files, the stream must not have been read yet
+    pub fn with_dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self.lexers = self
+            .lexers
+            .into_iter()
+            .map(|(file, lexer)| (file, lexer.with_dialect(dialect)))
+            .collect();
+        self
+    }
+
+    /// Sets the number of errors after which the stream ends
+    pub fn with_max_errors(mut self, max_errors: usize) -> Self {
+        self.max_errors = max_errors;
+        self
+    }
+
+    /// Returns the errors found while reading the tokens
+    pub fn into_errors(self) -> Vec<SourceError> {
+        self.errors
+    }
+
+    /// Reads the next token of the innermost file
+    fn read(&mut self) -> Option<Token<'a>> {
+        let (_, lexer) = self.lexers.last_mut()?;
+        match lexer.next()? {
+            Ok(tok) => Some(tok),
+            Err(err) => {
+                let span = err.span.clone();
+                self.errors.push(err.into());
+                if self.errors.len() >= self.max_errors {
+                    self.lexers.clear();
+                    return Some(Token::new(TokenKind::Eof, span));
+                }
+                Some(Token::new(TokenKind::Error, span))
+            }
+        }
+    }
+
+    /// Starts reading the file included by the `.include` with the path at `span`, returns
+    /// false if the file can't be included
+    fn include(&mut self, span: Range<usize>) -> bool {
+        // the files that can't be read were already reported
+        let Some(file) = self.includes.get(&span.start).copied() else {
+            return false;
+        };
+        if self.lexers.iter().any(|(f, _)| *f == file) {
+            let path = String::from_utf8_lossy(&self.sources.text().as_bytes()[span.clone()]);
+            let err = ParserError::new(
+                ParserErrorKind::RecursiveInclude(path.trim_matches('"').to_owned()),
+                span,
+            );
+            self.errors.push(err.into());
+            return false;
+        }
+        self.lexers
+            .push((file, Lexer::for_file(self.sources, file)));
+        true
+    }
+}
+
+impl<'a> Iterator for TokenStream<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let tok = match self.pending.pop_front() {
+                Some(tok) => tok,
+                None => self.read()?,
+            };
+            match tok.kind {
+                // the end of file is the one of the outermost file
+                TokenKind::Eof if self.lexers.len() > 1 => {
+                    self.lexers.pop();
+                    if let Some(end) = self.line_ends.pop() {
+                        return Some(end);
+                    }
+                }
+                TokenKind::Directive(Directive::Include) => {
+                    let mut line = vec![tok];
+                    let mut end = None;
+                    while let Some(tok) = self.read() {
+                        if matches!(tok.kind, TokenKind::Newline | TokenKind::Eof) {
+                            end = Some(tok);
+                            break;
+                        }
+                        line.push(tok);
+                    }
+                    match include_path(&line).map(|(_, span)| span) {
+                        Some(span) if self.include(span.clone()) => self.line_ends.extend(end),
+                        Some(_) => self.pending.extend(end),
+                        // the parser reports the wrong operands
+                        None => {
+                            let mut line = line.into_iter();
+                            let first = line.next();
+                            self.pending.extend(line.chain(end));
+                            return first;
+                        }
+                    }
+                }
+                _ => return Some(tok),
+            }
+        }
+    }
+}
+
+/// Returns the path of an `.include` along with its span, if the line made of the directive and
+/// its operands is valid
+pub(crate) fn include_path<'t>(line: &'t [Token]) -> Option<(&'t [u8], Range<usize>)> {
+    let mut operands = line[1..].iter().filter(|t| t.kind != TokenKind::Whitespace);
+    match (operands.next(), operands.next()) {
+        (
+            Some(Token {
+                kind: TokenKind::String(path),
+                span,
+            }),
+            None,
+        ) => Some((path, span.clone())),
+        _ => None,
+    }
+}
@@ -1,6 +1,8 @@
+use crate::defs::dialect::Dialect;
 use crate::defs::directive::Directive;
 use crate::defs::register::RegisterParseError;
 use crate::defs::register::{RegisterName, RegisterPrefixedName};
+use crate::source::SourceDb;
 
 use super::*;
 #[test]
@@ -17,14 +19,14 @@ syscall
     let tokens = [
         TokenKind::Directive(Directive::Data),
         TokenKind::Newline,
-        TokenKind::Ident("x".into()),
+        TokenKind::Ident("x"),
         TokenKind::Colon,
         TokenKind::Whitespace,
         TokenKind::Directive(Directive::Word),
         TokenKind::Whitespace,
         TokenKind::Number(7),
         TokenKind::Newline,
-        TokenKind::Ident("y".into()),
+        TokenKind::Ident("y"),
         TokenKind::Colon,
         TokenKind::Whitespace,
         TokenKind::Directive(Directive::Word),
@@ -33,23 +35,23 @@ syscall
         TokenKind::Newline,
         TokenKind::Directive(Directive::Text),
         TokenKind::Newline,
-        TokenKind::Ident("la".into()),
+        TokenKind::Pseudo(PseudoKind::La),
         TokenKind::Whitespace,
         TokenKind::Register(Register::PrefixedNumber(
             RegisterPrefixedName::new_unchecked('s', 0),
         )),
         TokenKind::Whitespace,
-        TokenKind::Ident("x".into()),
+        TokenKind::Ident("x"),
         TokenKind::Newline,
-        TokenKind::Ident("la".into()),
+        TokenKind::Pseudo(PseudoKind::La),
         TokenKind::Whitespace,
         TokenKind::Register(Register::PrefixedNumber(
             RegisterPrefixedName::new_unchecked('a', 0),
         )),
         TokenKind::Whitespace,
-        TokenKind::Ident("mylabel".into()),
+        TokenKind::Ident("mylabel"),
         TokenKind::Newline,
-        TokenKind::Ident("li".into()),
+        TokenKind::Pseudo(PseudoKind::Li),
         TokenKind::Whitespace,
         TokenKind::Register(Register::PrefixedNumber(
             RegisterPrefixedName::new_unchecked('v', 0),
@@ -74,17 +76,17 @@ out "inside \" escaped"
 double "inside\"some\"double"
 "#;
     let tokens = [
-        TokenKind::Ident("data".into()),
+        TokenKind::Ident("data"),
         TokenKind::Whitespace,
-        TokenKind::String("inside string".into()),
+        TokenKind::String(Cow::Borrowed(b"inside string")),
         TokenKind::Newline,
-        TokenKind::Ident("out".into()),
+        TokenKind::Ident("out"),
         TokenKind::Whitespace,
-        TokenKind::String("inside \" escaped".into()),
+        TokenKind::String(Cow::Borrowed(b"inside \" escaped")),
         TokenKind::Newline,
-        TokenKind::Ident("double".into()),
+        TokenKind::Ident("double"),
         TokenKind::Whitespace,
-        TokenKind::String("inside\"some\"double".into()),
+        TokenKind::String(Cow::Borrowed(b"inside\"some\"double")),
         TokenKind::Newline,
     ];
     let mut lexer = Lexer::new(input);
@@ -92,6 +94,16 @@ double "inside\"some\"double"
         assert_eq!(lexer.next_token().unwrap().kind, res);
     }
     assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Eof);
+    // only the strings with escapes are copied
+    let mut lexer = Lexer::new(r#""plain" "esc\n""#);
+    let tok = lexer.next_token().unwrap();
+    assert!(matches!(
+        tok.kind,
+        TokenKind::String(Cow::Borrowed(b"plain"))
+    ));
+    lexer.next_token().unwrap();
+    let tok = lexer.next_token().unwrap();
+    assert!(matches!(tok.kind, TokenKind::String(Cow::Owned(_))));
     let mut lexer = Lexer::new("\"Open string");
     assert_eq!(
         lexer.next_token(),
@@ -99,6 +111,139 @@ double "inside\"some\"double"
     );
 }
 #[test]
+fn escapes() {
+    let input = r#""a\n\t\r\0\\\"\'\a\b\f\v" "\x41\x7\xff\101\0101\377""#;
+    let kinds = Lexer::new(input)
+        .lex()
+        .unwrap()
+        .into_iter()
+        .map(|t| t.kind)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        vec![
+            TokenKind::String(Cow::Borrowed(b"a\n\t\r\0\\\"'\x07\x08\x0c\x0b")),
+            TokenKind::Whitespace,
+            // octal escapes stop after three digits
+            TokenKind::String(Cow::Borrowed(b"A\x07\xffA\x081\xff")),
+            TokenKind::Eof,
+        ]
+    );
+
+    // the span covers only the escape
+    let cases = [
+        (r#""ok \q""#, 4..6),
+        (r#""\x""#, 1..3),
+        (r#""\xg1""#, 1..3),
+        (r#""\400""#, 1..5),
+        ("\"\\è\"", 1..4),
+    ];
+    for (input, span) in cases {
+        assert_eq!(
+            Lexer::new(input).lex(),
+            Err(vec![LexerError::new(LexerErrorKind::InvalidEscape, span)])
+        );
+    }
+    assert_eq!(
+        Lexer::new("\"end\\").lex(),
+        Err(vec![LexerError::new(
+            LexerErrorKind::ExpectedStringEnd,
+            0..5
+        )])
+    );
+}
+#[test]
+fn char_literals() {
+    let input = r#"'a' '\n' '\'' '"' '\x41'"#;
+    let kinds = Lexer::new(input)
+        .lex()
+        .unwrap()
+        .into_iter()
+        .map(|t| t.kind)
+        .filter(|k| *k != TokenKind::Whitespace)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        vec![
+            TokenKind::Char(b'a'),
+            TokenKind::Char(b'\n'),
+            TokenKind::Char(b'\''),
+            TokenKind::Char(b'"'),
+            TokenKind::Char(b'A'),
+            TokenKind::Eof,
+        ]
+    );
+
+    let cases = [
+        ("'ab' 'c'", LexerErrorKind::InvalidCharLiteral, 0..4),
+        ("''", LexerErrorKind::InvalidCharLiteral, 0..2),
+        ("'a", LexerErrorKind::ExpectedCharEnd, 0..2),
+        ("'ab\n'c'", LexerErrorKind::ExpectedCharEnd, 0..2),
+        ("'", LexerErrorKind::ExpectedCharEnd, 0..1),
+        (r#"'\q'"#, LexerErrorKind::InvalidEscape, 1..3),
+    ];
+    for (input, kind, span) in cases {
+        assert_eq!(
+            Lexer::new(input).lex_recovering().1[0],
+            LexerError::new(kind, span)
+        );
+    }
+}
+#[test]
+fn identifiers() {
+    let input = "_start: main.loop: b loop_end
+add.s $f0, $f1, $f12
+__data$1: c.lt.s b";
+    let kinds = Lexer::new(input)
+        .lex()
+        .unwrap()
+        .into_iter()
+        .map(|t| t.kind)
+        .filter(|k| !matches!(k, TokenKind::Whitespace | TokenKind::Comma))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        vec![
+            TokenKind::Ident("_start"),
+            TokenKind::Colon,
+            TokenKind::Ident("main.loop"),
+            TokenKind::Colon,
+            TokenKind::Instruction(InstructionKind::B),
+            TokenKind::Ident("loop_end"),
+            TokenKind::Newline,
+            TokenKind::Instruction(InstructionKind::AddS),
+            TokenKind::Register(Register::Float(0)),
+            TokenKind::Register(Register::Float(1)),
+            TokenKind::Register(Register::Float(12)),
+            TokenKind::Newline,
+            TokenKind::Ident("__data$1"),
+            TokenKind::Colon,
+            TokenKind::Instruction(InstructionKind::CLtS),
+            // only the first word of a statement can be a mnemonic
+            TokenKind::Ident("b"),
+            TokenKind::Eof,
+        ]
+    );
+
+    // a label named like a mnemonic
+    let kinds = Lexer::new("add: add.d")
+        .lex()
+        .unwrap()
+        .into_iter()
+        .map(|t| t.kind)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        vec![
+            TokenKind::Ident("add"),
+            TokenKind::Colon,
+            TokenKind::Whitespace,
+            TokenKind::Instruction(InstructionKind::AddD),
+            TokenKind::Eof,
+        ]
+    );
+}
+#[test]
 fn lex() {
     let input = "lw $ra 4";
     let mut lexer = Lexer::new(input);
@@ -127,15 +272,15 @@ test";
     let mut lexer = Lexer::new(input);
     assert_eq!(
         lexer.lex(),
-        Err(LexerError {
+        Err(vec![LexerError {
             kind: LexerErrorKind::Register(RegisterParseError::Other),
             span: 13..19
-        })
+        }])
     )
 }
 #[test]
 fn parse_numbers() {
-    let mut lexer = Lexer::new("3 12 0x1f 0b1101 0o12");
+    let mut lexer = Lexer::new("3 12 0x1f 0b1101 0o12 40000 0xFFFF0000");
     let tokens = vec![
         TokenKind::Number(3),
         TokenKind::Whitespace,
@@ -146,6 +291,10 @@ fn parse_numbers() {
         TokenKind::Number(13),
         TokenKind::Whitespace,
         TokenKind::Number(10),
+        TokenKind::Whitespace,
+        TokenKind::Number(40000),
+        TokenKind::Whitespace,
+        TokenKind::Number(0xffff_0000),
         TokenKind::Eof,
     ];
     assert_eq!(
@@ -158,7 +307,7 @@ fn parse_numbers() {
         tokens
     );
 
-    let strs = ["3a", "32768", "0x1h"];
+    let strs = ["3a", "0x10000000000000000", "0x1h"];
     let mut errs = [
         LexerErrorKind::NumberParseError,
         LexerErrorKind::NumberOutOfRange,
@@ -169,10 +318,84 @@ fn parse_numbers() {
         let mut lexer = Lexer::new(s);
         assert_eq!(
             lexer.lex(),
-            Err(LexerError {
+            Err(vec![LexerError {
                 kind: errs.next().unwrap(),
                 span: 0..s.len()
-            })
+            }])
+        );
+    }
+}
+
+#[test]
+fn parse_floats() {
+    let input = ".double 3.25 1e-5 2.5E+3 .5 0.25e2 inf NaN 12. 0x1e5";
+    let tokens = Lexer::new(input).lex().unwrap();
+    let kinds = tokens.into_iter().map(|t| t.kind).collect::<Vec<_>>();
+    let float = |num: f64| TokenKind::Float(num.to_bits());
+    assert_eq!(
+        kinds,
+        vec![
+            TokenKind::Directive(Directive::Double),
+            TokenKind::Whitespace,
+            float(3.25),
+            TokenKind::Whitespace,
+            float(1e-5),
+            TokenKind::Whitespace,
+            float(2.5e3),
+            TokenKind::Whitespace,
+            float(0.5),
+            TokenKind::Whitespace,
+            float(25.0),
+            TokenKind::Whitespace,
+            float(f64::INFINITY),
+            TokenKind::Whitespace,
+            float(f64::NAN),
+            TokenKind::Whitespace,
+            // a dot not followed by digits is not part of the number
+            TokenKind::Number(12),
+            TokenKind::Dot,
+            TokenKind::Whitespace,
+            TokenKind::Number(0x1e5),
+            TokenKind::Eof,
+        ]
+    );
+
+    // elsewhere the special values are names, which can be labels
+    let input = ".float inf\ninf: j nan\nli $t0, Infinity";
+    let tokens = Lexer::new(input).lex().unwrap();
+    let kinds = tokens
+        .into_iter()
+        .map(|t| t.kind)
+        .filter(|k| !matches!(k, TokenKind::Whitespace | TokenKind::Newline))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        vec![
+            TokenKind::Directive(Directive::Float),
+            float(f64::INFINITY),
+            TokenKind::Ident("inf"),
+            TokenKind::Colon,
+            TokenKind::Instruction(InstructionKind::J),
+            TokenKind::Ident("nan"),
+            TokenKind::Pseudo(PseudoKind::Li),
+            TokenKind::Register(Register::PrefixedNumber(
+                RegisterPrefixedName::new_unchecked('t', 0)
+            )),
+            TokenKind::Comma,
+            TokenKind::Ident("Infinity"),
+            TokenKind::Eof,
+        ]
+    );
+
+    let cases = [
+        ("1e999", LexerErrorKind::NumberOutOfRange),
+        ("1.5x", LexerErrorKind::NumberParseError),
+        ("2e", LexerErrorKind::NumberParseError),
+    ];
+    for (input, kind) in cases {
+        assert_eq!(
+            Lexer::new(input).lex(),
+            Err(vec![LexerError::new(kind, 0..input.len())])
         );
     }
 }
@@ -182,27 +405,99 @@ fn invalid_chars() {
     // 4, 3, 2 bytes respectively
     let strs = [" 😂 .text", "test €", "un è"];
     let ranges = [1..5, 5..8, 3..5];
-    for (s, span) in strs.into_iter().zip(ranges.into_iter()) {
+    for (s, span) in strs.into_iter().zip(ranges) {
         let mut lexer = Lexer::new(s);
         assert_eq!(
             lexer.lex(),
-            Err(LexerError {
+            Err(vec![LexerError {
                 kind: LexerErrorKind::NonAsciiChar,
                 span,
-            })
+            }])
         );
     }
 
-    let mut lexer = Lexer::new("s~");
+    let mut lexer = Lexer::new("s@");
     assert_eq!(
         lexer.lex(),
-        Err(LexerError {
-            kind: LexerErrorKind::InvalidToken('~'),
+        Err(vec![LexerError {
+            kind: LexerErrorKind::InvalidToken('@'),
             span: 1..2
-        })
+        }])
+    );
+
+    let mut lexer = Lexer::new("1 < 2");
+    assert_eq!(
+        lexer.lex(),
+        Err(vec![LexerError {
+            kind: LexerErrorKind::InvalidToken('<'),
+            span: 2..3
+        }])
     )
 }
 
+#[test]
+fn operators() {
+    let input = "~(1<<2)*3/4%5>>6&7|8^-9";
+    let tokens = Lexer::new(input).lex().unwrap();
+    let kinds = tokens.iter().map(|t| &t.kind).collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        vec![
+            &TokenKind::Tilde,
+            &TokenKind::LParen,
+            &TokenKind::Number(1),
+            &TokenKind::Shl,
+            &TokenKind::Number(2),
+            &TokenKind::RParen,
+            &TokenKind::Star,
+            &TokenKind::Number(3),
+            &TokenKind::Slash,
+            &TokenKind::Number(4),
+            &TokenKind::Percent,
+            &TokenKind::Number(5),
+            &TokenKind::Shr,
+            &TokenKind::Number(6),
+            &TokenKind::Ampersand,
+            &TokenKind::Number(7),
+            &TokenKind::Pipe,
+            &TokenKind::Number(8),
+            &TokenKind::Caret,
+            &TokenKind::Minus,
+            &TokenKind::Number(9),
+            &TokenKind::Eof,
+        ]
+    );
+
+    // `%` followed by the name of an operator and a parenthesis is a relocation operator
+    let kinds = Lexer::new("%hi(x) %lo(x) %gp_rel(x) x%hi %foo(x)")
+        .lex()
+        .unwrap()
+        .into_iter()
+        .map(|t| t.kind)
+        .filter(|k| {
+            !matches!(
+                k,
+                TokenKind::Whitespace | TokenKind::LParen | TokenKind::RParen
+            )
+        })
+        .filter(|k| *k != TokenKind::Ident("x"))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            TokenKind::Relocation(RelocationKind::Hi16),
+            TokenKind::Relocation(RelocationKind::Lo16),
+            TokenKind::Relocation(RelocationKind::GpRel16),
+            TokenKind::Percent,
+            TokenKind::Ident("hi"),
+            TokenKind::Percent,
+            TokenKind::Ident("foo"),
+            TokenKind::Eof,
+        ]
+    );
+    assert_eq!(tokens[3].span, 3..5);
+}
+
 #[test]
 fn comments() {
     let mut lexer = Lexer::new(
@@ -211,5 +506,321 @@ fn comments() {
 # some more comments on line three
 #",
     );
-    assert_eq!(lexer.lex(), Ok(vec![Token::new(TokenKind::Eof, 91..92)]));
+    assert_eq!(
+        lexer.lex(),
+        Ok(vec![
+            Token::new(TokenKind::Newline, 21..22),
+            Token::new(TokenKind::Newline, 54..55),
+            Token::new(TokenKind::Newline, 89..90),
+            Token::new(TokenKind::Eof, 91..92)
+        ])
+    );
+}
+
+#[test]
+fn trivia() {
+    let mut lexer = Lexer::new("nop # no operation\n#").with_trivia();
+    assert_eq!(
+        lexer.lex(),
+        Ok(vec![
+            Token::new(TokenKind::Instruction(InstructionKind::Nop), 0..3),
+            Token::new(TokenKind::Whitespace, 3..4),
+            Token::new(TokenKind::Comment, 4..18),
+            Token::new(TokenKind::Newline, 18..19),
+            Token::new(TokenKind::Comment, 19..20),
+            Token::new(TokenKind::Eof, 20..21)
+        ])
+    );
+
+    // a block comment over several lines still ends the statement
+    let mut lexer = Lexer::new("/* a */ nop /* b\n */ nop")
+        .with_dialect(Dialect::Gnu)
+        .with_trivia();
+    let kinds: Vec<_> = lexer.lex().unwrap().into_iter().map(|t| t.kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            TokenKind::Comment,
+            TokenKind::Whitespace,
+            TokenKind::Instruction(InstructionKind::Nop),
+            TokenKind::Whitespace,
+            TokenKind::Comment,
+            TokenKind::Whitespace,
+            TokenKind::Instruction(InstructionKind::Nop),
+            TokenKind::Eof
+        ]
+    );
+}
+
+#[test]
+fn error_recovery() {
+    let input = "add $t0, $t9, $t1
+lw $t0 @ $t1
+sub $e0, $t1, $t2 # bad register
+jr $ra";
+    let (tokens, errors) = Lexer::new(input).lex_recovering();
+    assert_eq!(
+        errors,
+        vec![
+            LexerError::new(LexerErrorKind::InvalidToken('@'), 25..26),
+            LexerError::new(
+                LexerErrorKind::Register(RegisterParseError::InvalidPrefix('e')),
+                35..38
+            ),
+        ]
+    );
+    // the lines with errors don't produce any token
+    let kinds = tokens.into_iter().map(|t| t.kind).collect::<Vec<_>>();
+    assert_eq!(
+        kinds.iter().filter(|k| **k == TokenKind::Newline).count(),
+        3
+    );
+    assert_eq!(
+        kinds[kinds.len() - 6..],
+        [
+            TokenKind::Newline,
+            TokenKind::Newline,
+            TokenKind::Instruction(InstructionKind::Jr),
+            TokenKind::Whitespace,
+            TokenKind::Register(Register::Name(RegisterName::Ra)),
+            TokenKind::Eof,
+        ]
+    );
+
+    let errors = Lexer::new("@\n@\n@").with_max_errors(2).lex().unwrap_err();
+    assert_eq!(errors.len(), 2);
+}
+
+#[test]
+fn iterator() {
+    // the tokens are read lazily and an error skips the rest of its line
+    let mut lexer = Lexer::new("nop @ $t0\njr $ra");
+    assert_eq!(
+        lexer.next().unwrap().unwrap().kind,
+        TokenKind::Instruction(InstructionKind::Nop)
+    );
+    assert_eq!(lexer.next().unwrap().unwrap().kind, TokenKind::Whitespace);
+    assert_eq!(
+        lexer.next().unwrap(),
+        Err(LexerError::new(LexerErrorKind::InvalidToken('@'), 4..5))
+    );
+    let kinds = lexer.map(|t| t.unwrap().kind).collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            TokenKind::Newline,
+            TokenKind::Instruction(InstructionKind::Jr),
+            TokenKind::Whitespace,
+            TokenKind::Register(Register::Name(RegisterName::Ra)),
+            TokenKind::Eof,
+        ]
+    );
+}
+
+#[test]
+fn include_stream() {
+    let mut sources = SourceDb::new();
+    let main = sources.add_file("main.asm", ".include \"lib.asm\"\njr $ra");
+    let lib = sources.add_file("lib.asm", "nop @");
+    let includes = [(sources.span(main).start + 9, lib)].into_iter().collect();
+    let mut stream = TokenStream::new(&sources, main, includes);
+    let kinds = stream
+        .by_ref()
+        .filter(|t| t.kind != TokenKind::Whitespace)
+        .map(|t| t.kind)
+        .collect::<Vec<_>>();
+    // the included tokens take the place of the directive, without their end of file
+    assert_eq!(
+        kinds,
+        [
+            TokenKind::Instruction(InstructionKind::Nop),
+            TokenKind::Error,
+            TokenKind::Newline,
+            TokenKind::Instruction(InstructionKind::Jr),
+            TokenKind::Register(Register::Name(RegisterName::Ra)),
+            TokenKind::Eof,
+        ]
+    );
+    let start = sources.span(lib).start;
+    let errors = stream.into_errors();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].get_span(), start + 4..start + 5);
+}
+
+#[test]
+fn spans() {
+    let mut sources = SourceDb::new();
+    sources.add_file("first.asm", "nop");
+    let input = "x: lw $t0, -4($sp) # c\n.asciiz \"a\\n\", 'b'";
+    let file = sources.add_file("second.asm", input);
+    let start = sources.span(file).start;
+    let tokens = Lexer::for_file(&sources, file).lex().unwrap();
+    let texts = tokens
+        .iter()
+        .filter(|t| t.kind != TokenKind::Whitespace)
+        .map(|t| &sources.text()[t.span.start..t.span.end])
+        .collect::<Vec<_>>();
+    assert_eq!(
+        texts,
+        [
+            "x", ":", "lw", "$t0", ",", "-", "4", "(", "$sp", ")", "\n", ".asciiz", "\"a\\n\"",
+            ",", "'b'", "\n"
+        ]
+    );
+    // the end of file is right after the input
+    assert_eq!(
+        tokens.last().unwrap().span,
+        start + input.len()..start + input.len() + 1
+    );
+}
+
+#[test]
+fn dialects() {
+    let kinds = |input, dialect| {
+        Lexer::new(input).with_dialect(dialect).lex().map(|tokens| {
+            tokens
+                .into_iter()
+                .map(|t| t.kind)
+                .filter(|k| *k != TokenKind::Whitespace)
+                .collect::<Vec<_>>()
+        })
+    };
+    // numbers with a leading zero are octal, except in MARS
+    assert_eq!(
+        kinds("010 0", Dialect::Mars).unwrap()[..2],
+        [TokenKind::Number(10), TokenKind::Number(0)]
+    );
+    assert_eq!(
+        kinds("010 0", Dialect::Spim).unwrap()[..2],
+        [TokenKind::Number(8), TokenKind::Number(0)]
+    );
+    assert_eq!(
+        kinds("09", Dialect::Gnu),
+        Err(vec![LexerError::new(
+            LexerErrorKind::NumberParseError,
+            0..2
+        )])
+    );
+
+    let fp = TokenKind::Register(Register::Name(RegisterName::Fp));
+    assert_eq!(kinds("$s8", Dialect::Spim).unwrap()[0], fp);
+    assert_eq!(
+        kinds("$r4", Dialect::Gnu).unwrap()[0],
+        TokenKind::Register(Register::Number(4))
+    );
+    let not_in = |name: &str, dialect| {
+        Err(vec![LexerError::new(
+            LexerErrorKind::NotInDialect {
+                name: name.into(),
+                dialect,
+            },
+            0..name.len(),
+        )])
+    };
+    assert_eq!(kinds("$s8", Dialect::Mars), not_in("$s8", Dialect::Mars));
+    assert_eq!(kinds("$r4", Dialect::Spim), not_in("$r4", Dialect::Spim));
+
+    assert_eq!(
+        kinds(".global", Dialect::Gnu).unwrap()[0],
+        TokenKind::Directive(Directive::Global)
+    );
+    assert_eq!(
+        kinds(".global", Dialect::Mars),
+        not_in(".global", Dialect::Mars)
+    );
+    assert_eq!(kinds(".eqv", Dialect::Spim), not_in(".eqv", Dialect::Spim));
+    assert_eq!(
+        kinds("subi", Dialect::Mars).unwrap()[0],
+        TokenKind::Pseudo(PseudoKind::Subi)
+    );
+    assert_eq!(kinds("subi", Dialect::Gnu), not_in("subi", Dialect::Gnu));
+    // a label with the name is fine
+    assert_eq!(
+        kinds("subiu:", Dialect::Spim).unwrap()[0],
+        TokenKind::Ident("subiu")
+    );
+    assert_eq!(
+        kinds(".end_macro", Dialect::Mars).unwrap()[0],
+        TokenKind::Directive(Directive::EndMacro)
+    );
+
+    // GNU has C style comments and `;` between statements
+    assert_eq!(
+        kinds("nop /* a */; nop /*\n*/ nop", Dialect::Gnu).unwrap(),
+        [
+            TokenKind::Instruction(InstructionKind::Nop),
+            TokenKind::Newline,
+            TokenKind::Instruction(InstructionKind::Nop),
+            TokenKind::Newline,
+            TokenKind::Instruction(InstructionKind::Nop),
+            TokenKind::Eof,
+        ]
+    );
+    assert_eq!(
+        kinds("nop /* a", Dialect::Gnu),
+        Err(vec![LexerError::new(
+            LexerErrorKind::ExpectedCommentEnd,
+            4..6
+        )])
+    );
+    assert_eq!(
+        kinds("4 /*2*/", Dialect::Mars).unwrap()[..3],
+        [TokenKind::Number(4), TokenKind::Slash, TokenKind::Star]
+    );
+}
+
+#[test]
+fn local_labels() {
+    let kinds = Lexer::new("1: b 1f\n2:\n    beq $t0, $zero, 10b\n0b1 0b 12:")
+        .lex()
+        .unwrap()
+        .into_iter()
+        .map(|t| t.kind)
+        .filter(|k| *k != TokenKind::Whitespace)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            TokenKind::LocalLabel(1),
+            TokenKind::Colon,
+            TokenKind::Instruction(InstructionKind::B),
+            TokenKind::LocalRef {
+                number: 1,
+                forward: true
+            },
+            TokenKind::Newline,
+            TokenKind::LocalLabel(2),
+            TokenKind::Colon,
+            TokenKind::Newline,
+            TokenKind::Instruction(InstructionKind::Beq),
+            TokenKind::Register(Register::PrefixedNumber(
+                RegisterPrefixedName::new_unchecked('t', 0)
+            )),
+            TokenKind::Comma,
+            TokenKind::Register(Register::Name(RegisterName::Zero)),
+            TokenKind::Comma,
+            TokenKind::LocalRef {
+                number: 10,
+                forward: false
+            },
+            TokenKind::Newline,
+            // `0b` followed by binary digits is still a number
+            TokenKind::Number(1),
+            TokenKind::LocalRef {
+                number: 0,
+                forward: false
+            },
+            // only at the start of a statement a number followed by `:` is a label
+            TokenKind::Number(12),
+            TokenKind::Colon,
+            TokenKind::Eof,
+        ]
+    );
+    assert_eq!(
+        Lexer::new("5000000000f").lex(),
+        Err(vec![LexerError::new(
+            LexerErrorKind::NumberOutOfRange,
+            0..11
+        )])
+    );
 }
@@ -1,26 +1,226 @@
 #![allow(dead_code)]
 
-use errors::CompileError;
-use lexer::Lexer;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+
+use assembler::assemble;
+use defs::dialect::Dialect;
+use defs::directive::Directive;
+use defs::program::Program;
+use defs::token::TokenKind;
+use errors::{CompileError, ParserError, ParserErrorKind, SourceError};
+use lexer::{include_path, Lexer, TokenStream};
 use parser::Parser;
+use source::{FileId, SourceDb};
+
+pub use errors::{Warning, WarningName};
 
+mod assembler;
+pub mod cst;
 pub mod defs;
+pub mod disassembler;
+mod encoder;
 mod errors;
+mod expansion;
 mod lexer;
 mod parser;
+pub mod source;
+
+/// A compiled program along with the warnings found while compiling it
+#[derive(Debug)]
+pub struct CompileOutput {
+    pub program: Program,
+    pub warnings: Vec<Warning>,
+}
 
 pub struct MipsCompiler<'a> {
-    input: &'a str,
+    /// Holds the file being compiled, the files it includes are added to it
+    sources: &'a mut SourceDb,
+    file: FileId,
+    /// The number of errors after which compilation stops
+    max_errors: usize,
+    /// The warnings that are never reported
+    allowed_warnings: Vec<WarningName>,
+    /// Whether warnings are reported as errors
+    warnings_as_errors: bool,
+    dialect: Dialect,
 }
 
 impl<'a> MipsCompiler<'a> {
-    pub fn new(input: &'a str) -> Self {
-        Self { input }
-    }
-    pub fn compile(self) -> Result<(), CompileError> {
-        //TODO: bring every part together
-        let tokens = Lexer::new(self.input).lex()?;
-        let parser = Parser::new(&tokens);
-        Ok(())
+    /// Creates a compiler for a file of the database. The spans of the errors, warnings and
+    /// statements point into the database, which can resolve them to their file.
+    pub fn new(sources: &'a mut SourceDb, file: FileId) -> Self {
+        Self {
+            sources,
+            file,
+            max_errors: usize::MAX,
+            allowed_warnings: Vec::new(),
+            warnings_as_errors: false,
+            dialect: Dialect::default(),
+        }
+    }
+
+    /// Sets the assembler whose syntax the files follow, MARS by default
+    pub fn with_dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
+    /// Sets the number of errors after which compilation stops, by default all errors are reported
+    pub fn with_max_errors(mut self, max_errors: usize) -> Self {
+        self.max_errors = max_errors;
+        self
+    }
+
+    /// Silences a kind of warning in the whole file.
+    /// Warnings can also be silenced on a single line with a comment like `# @allow(unused-label)`.
+    pub fn allow_warning(mut self, warning: WarningName) -> Self {
+        self.allowed_warnings.push(warning);
+        self
+    }
+
+    /// Makes compilation fail if there are any warnings, reporting them as errors
+    pub fn with_warnings_as_errors(mut self, warnings_as_errors: bool) -> Self {
+        self.warnings_as_errors = warnings_as_errors;
+        self
+    }
+
+    pub fn compile(mut self) -> Result<CompileOutput, CompileError> {
+        let mut errors = Vec::new();
+        let mut includes = HashMap::new();
+        self.load_includes(self.file, &mut includes, &mut errors);
+        if !errors.is_empty() && errors.len() >= self.max_errors {
+            return Err(CompileError { errors });
+        }
+        let mut tokens = TokenStream::new(&*self.sources, self.file, includes)
+            .with_dialect(self.dialect)
+            .with_max_errors(self.max_errors - errors.len());
+        let parsed = Parser::new(&mut tokens)
+            .with_dialect(self.dialect)
+            .with_max_errors(self.max_errors - errors.len())
+            .parse();
+        errors.extend(tokens.into_errors());
+        let (mut program, warnings) = match parsed {
+            Ok(output) if errors.is_empty() => output,
+            Ok(_) => return Err(CompileError { errors }),
+            Err(parser_errors) => {
+                errors.extend(parser_errors.into_iter().map(SourceError::from));
+                errors.truncate(self.max_errors);
+                return Err(CompileError { errors });
+            }
+        };
+        assemble(&mut program, self.dialect).map_err(|assembler_errors| {
+            let mut err = CompileError::from(assembler_errors);
+            err.errors.truncate(self.max_errors);
+            err
+        })?;
+        let warnings = warnings
+            .into_iter()
+            .filter(|w| !self.allowed_warnings.contains(&w.name()) && !self.allowed_on_line(w))
+            .collect::<Vec<_>>();
+        if self.warnings_as_errors && !warnings.is_empty() {
+            return Err(CompileError {
+                errors: warnings.into_iter().map(SourceError::Warning).collect(),
+            });
+        }
+        Ok(CompileOutput { program, warnings })
+    }
+
+    /// Adds the files included by `file` to the database, along with the ones they include in
+    /// turn, so that their tokens can be borrowed while parsing. `includes` maps the start of
+    /// the path of each `.include` to the file it points to.
+    fn load_includes(
+        &mut self,
+        file: FileId,
+        includes: &mut HashMap<usize, FileId>,
+        errors: &mut Vec<SourceError>,
+    ) {
+        if !self.sources.content(file).contains(".include") {
+            return;
+        }
+        // the errors of the lexer are reported while parsing
+        let (tokens, _) = Lexer::for_file(self.sources, file)
+            .with_dialect(self.dialect)
+            .lex_recovering();
+        let paths = tokens
+            .split(|t| matches!(t.kind, TokenKind::Newline | TokenKind::Eof))
+            .filter_map(|line| {
+                let start = line
+                    .iter()
+                    .position(|t| t.kind == TokenKind::Directive(Directive::Include))?;
+                include_path(&line[start..])
+            })
+            .map(|(path, span)| (String::from_utf8_lossy(path).into_owned(), span))
+            .collect::<Vec<_>>();
+        for (path, span) in paths {
+            if errors.len() >= self.max_errors {
+                return;
+            }
+            let loaded = self.sources.files().count();
+            match self.include(file, &path, span.clone()) {
+                Ok(included) => {
+                    includes.insert(span.start, included);
+                    // the files that were already there have been scanned
+                    if self.sources.files().count() > loaded {
+                        self.load_includes(included, includes, errors);
+                    }
+                }
+                Err(err) => errors.push(err.into()),
+            }
+        }
+    }
+
+    /// Adds the file included by `parent` to the database, if it's not there already. The path
+    /// is relative to the directory of `parent`.
+    fn include(
+        &mut self,
+        parent: FileId,
+        path: &str,
+        span: Range<usize>,
+    ) -> Result<FileId, ParserError> {
+        let full_path = match Path::new(self.sources.name(parent)).parent() {
+            Some(dir) => dir.join(path),
+            None => path.into(),
+        };
+        let name = full_path.to_string_lossy().into_owned();
+        let file = match self.sources.find(&name) {
+            Some(file) => file,
+            None => match std::fs::read_to_string(&full_path) {
+                Ok(content) => self.sources.add_file(name, &content),
+                Err(err) => {
+                    return Err(ParserError::new(
+                        ParserErrorKind::IncludeFailed {
+                            path: path.into(),
+                            reason: err.to_string(),
+                        },
+                        span,
+                    ))
+                }
+            },
+        };
+        Ok(file)
+    }
+
+    /// Returns true if the warning is silenced by a comment like `# @allow(unused-label)` on the
+    /// line it points to
+    fn allowed_on_line(&self, warning: &Warning) -> bool {
+        let (file, span) = self.sources.resolve(&warning.span);
+        let content = self.sources.content(file);
+        let start = content[..span.start].rfind('\n').map_or(0, |i| i + 1);
+        let end = content[span.start..]
+            .find('\n')
+            .map_or(content.len(), |i| span.start + i);
+        let line = &content[start..end];
+        let Some(comment) = line.find('#').map(|i| &line[i..]) else {
+            return false;
+        };
+        let name = warning.name().to_string();
+        comment.match_indices("@allow(").any(|(i, pat)| {
+            let args = &comment[i + pat.len()..];
+            args[..args.find(')').unwrap_or(args.len())]
+                .split(',')
+                .any(|arg| arg.trim() == name)
+        })
     }
 }
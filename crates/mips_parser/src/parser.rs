@@ -1,21 +1,1040 @@
-use std::{iter::Peekable, slice::Iter};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    ops::{Range, RangeInclusive},
+};
 
-use crate::defs::{instruction::InstructionKind, token::Token};
+use crate::defs::dialect::Dialect;
+use crate::defs::directive::Directive;
+use crate::defs::expression::{BinaryOp, EvalErrorKind, Expr, ExprKind, UnaryOp};
+use crate::defs::instruction::{find_signature, InstructionKind, OperandShape};
+use crate::defs::program::{
+    Address, DirectiveStatement, Immediate, Instruction, Operand, OperandKind, Program,
+    PseudoInstruction, RelocationKind, SegmentKind, Statement,
+};
+use crate::defs::pseudo::PseudoKind;
+use crate::defs::register::Register;
+use crate::defs::symbols::LocalLabels;
+use crate::defs::token::{Token, TokenKind};
+use crate::errors::{ParserError, ParserErrorKind, Warning, WarningKind};
+use crate::expansion::{expand, expand_pseudo};
 
+#[cfg(test)]
+mod tests;
 
-pub(crate) struct Parser<'a> {
-    tokens: Peekable<Iter<'a, Token>>,
+/// Parses the tokens as they are read, so they never need to be all in memory
+pub(crate) struct Parser<'a, I: Iterator<Item = Token<'a>>> {
+    tokens: I,
+    /// The tokens read ahead of the current position, whitespace excluded
+    lookahead: VecDeque<Token<'a>>,
+    /// True if the current statement contains a token the lexer couldn't read, in which case
+    /// the errors of the statement are not reported, as the lexer already reported one
+    lexer_error: bool,
+    program: Program,
+    /// The segment new statements are added to
+    segment: SegmentKind,
+    errors: Vec<ParserError>,
+    /// The number of errors after which parsing stops
+    max_errors: usize,
+    warnings: Vec<Warning>,
+    /// The labels that were defined, with the span of their definition
+    label_defs: Vec<(String, Range<usize>)>,
+    /// The labels that are referenced by an operand
+    label_refs: HashSet<String>,
+    /// The constants defined with `.eqv` or `.set`
+    constants: HashMap<String, i64>,
+    /// True if the last instruction was an unconditional jump with no label after it
+    after_jump: bool,
+    dialect: Dialect,
+    local_labels: LocalLabels,
+    /// False after a `.set noat`, when pseudo-instructions can't be expanded using `$at`
+    at_available: bool,
 }
 
-impl<'a> Parser<'a> {
-    pub fn new(tokens: &'a [Token]) -> Self {
+impl<'a, I: Iterator<Item = Token<'a>>> Parser<'a, I> {
+    pub fn new(tokens: I) -> Self {
         Parser {
-            tokens: tokens.iter().peekable(),
+            tokens,
+            lookahead: VecDeque::new(),
+            lexer_error: false,
+            program: Program::default(),
+            // like in MARS, statements before any segment directive go in the text segment
+            segment: SegmentKind::Text,
+            errors: Vec::new(),
+            max_errors: usize::MAX,
+            warnings: Vec::new(),
+            label_defs: Vec::new(),
+            label_refs: HashSet::new(),
+            constants: HashMap::new(),
+            after_jump: false,
+            dialect: Dialect::default(),
+            local_labels: LocalLabels::default(),
+            at_available: true,
         }
     }
 
-    fn do_something(&mut self) {
-        let a = self.tokens.next();
-        dbg!(self.tokens.next());
+    /// Sets the dialect the source is written in
+    pub fn with_dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
     }
+
+    /// Sets the number of errors after which the parser stops
+    pub fn with_max_errors(mut self, max_errors: usize) -> Self {
+        self.max_errors = max_errors;
+        self
+    }
+
+    /// Parses all the tokens into a [`Program`] along with the warnings for it, or returns all
+    /// the errors that were found.
+    /// When a statement has an error the rest of its line is skipped and parsing resumes from
+    /// the next line.
+    pub fn parse(mut self) -> Result<(Program, Vec<Warning>), Vec<ParserError>> {
+        loop {
+            match self.peek_token().kind {
+                TokenKind::Eof => break,
+                TokenKind::Newline => {
+                    self.lookahead.pop_front();
+                }
+                _ => {
+                    self.lexer_error = false;
+                    if let Err(err) = self.parse_statement() {
+                        if !self.lexer_error {
+                            self.errors.push(err);
+                        }
+                        if self.errors.len() >= self.max_errors {
+                            break;
+                        }
+                        self.skip_line();
+                    }
+                }
+            }
+        }
+        for (number, span) in self.local_labels.unresolved() {
+            if self.errors.len() >= self.max_errors {
+                break;
+            }
+            self.errors.push(ParserError::new(
+                ParserErrorKind::UndefinedLocalLabel {
+                    number,
+                    forward: true,
+                },
+                span,
+            ));
+        }
+        if !self.errors.is_empty() {
+            return Err(self.errors);
+        }
+        for (label, span) in &self.label_defs {
+            // `main` is the entry point even when it's not declared global
+            if label != "main" && !self.label_refs.contains(label) {
+                self.warnings.push(Warning::new(
+                    WarningKind::UnusedLabel(label.clone()),
+                    span.clone(),
+                ));
+            }
+        }
+        Ok((self.program, self.warnings))
+    }
+
+    /// Parses a single statement: a label definition, an instruction or a directive.
+    /// Instructions and directives consume the rest of the line, while a label can be followed
+    /// by another statement on the same line.
+    fn parse_statement(&mut self) -> Result<(), ParserError> {
+        let tok = self.next_token();
+        match &tok.kind {
+            TokenKind::Ident(name) => {
+                // an identifier at the start of a statement can only be a label definition
+                if self.peek_token().kind != TokenKind::Colon {
+                    return Err(unexpected(&tok, STATEMENT_START));
+                }
+                self.next_token();
+                self.after_jump = false;
+                self.label_defs.push((name.to_string(), tok.span.clone()));
+                self.push_statement(Statement::Label {
+                    name: name.to_string(),
+                    span: tok.span.clone(),
+                });
+            }
+            TokenKind::LocalLabel(number) => {
+                self.expect(TokenKind::Colon, "`:`")?;
+                self.after_jump = false;
+                // local labels are meant to be used once, so they are never reported as unused
+                let name = self.local_labels.define(*number);
+                self.push_statement(Statement::Label {
+                    name,
+                    span: tok.span.clone(),
+                });
+            }
+            TokenKind::Instruction(_) | TokenKind::Pseudo(_) => {
+                if self.segment.is_data() {
+                    return Err(ParserError::new(
+                        ParserErrorKind::InstructionInDataSegment(self.segment),
+                        tok.span.clone(),
+                    ));
+                }
+                let operands = self.parse_operands()?;
+                let span = tok.span.start..operands.last().map_or(tok.span.end, |o| o.span.end);
+                let pseudo = match &tok.kind {
+                    TokenKind::Instruction(kind) => pseudo_form(*kind, &operands),
+                    TokenKind::Pseudo(kind) => Some(*kind),
+                    _ => None,
+                };
+                if let Some(kind) = pseudo {
+                    self.push_pseudo(PseudoInstruction {
+                        kind,
+                        operands,
+                        span,
+                    })?;
+                } else if let TokenKind::Instruction(kind) = tok.kind {
+                    self.push_instruction(Instruction {
+                        kind,
+                        operands,
+                        span,
+                    })?;
+                }
+            }
+            TokenKind::Directive(directive) => {
+                let directive = &match directive {
+                    Directive::Section => self.parse_section()?,
+                    directive => *directive,
+                };
+                // `.align` can also align the next instruction
+                if directive.is_data() && *directive != Directive::Align && !self.segment.is_data()
+                {
+                    return Err(ParserError::new(
+                        ParserErrorKind::DirectiveOutsideSegment {
+                            directive: *directive,
+                            segment: self.segment,
+                        },
+                        tok.span.clone(),
+                    ));
+                }
+                let operands = match directive {
+                    Directive::Eqv | Directive::Set => self.parse_definition()?,
+                    _ => self.parse_operands()?,
+                };
+                let span = tok.span.start..operands.last().map_or(tok.span.end, |o| o.span.end);
+                let stmt = DirectiveStatement {
+                    directive: *directive,
+                    operands,
+                    span,
+                };
+                check_directive(&stmt)?;
+                self.check_directive_warnings(&stmt);
+                if let [name, value] = stmt.operands.as_slice() {
+                    if let (
+                        OperandKind::Label(name),
+                        OperandKind::Immediate(Immediate::Number(num)),
+                    ) = (&name.kind, &value.kind)
+                    {
+                        if matches!(directive, Directive::Eqv | Directive::Set) {
+                            self.constants.insert(name.clone(), *num);
+                        }
+                    }
+                }
+                if let ([option], Directive::Set) = (stmt.operands.as_slice(), directive) {
+                    if let OperandKind::Label(name) = &option.kind {
+                        if self.dialect.has_noat() && matches!(name.as_str(), "at" | "noat") {
+                            self.at_available = name == "at";
+                        }
+                    }
+                }
+                if let Some(segment) = directive.segment() {
+                    if let [address] = stmt.operands.as_slice() {
+                        self.set_segment_address(segment, address)?;
+                    }
+                    self.segment = segment;
+                    self.after_jump = false;
+                } else if directive.is_data() {
+                    self.push_statement(Statement::Data(stmt));
+                } else {
+                    self.push_statement(Statement::Directive(stmt));
+                }
+            }
+            _ => return Err(unexpected(&tok, STATEMENT_START)),
+        }
+        Ok(())
+    }
+
+    /// Records the labels referenced by the operands
+    fn add_label_refs(&mut self, operands: &[Operand]) {
+        for operand in operands {
+            for label in operand.kind.labels() {
+                self.label_refs.insert(label.to_owned());
+            }
+        }
+    }
+
+    /// Checks an instruction and adds the machine instructions it expands to
+    fn push_instruction(&mut self, instruction: Instruction) -> Result<(), ParserError> {
+        let kind = instruction.kind;
+        let signature = check_operands(
+            kind.signatures(),
+            || kind.usage(),
+            &instruction.operands,
+            &instruction.span,
+        )?;
+        check_ranges(&kind, signature, &instruction.operands)?;
+        self.check_instruction_warnings(
+            signature,
+            kind.destination(),
+            &instruction.operands,
+            &instruction.span,
+        );
+        if matches!(
+            kind,
+            InstructionKind::J | InstructionKind::B | InstructionKind::Jr
+        ) {
+            self.after_jump = true;
+        }
+        for instruction in expand(instruction, self.at_available)? {
+            self.push_statement(Statement::Instruction(instruction));
+        }
+        Ok(())
+    }
+
+    /// Checks a pseudo-instruction and adds the machine instructions it expands to
+    fn push_pseudo(&mut self, pseudo: PseudoInstruction) -> Result<(), ParserError> {
+        let kind = pseudo.kind;
+        let signature = check_operands(
+            kind.signatures(),
+            || kind.usage(),
+            &pseudo.operands,
+            &pseudo.span,
+        )?;
+        check_ranges(&kind, signature, &pseudo.operands)?;
+        self.check_instruction_warnings(
+            signature,
+            kind.destination(),
+            &pseudo.operands,
+            &pseudo.span,
+        );
+        for instruction in expand_pseudo(pseudo, self.at_available)? {
+            self.push_statement(Statement::Instruction(instruction));
+        }
+        Ok(())
+    }
+
+    /// Adds the warnings for an instruction or a pseudo-instruction as it was written in the
+    /// source
+    fn check_instruction_warnings(
+        &mut self,
+        signature: &[OperandShape],
+        destination: Option<OperandShape>,
+        operands: &[Operand],
+        span: &Range<usize>,
+    ) {
+        self.add_label_refs(operands);
+        if self.after_jump {
+            self.after_jump = false;
+            self.warnings
+                .push(Warning::new(WarningKind::UnreachableCode, span.clone()));
+        }
+        for (shape, operand) in signature.iter().zip(operands) {
+            let reg = match &operand.kind {
+                OperandKind::Register(reg) => reg,
+                OperandKind::Address(Address {
+                    base: Some(reg), ..
+                }) => reg,
+                _ => continue,
+            };
+            if Some(*shape) == destination && reg.number() == 0 {
+                self.warnings
+                    .push(Warning::new(WarningKind::WriteToZero, operand.span.clone()));
+            }
+            // the kernel can use the registers reserved to it
+            let reserved = match self.segment {
+                SegmentKind::Ktext => [1].as_slice(),
+                _ => [1, 26, 27].as_slice(),
+            };
+            // after `.set noat` the program can use `$at` freely
+            if reserved.contains(&reg.number()) && (reg.number() != 1 || self.at_available) {
+                self.warnings.push(Warning::new(
+                    WarningKind::ReservedRegister(reg.clone()),
+                    operand.span.clone(),
+                ));
+            }
+        }
+    }
+
+    /// Adds the warnings for a directive
+    fn check_directive_warnings(&mut self, stmt: &DirectiveStatement) {
+        self.add_label_refs(&stmt.operands);
+        let range = match stmt.directive {
+            Directive::Byte => -128..=255,
+            Directive::Half => -32768..=65535,
+            Directive::Word => i32::MIN as i64..=u32::MAX as i64,
+            _ => return,
+        };
+        for operand in &stmt.operands {
+            let kind = match &operand.kind {
+                OperandKind::Repeat(kind, _) => kind,
+                kind => kind,
+            };
+            if let OperandKind::Immediate(Immediate::Number(num)) = *kind {
+                if !range.contains(&num) {
+                    self.warnings.push(Warning::new(
+                        WarningKind::TruncatedValue(stmt.directive),
+                        operand.span.clone(),
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Parses a comma separated list of operands until the end of the line
+    fn parse_operands(&mut self) -> Result<Vec<Operand>, ParserError> {
+        let mut operands = Vec::new();
+        if self.at_line_end() {
+            return Ok(operands);
+        }
+        loop {
+            operands.push(self.parse_operand()?);
+            if self.at_line_end() {
+                return Ok(operands);
+            }
+            let tok = self.next_token();
+            match tok.kind {
+                TokenKind::Comma => {}
+                TokenKind::Register(_)
+                | TokenKind::Ident(_)
+                | TokenKind::LocalRef { .. }
+                | TokenKind::Relocation(_)
+                | TokenKind::String(_)
+                | TokenKind::Number(_)
+                | TokenKind::Char(_)
+                | TokenKind::Float(_)
+                | TokenKind::Minus
+                | TokenKind::Tilde
+                | TokenKind::LParen => {
+                    return Err(ParserError::new(
+                        ParserErrorKind::MissingComma,
+                        tok.span.clone(),
+                    ))
+                }
+                _ => return Err(unexpected(&tok, "a comma or the end of the line")),
+            }
+        }
+    }
+
+    fn parse_operand(&mut self) -> Result<Operand, ParserError> {
+        let tok = self.peek_token();
+        match &tok.kind {
+            TokenKind::Register(reg) => {
+                self.next_token();
+                return Ok(Operand {
+                    kind: OperandKind::Register(reg.clone()),
+                    span: tok.span.clone(),
+                });
+            }
+            TokenKind::String(string) => {
+                self.next_token();
+                return Ok(Operand {
+                    kind: OperandKind::String(string.to_vec()),
+                    span: tok.span.clone(),
+                });
+            }
+            TokenKind::Float(_) | TokenKind::Minus if self.float_follows() => {
+                return self.parse_float();
+            }
+            TokenKind::Relocation(kind) => {
+                self.next_token();
+                return self.parse_relocation(*kind, tok.span.start);
+            }
+            // an address without offset like `($t0)`
+            TokenKind::LParen if self.base_follows() => {
+                self.next_token();
+                let (base, end) = self.parse_base()?;
+                return Ok(Operand {
+                    kind: OperandKind::Address(Address {
+                        offset: Immediate::Number(0),
+                        base: Some(base),
+                    }),
+                    span: tok.span.start..end,
+                });
+            }
+            _ => {}
+        }
+        let expr = self.parse_expr(0)?;
+        let span = expr.span.clone();
+        let is_label =
+            matches!(&expr.kind, ExprKind::Symbol(name) if !self.constants.contains_key(name));
+        let immediate = self.fold(expr)?;
+        // an expression followed by a parenthesis is an address like `4($sp)`
+        if self.peek_token().kind != TokenKind::LParen {
+            let kind = match immediate {
+                Immediate::Label(name, _) if is_label => OperandKind::Label(name),
+                immediate => OperandKind::Immediate(immediate),
+            };
+            if self.peek_token().kind == TokenKind::Colon {
+                return self.parse_repeat(Operand { kind, span });
+            }
+            return Ok(Operand { kind, span });
+        }
+        self.next_token();
+        let (base, end) = self.parse_base()?;
+        Ok(Operand {
+            kind: OperandKind::Address(Address {
+                offset: immediate,
+                base: Some(base),
+            }),
+            span: span.start..end,
+        })
+    }
+
+    /// Parses the value a relocation operator applies to, like the `(buf)` of `%hi(buf)`,
+    /// along with the base register that can follow it, like in `%gp_rel(var)($gp)`
+    fn parse_relocation(
+        &mut self,
+        kind: RelocationKind,
+        start: usize,
+    ) -> Result<Operand, ParserError> {
+        self.expect(TokenKind::LParen, "`(`")?;
+        let expr = self.parse_expr(0)?;
+        let end = self.expect(TokenKind::RParen, "`)`")?.span.end;
+        let value = Box::new(self.fold(expr)?);
+        let offset = match kind {
+            RelocationKind::Hi16 => Immediate::Hi(value),
+            RelocationKind::Lo16 => Immediate::Lo(value),
+            RelocationKind::GpRel16 => Immediate::GpRel(value),
+        };
+        if self.peek_token().kind != TokenKind::LParen {
+            return Ok(Operand {
+                kind: OperandKind::Immediate(offset),
+                span: start..end,
+            });
+        }
+        self.next_token();
+        let (base, end) = self.parse_base()?;
+        Ok(Operand {
+            kind: OperandKind::Address(Address {
+                offset,
+                base: Some(base),
+            }),
+            span: start..end,
+        })
+    }
+
+    /// Parses the number of times a value is repeated after it, like `0:100`
+    fn parse_repeat(&mut self, value: Operand) -> Result<Operand, ParserError> {
+        self.next_token();
+        let expr = self.parse_expr(0)?;
+        let span = expr.span.clone();
+        let count = match self.fold(expr)? {
+            Immediate::Number(count) => count,
+            _ => return Err(ParserError::new(ParserErrorKind::ExpectedConstant, span)),
+        };
+        let range = 1..=u32::MAX as i64;
+        if !range.contains(&count) {
+            return Err(ParserError::new(
+                ParserErrorKind::ValueOutOfRange {
+                    range,
+                    expected: "The repeat count must be a positive 32 bit number".into(),
+                },
+                span,
+            ));
+        }
+        Ok(Operand {
+            kind: OperandKind::Repeat(Box::new(value.kind), count as u32),
+            span: value.span.start..span.end,
+        })
+    }
+
+    /// Returns true if the next token is a floating point number, optionally after a minus
+    fn float_follows(&mut self) -> bool {
+        let index = match self.peek_nth(0) {
+            Some(Token {
+                kind: TokenKind::Minus,
+                ..
+            }) => 1,
+            _ => 0,
+        };
+        self.peek_nth(index)
+            .is_some_and(|t| matches!(t.kind, TokenKind::Float(_)))
+    }
+
+    /// Parses a floating point number, optionally after a minus, which can be repeated like the
+    /// other values of a data directive
+    fn parse_float(&mut self) -> Result<Operand, ParserError> {
+        let mut tok = self.next_token();
+        let start = tok.span.start;
+        let negative = tok.kind == TokenKind::Minus;
+        if negative {
+            tok = self.next_token();
+        }
+        let TokenKind::Float(bits) = tok.kind else {
+            return Err(unexpected(&tok, "a floating point number"));
+        };
+        let num = f64::from_bits(bits);
+        let num = if negative { -num } else { num };
+        let operand = Operand {
+            kind: OperandKind::Float(num.to_bits()),
+            span: start..tok.span.end,
+        };
+        if self.peek_token().kind == TokenKind::Colon {
+            return self.parse_repeat(operand);
+        }
+        Ok(operand)
+    }
+
+    /// Returns true if the next tokens are the start of a base register like `($t0)`
+    fn base_follows(&mut self) -> bool {
+        self.peek_nth(1)
+            .is_some_and(|t| matches!(t.kind, TokenKind::Register(_)))
+    }
+
+    /// Parses the base register of an address after the opening parenthesis, returning it with
+    /// the end of the closing parenthesis
+    fn parse_base(&mut self) -> Result<(Register, usize), ParserError> {
+        let base_tok = self.next_token();
+        let TokenKind::Register(base) = &base_tok.kind else {
+            return Err(unexpected(&base_tok, "a base register"));
+        };
+        let end = self.expect(TokenKind::RParen, "`)`")?.span.end;
+        Ok((base.clone(), end))
+    }
+
+    /// Parses an expression made of the binary operators with at least the given precedence
+    fn parse_expr(&mut self, min_precedence: u8) -> Result<Expr, ParserError> {
+        let mut lhs = self.parse_unary()?;
+        while let Some(op) = binary_op(&self.peek_token().kind) {
+            if op.precedence() < min_precedence {
+                break;
+            }
+            self.next_token();
+            // operators with the same precedence are applied from left to right
+            let rhs = self.parse_expr(op.precedence() + 1)?;
+            let span = lhs.span.start..rhs.span.end;
+            lhs = Expr::new(ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)), span);
+        }
+        Ok(lhs)
+    }
+
+    /// Parses a number, a symbol, an expression between parentheses or a unary operator
+    /// applied to one of them
+    fn parse_unary(&mut self) -> Result<Expr, ParserError> {
+        let tok = self.next_token();
+        let op = match &tok.kind {
+            TokenKind::Number(num) => {
+                return Ok(Expr::new(ExprKind::Number(*num), tok.span.clone()))
+            }
+            TokenKind::Char(c) => {
+                return Ok(Expr::new(ExprKind::Number(*c as i64), tok.span.clone()))
+            }
+            TokenKind::Ident(name) => {
+                return Ok(Expr::new(
+                    ExprKind::Symbol(name.to_string()),
+                    tok.span.clone(),
+                ))
+            }
+            TokenKind::LocalRef { number, forward } => {
+                let name = if *forward {
+                    self.local_labels.forward(*number, tok.span.clone())
+                } else {
+                    self.local_labels.backward(*number).ok_or_else(|| {
+                        ParserError::new(
+                            ParserErrorKind::UndefinedLocalLabel {
+                                number: *number,
+                                forward: false,
+                            },
+                            tok.span.clone(),
+                        )
+                    })?
+                };
+                return Ok(Expr::new(ExprKind::Symbol(name), tok.span.clone()));
+            }
+            TokenKind::LParen => {
+                let expr = self.parse_expr(0)?;
+                let end = self.expect(TokenKind::RParen, "`)`")?.span.end;
+                return Ok(Expr::new(expr.kind, tok.span.start..end));
+            }
+            TokenKind::Plus => return self.parse_unary(),
+            TokenKind::Minus => UnaryOp::Neg,
+            TokenKind::Tilde => UnaryOp::Not,
+            _ => return Err(unexpected(&tok, "an expression")),
+        };
+        let expr = self.parse_unary()?;
+        let span = tok.span.start..expr.span.end;
+        Ok(Expr::new(ExprKind::Unary(op, Box::new(expr)), span))
+    }
+
+    /// Computes the parts of an expression that don't depend on the address of a label
+    fn fold(&self, expr: Expr) -> Result<Immediate, ParserError> {
+        let expr = expr
+            .simplify(&|name| self.constants.get(name).copied())
+            .map_err(|err| {
+                let kind = match err.kind {
+                    EvalErrorKind::DivisionByZero => ParserErrorKind::DivisionByZero,
+                    EvalErrorKind::ShiftOutOfRange(amount) => {
+                        ParserErrorKind::ShiftOutOfRange(amount)
+                    }
+                    EvalErrorKind::Overflow => ParserErrorKind::Overflow,
+                    EvalErrorKind::UndefinedSymbol(_) => {
+                        unreachable!("Undefined symbols are kept in the expression")
+                    }
+                };
+                ParserError::new(kind, err.span)
+            })?;
+        let immediate = match &expr.kind {
+            ExprKind::Number(num) => Immediate::Number(*num),
+            ExprKind::Symbol(name) => Immediate::Label(name.clone(), 0),
+            ExprKind::Binary(op @ (BinaryOp::Add | BinaryOp::Sub), lhs, rhs) => {
+                match (&lhs.kind, &rhs.kind) {
+                    (ExprKind::Symbol(name), ExprKind::Number(num)) => {
+                        let offset = if *op == BinaryOp::Add { *num } else { -num };
+                        Immediate::Label(name.clone(), offset)
+                    }
+                    (ExprKind::Number(num), ExprKind::Symbol(name)) if *op == BinaryOp::Add => {
+                        Immediate::Label(name.clone(), *num)
+                    }
+                    _ => Immediate::Expr(expr),
+                }
+            }
+            _ => Immediate::Expr(expr),
+        };
+        Ok(immediate)
+    }
+
+    /// Parses the operands of `.eqv` and `.set`, a name followed by an optional comma and the
+    /// value it stands for. `.set` can also be used with only a name, like `.set noat`.
+    fn parse_definition(&mut self) -> Result<Vec<Operand>, ParserError> {
+        if self.at_line_end() {
+            return Ok(Vec::new());
+        }
+        let tok = self.next_token();
+        let TokenKind::Ident(name) = &tok.kind else {
+            return Err(unexpected(&tok, "a name"));
+        };
+        let mut operands = vec![Operand {
+            kind: OperandKind::Label(name.to_string()),
+            span: tok.span.clone(),
+        }];
+        if self.peek_token().kind == TokenKind::Comma {
+            self.next_token();
+        }
+        if !self.at_line_end() {
+            operands.push(self.parse_operand()?);
+            if !self.at_line_end() {
+                return Err(unexpected(&self.next_token(), "the end of the line"));
+            }
+        }
+        Ok(operands)
+    }
+
+    /// Parses the name of the segment after `.section`, returning the directive that begins it
+    fn parse_section(&mut self) -> Result<Directive, ParserError> {
+        let tok = self.next_token();
+        match tok.kind {
+            TokenKind::Directive(directive) if directive.segment().is_some() => Ok(directive),
+            _ => Err(unexpected(&tok, "a segment like `.text` or `.data`")),
+        }
+    }
+
+    /// Sets the address a segment starts at, from the operand of its directive like
+    /// `.data 0x10010100`. The address can only be given before the segment has statements.
+    fn set_segment_address(
+        &mut self,
+        kind: SegmentKind,
+        operand: &Operand,
+    ) -> Result<(), ParserError> {
+        let value = operand
+            .kind
+            .number()
+            .expect("The address of a segment should be checked");
+        let end = kind.end_address();
+        let range = 0..=end as i64 - 1;
+        if !range.contains(&value) {
+            return Err(ParserError::new(
+                ParserErrorKind::ValueOutOfRange {
+                    range,
+                    expected: format!("The {kind} segment must start below {end:#010x}"),
+                },
+                operand.span.clone(),
+            ));
+        }
+        let segment = self.program.segment_mut(kind);
+        if !segment.stmts.is_empty() && segment.address != value as u32 {
+            return Err(ParserError::new(
+                ParserErrorKind::SegmentAlreadyStarted(kind),
+                operand.span.clone(),
+            ));
+        }
+        segment.address = value as u32;
+        Ok(())
+    }
+
+    /// Adds a statement to the current segment
+    fn push_statement(&mut self, stmt: Statement) {
+        self.program.segment_mut(self.segment).stmts.push(stmt);
+    }
+
+    /// Consumes the next token, returning an error if it's not of the expected kind.
+    /// `expected` describes the token in the error message.
+    fn expect(&mut self, kind: TokenKind, expected: &str) -> Result<Token<'a>, ParserError> {
+        let tok = self.next_token();
+        if tok.kind == kind {
+            Ok(tok)
+        } else {
+            Err(unexpected(&tok, expected))
+        }
+    }
+
+    /// Returns true if the next token ends the line, without consuming it
+    fn at_line_end(&mut self) -> bool {
+        matches!(self.peek_token().kind, TokenKind::Newline | TokenKind::Eof)
+    }
+
+    /// Consumes the tokens until the end of the line, leaving the newline to be read next
+    fn skip_line(&mut self) {
+        while !self.at_line_end() {
+            self.lookahead.pop_front();
+        }
+    }
+
+    /// Consumes and returns the next token that isn't whitespace.
+    /// The tokens that end a line are never consumed, so that a statement can't continue on the
+    /// next line and they are returned on every call at the end of the line.
+    fn next_token(&mut self) -> Token<'a> {
+        let tok = self.peek_token();
+        if !matches!(tok.kind, TokenKind::Newline | TokenKind::Eof) {
+            self.lookahead.pop_front();
+        }
+        tok
+    }
+
+    /// Returns the next token that isn't whitespace without consuming it
+    fn peek_token(&mut self) -> Token<'a> {
+        let tok = self
+            .peek_nth(0)
+            .expect("The token stream should end with Eof")
+            .clone();
+        if tok.kind == TokenKind::Error {
+            self.lexer_error = true;
+        }
+        tok
+    }
+
+    /// Returns the token that is `n` tokens after the next one, whitespace excluded, reading
+    /// it from the input if needed
+    fn peek_nth(&mut self, n: usize) -> Option<&Token<'a>> {
+        while self.lookahead.len() <= n {
+            match self.tokens.next() {
+                Some(tok) if tok.kind == TokenKind::Whitespace => {}
+                Some(tok) => self.lookahead.push_back(tok),
+                None => break,
+            }
+        }
+        self.lookahead.get(n)
+    }
+}
+
+/// Describes what can be found at the start of a statement, for error messages
+const STATEMENT_START: &str = "an instruction, a directive or a label definition";
+
+fn unexpected(tok: &Token, expected: &str) -> ParserError {
+    ParserError::new(
+        ParserErrorKind::UnexpectedToken(expected.to_owned()),
+        tok.span.clone(),
+    )
+}
+
+/// Returns the binary operator the token stands for, if any
+fn binary_op(kind: &TokenKind) -> Option<BinaryOp> {
+    Some(match kind {
+        TokenKind::Plus => BinaryOp::Add,
+        TokenKind::Minus => BinaryOp::Sub,
+        TokenKind::Star => BinaryOp::Mul,
+        TokenKind::Slash => BinaryOp::Div,
+        TokenKind::Percent => BinaryOp::Rem,
+        TokenKind::Shl => BinaryOp::Shl,
+        TokenKind::Shr => BinaryOp::Shr,
+        TokenKind::Ampersand => BinaryOp::And,
+        TokenKind::Pipe => BinaryOp::Or,
+        TokenKind::Caret => BinaryOp::Xor,
+        _ => return None,
+    })
+}
+
+/// Returns true if the operand is valid at the given position of a statement
+type OperandCheck = dyn Fn(usize, &OperandKind) -> bool;
+
+/// Checks that a directive has the right number and kind of operands
+fn check_directive(stmt: &DirectiveStatement) -> Result<(), ParserError> {
+    let is_imm = |_: usize, o: &OperandKind| matches!(o, OperandKind::Immediate(_));
+    // the value must be known before the program is laid out
+    fn is_number(_: usize, o: &OperandKind) -> bool {
+        matches!(o, OperandKind::Immediate(Immediate::Number(_)))
+    }
+    let is_string = |_: usize, o: &OperandKind| matches!(o, OperandKind::String(_));
+    // the range of valid operand counts, and which operands are valid at each position
+    let (count, valid): (RangeInclusive<usize>, &OperandCheck) = match stmt.directive {
+        Directive::Align | Directive::Space => (1..=1, &is_number),
+        Directive::Ascii | Directive::Asciiz => (1..=usize::MAX, &is_string),
+        Directive::Include => (1..=1, &is_string),
+        // a word can also store the address of a label
+        Directive::Word => (1..=usize::MAX, &|_, o| match o {
+            OperandKind::Repeat(kind, _) => {
+                matches!(**kind, OperandKind::Immediate(_) | OperandKind::Label(_))
+            }
+            _ => matches!(o, OperandKind::Immediate(_) | OperandKind::Label(_)),
+        }),
+        Directive::Byte | Directive::Half => (1..=usize::MAX, &|_, o| match o {
+            OperandKind::Repeat(kind, _) => matches!(**kind, OperandKind::Immediate(_)),
+            _ => matches!(o, OperandKind::Immediate(_)),
+        }),
+        Directive::Float | Directive::Double => (1..=usize::MAX, &|_, o| match o {
+            OperandKind::Repeat(kind, _) => {
+                matches!(**kind, OperandKind::Immediate(_) | OperandKind::Float(_))
+            }
+            _ => matches!(o, OperandKind::Immediate(_) | OperandKind::Float(_)),
+        }),
+        Directive::Globl | Directive::Global => {
+            (1..=usize::MAX, &|_, o| matches!(o, OperandKind::Label(_)))
+        }
+        Directive::Extern => (2..=2, &|i, o| match i {
+            0 => matches!(o, OperandKind::Label(_)),
+            _ => matches!(o, OperandKind::Immediate(_)),
+        }),
+        Directive::Eqv => (2..=2, &|i, o| match i {
+            0 => matches!(o, OperandKind::Label(_)),
+            _ => is_number(i, o),
+        }),
+        Directive::Set => (1..=2, &|i, o| match i {
+            0 => matches!(o, OperandKind::Label(_)),
+            _ => is_number(i, o),
+        }),
+        Directive::Macro => (1..=usize::MAX, &|i, o| {
+            i > 0 || matches!(o, OperandKind::Label(_))
+        }),
+        Directive::EndMacro => (0..=0, &is_imm),
+        Directive::Data | Directive::Text | Directive::Kdata | Directive::Ktext => {
+            (0..=1, &is_number)
+        }
+        Directive::Section => unreachable!("`.section` is replaced by the segment it begins"),
+    };
+    let usage = || stmt.directive.usage().to_owned();
+    if !count.contains(&stmt.operands.len()) {
+        return Err(ParserError::new(
+            ParserErrorKind::WrongOperandCount {
+                found: stmt.operands.len(),
+                usage: usage(),
+            },
+            stmt.span.clone(),
+        ));
+    }
+    for (i, operand) in stmt.operands.iter().enumerate() {
+        if !valid(i, &operand.kind) {
+            return Err(ParserError::new(
+                ParserErrorKind::WrongOperandKind { usage: usage() },
+                operand.span.clone(),
+            ));
+        }
+        let kind = match &operand.kind {
+            OperandKind::Repeat(kind, _) => kind,
+            kind => kind,
+        };
+        if let (Directive::Float, OperandKind::Float(bits)) = (stmt.directive, kind) {
+            let num = f64::from_bits(*bits);
+            if num.is_finite() && num.abs() > f32::MAX as f64 {
+                return Err(ParserError::new(
+                    ParserErrorKind::FloatOutOfRange,
+                    operand.span.clone(),
+                ));
+            }
+        }
+        let (range, expected) = match stmt.directive {
+            Directive::Space => (
+                0..=u32::MAX as i64,
+                "`.space` expects a number of bytes that fits in 32 bits",
+            ),
+            Directive::Align => (
+                0..=31,
+                "`.align` expects the power of two of a 32 bit alignment",
+            ),
+            _ => continue,
+        };
+        if kind.number().is_some_and(|value| !range.contains(&value)) {
+            return Err(ParserError::new(
+                ParserErrorKind::ValueOutOfRange {
+                    range,
+                    expected: expected.into(),
+                },
+                operand.span.clone(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the pseudo-instruction with the same mnemonic as the instruction, like the `div` of
+/// `div $t0, $t1, $t2`, if it accepts the operands and the instruction doesn't
+fn pseudo_form(kind: InstructionKind, operands: &[Operand]) -> Option<PseudoKind> {
+    if kind.matching_signature(operands).is_some() {
+        return None;
+    }
+    let pseudo = kind.to_string().parse::<PseudoKind>().ok()?;
+    pseudo.matching_signature(operands).map(|_| pseudo)
+}
+
+/// Checks that the constant operands of a checked instruction fit in their fields. The ones that
+/// depend on the address of a label are checked once the program is laid out.
+fn check_ranges(
+    name: &dyn std::fmt::Display,
+    signature: &[OperandShape],
+    operands: &[Operand],
+) -> Result<(), ParserError> {
+    for (shape, operand) in signature.iter().zip(operands) {
+        let (Some(range), Some(value)) = (shape.range(), operand.kind.number()) else {
+            continue;
+        };
+        if !range.contains(&value) {
+            return Err(ParserError::new(
+                ParserErrorKind::ValueOutOfRange {
+                    range,
+                    expected: format!("`{name}` expects {} here", shape.width()),
+                },
+                operand.span.clone(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Checks the operands of an instruction against its signatures, returns the one that accepts
+/// them. `usage` describes the signatures, it's only computed for the errors.
+fn check_operands(
+    signatures: &'static [&'static [OperandShape]],
+    usage: impl Fn() -> String,
+    operands: &[Operand],
+    span: &Range<usize>,
+) -> Result<&'static [OperandShape], ParserError> {
+    if let Some(signature) = find_signature(signatures, operands) {
+        return Ok(signature);
+    }
+    let Some(first) = signatures.iter().find(|sig| sig.len() == operands.len()) else {
+        let max = signatures.iter().map(|s| s.len()).max();
+        // point at the extra operands if there are too many, or at the whole instruction
+        let span = match (max, operands.last()) {
+            (Some(max), Some(last)) if operands.len() > max => {
+                operands[max].span.start..last.span.end
+            }
+            _ => span.clone(),
+        };
+        return Err(ParserError::new(
+            ParserErrorKind::WrongOperandCount {
+                found: operands.len(),
+                usage: usage(),
+            },
+            span,
+        ));
+    };
+    let (_, operand) = first
+        .iter()
+        .zip(operands)
+        .find(|(shape, operand)| !shape.accepts(&operand.kind))
+        .expect("The signature should not match the operands");
+    Err(ParserError::new(
+        ParserErrorKind::WrongOperandKind { usage: usage() },
+        operand.span.clone(),
+    ))
 }
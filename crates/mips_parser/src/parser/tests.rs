@@ -0,0 +1,950 @@
+use crate::defs::dialect::Dialect;
+use crate::defs::directive::Directive;
+use crate::defs::instruction::InstructionKind;
+use crate::defs::program::Segment;
+use crate::defs::pseudo::PseudoKind;
+use crate::defs::register::{Register, RegisterName, RegisterPrefixedName};
+use crate::lexer::Lexer;
+
+use super::*;
+
+/// Parses the input, returning only the first error
+fn parse(input: &str) -> Result<Program, ParserError> {
+    let tokens = Lexer::new(input).lex().unwrap();
+    Parser::new(tokens.into_iter())
+        .parse()
+        .map(|(program, _)| program)
+        .map_err(|mut errs| errs.remove(0))
+}
+
+#[test]
+fn segments_and_labels() {
+    let input = ".data
+x: .word 7
+.text
+main:
+    addi $t0, $t0, -4
+.data
+y: .word 3
+";
+    let program = parse(input).unwrap();
+    let t0 = || Register::PrefixedNumber(RegisterPrefixedName::new_unchecked('t', 0));
+    assert_eq!(
+        program.segments,
+        vec![
+            Segment {
+                kind: SegmentKind::Data,
+                address: SegmentKind::Data.base_address(),
+                data: Vec::new(),
+                relocations: Vec::new(),
+                stmts: vec![
+                    Statement::Label {
+                        name: "x".into(),
+                        span: 6..7
+                    },
+                    Statement::Data(DirectiveStatement {
+                        directive: Directive::Word,
+                        operands: vec![Operand {
+                            kind: OperandKind::Immediate(Immediate::Number(7)),
+                            span: 15..16
+                        }],
+                        span: 9..16
+                    }),
+                    Statement::Label {
+                        name: "y".into(),
+                        span: 57..58
+                    },
+                    Statement::Data(DirectiveStatement {
+                        directive: Directive::Word,
+                        operands: vec![Operand {
+                            kind: OperandKind::Immediate(Immediate::Number(3)),
+                            span: 66..67
+                        }],
+                        span: 60..67
+                    }),
+                ]
+            },
+            Segment {
+                kind: SegmentKind::Text,
+                address: SegmentKind::Text.base_address(),
+                data: Vec::new(),
+                relocations: Vec::new(),
+                stmts: vec![
+                    Statement::Label {
+                        name: "main".into(),
+                        span: 23..27
+                    },
+                    Statement::Instruction(Instruction {
+                        kind: InstructionKind::Addi,
+                        operands: vec![
+                            Operand {
+                                kind: OperandKind::Register(t0()),
+                                span: 38..41
+                            },
+                            Operand {
+                                kind: OperandKind::Register(t0()),
+                                span: 43..46
+                            },
+                            Operand {
+                                kind: OperandKind::Immediate(Immediate::Number(-4)),
+                                span: 48..50
+                            },
+                        ],
+                        span: 33..50
+                    }),
+                ]
+            },
+        ]
+    );
+}
+
+#[test]
+fn operands() {
+    let program = parse("lw $ra, -8($sp)\nj end\nsyscall").unwrap();
+    let [Segment { kind, stmts, .. }] = program.segments.as_slice() else {
+        panic!("Expected a single segment");
+    };
+    assert_eq!(*kind, SegmentKind::Text);
+    let kinds = stmts
+        .iter()
+        .map(|s| match s {
+            Statement::Instruction(i) => (
+                i.kind,
+                i.operands.iter().map(|o| &o.kind).collect::<Vec<_>>(),
+            ),
+            _ => panic!("Expected only instructions"),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        vec![
+            (
+                InstructionKind::Lw,
+                vec![
+                    &OperandKind::Register(Register::Name(RegisterName::Ra)),
+                    &OperandKind::Address(Address {
+                        offset: Immediate::Number(-8),
+                        base: Some(Register::Name(RegisterName::Sp))
+                    })
+                ]
+            ),
+            (InstructionKind::J, vec![&OperandKind::Label("end".into())]),
+            (InstructionKind::Syscall, vec![]),
+        ]
+    );
+}
+
+#[test]
+fn unexpected_tokens() {
+    let inputs = [
+        "main .text",
+        "lw $t0, 4($t1",
+        "(",
+        "sub $t0, -$t1",
+        "add $t0, $t1)",
+    ];
+    let expected = [
+        (STATEMENT_START, 0..4),
+        ("`)`", 13..14),
+        (STATEMENT_START, 0..1),
+        ("an expression", 10..13),
+        ("a comma or the end of the line", 12..13),
+    ];
+    for (input, (expected, span)) in inputs.into_iter().zip(expected) {
+        assert_eq!(
+            parse(input),
+            Err(ParserError::new(
+                ParserErrorKind::UnexpectedToken(expected.into()),
+                span
+            ))
+        );
+    }
+}
+
+#[test]
+fn errors() {
+    let word_usage = Directive::Word.usage().to_owned();
+    let cases = [
+        ("add $t0 $t1", ParserErrorKind::MissingComma, 8..11),
+        (
+            ".data\n.word",
+            ParserErrorKind::WrongOperandCount {
+                found: 0,
+                usage: word_usage.clone(),
+            },
+            6..11,
+        ),
+        (
+            ".data\n.word 1, \"two\"",
+            ParserErrorKind::WrongOperandKind { usage: word_usage },
+            15..20,
+        ),
+        (
+            ".align 2\n.asciiz \"str\"",
+            ParserErrorKind::DirectiveOutsideSegment {
+                directive: Directive::Asciiz,
+                segment: SegmentKind::Text,
+            },
+            9..16,
+        ),
+        (
+            ".data\n.word 1\n.data 0x10020000",
+            ParserErrorKind::SegmentAlreadyStarted(SegmentKind::Data),
+            20..30,
+        ),
+        (
+            ".text 0x10000000",
+            ParserErrorKind::ValueOutOfRange {
+                range: 0..=0x0fff_ffff,
+                expected: "The text segment must start below 0x10000000".into(),
+            },
+            6..16,
+        ),
+        (
+            ".kdata\nsyscall",
+            ParserErrorKind::InstructionInDataSegment(SegmentKind::Kdata),
+            7..14,
+        ),
+        (
+            ".data\n.word 1:0",
+            ParserErrorKind::ValueOutOfRange {
+                range: 1..=u32::MAX as i64,
+                expected: "The repeat count must be a positive 32 bit number".into(),
+            },
+            14..15,
+        ),
+        (
+            ".data\n.half 1:x",
+            ParserErrorKind::ExpectedConstant,
+            14..15,
+        ),
+        (
+            ".data\n.asciiz \"a\":2",
+            ParserErrorKind::UnexpectedToken("a comma or the end of the line".into()),
+            17..18,
+        ),
+        (
+            "addi $t0, $t1, 0:2",
+            ParserErrorKind::WrongOperandKind {
+                usage: InstructionKind::Addi.usage(),
+            },
+            15..18,
+        ),
+        (
+            ".data\n.float 1.5, -3.5e38:2",
+            ParserErrorKind::FloatOutOfRange,
+            18..27,
+        ),
+        (
+            "addi $t0, $t1, 1.5",
+            ParserErrorKind::WrongOperandKind {
+                usage: InstructionKind::Addi.usage(),
+            },
+            15..18,
+        ),
+        (
+            ".data\n.word 2.5",
+            ParserErrorKind::WrongOperandKind {
+                usage: Directive::Word.usage().to_owned(),
+            },
+            12..15,
+        ),
+    ];
+    for (input, kind, span) in cases {
+        assert_eq!(parse(input), Err(ParserError::new(kind, span)));
+    }
+}
+
+#[test]
+fn operand_signatures() {
+    let valid = "add $t0, $t1, $t2
+addi $t0, $t1, -1
+lw $t0, 4($sp)
+beq $t0, $t1, end
+j end
+jalr $t0
+jalr $t1, $t0
+syscall
+sll $t0, $t1, 2
+break
+break 3
+b: add.s $f0, $f2, $f4
+cvt.w.d $f0, $f2
+c.lt.s 1, $f0, $f1
+bc1t b
+lwc1 $f0, 4($sp)
+mfc1 $t0, $f0";
+    assert!(parse(valid).is_ok());
+
+    let usage = |kind: InstructionKind| kind.usage();
+    assert_eq!(usage(InstructionKind::Addi), "addi expects `$rt, $rs, imm`");
+    assert_eq!(usage(InstructionKind::Eret), "eret expects no operands");
+    assert_eq!(
+        usage(InstructionKind::Jalr),
+        "jalr expects `$rs` or `$rd, $rs`"
+    );
+    let cases = [
+        (
+            "addi $t0, $t1, $t2",
+            ParserErrorKind::WrongOperandKind {
+                usage: usage(InstructionKind::Addi),
+            },
+            15..18,
+        ),
+        (
+            "lw $t0, $t1",
+            ParserErrorKind::WrongOperandKind {
+                usage: usage(InstructionKind::Lw),
+            },
+            8..11,
+        ),
+        (
+            "add $t0, $t1",
+            ParserErrorKind::WrongOperandCount {
+                found: 2,
+                usage: usage(InstructionKind::Add),
+            },
+            0..12,
+        ),
+        (
+            "syscall $t0, 4",
+            ParserErrorKind::WrongOperandCount {
+                found: 2,
+                usage: usage(InstructionKind::Syscall),
+            },
+            8..14,
+        ),
+        (
+            "j $ra",
+            ParserErrorKind::WrongOperandKind {
+                usage: usage(InstructionKind::J),
+            },
+            2..5,
+        ),
+        // general purpose and floating point registers are not interchangeable
+        (
+            "add.s $f0, $t1, $f2",
+            ParserErrorKind::WrongOperandKind {
+                usage: usage(InstructionKind::AddS),
+            },
+            11..14,
+        ),
+        (
+            "lw $t0, 4($f2)",
+            ParserErrorKind::WrongOperandKind {
+                usage: usage(InstructionKind::Lw),
+            },
+            8..14,
+        ),
+    ];
+    for (input, kind, span) in cases {
+        assert_eq!(parse(input), Err(ParserError::new(kind, span)));
+    }
+}
+
+#[test]
+fn addressing_forms() {
+    let input = "lw $t0, 4($sp)
+sb $t0, ($sp)
+lwl $t0, 100
+ll $t0, array
+sc $t0, array+8
+pref 1, array($t1)
+cache 2, array-4($t1)";
+    let program = parse(input).unwrap();
+    let instructions = program.segments[0]
+        .stmts
+        .iter()
+        .map(|s| match s {
+            Statement::Instruction(i) => (i.kind, i.operands.last().unwrap().kind.clone()),
+            _ => panic!("Expected only instructions"),
+        })
+        .collect::<Vec<_>>();
+    let t1 = || Register::PrefixedNumber(RegisterPrefixedName::new_unchecked('t', 1));
+    let at = || Register::Name(RegisterName::At);
+    let address = |offset, base| OperandKind::Address(Address { offset, base });
+    let label = |offset| Immediate::Label("array".into(), offset);
+    let hi = |imm| OperandKind::Immediate(Immediate::Hi(Box::new(imm)));
+    let lo = |imm| Immediate::Lo(Box::new(imm));
+    use InstructionKind::*;
+    assert_eq!(
+        instructions,
+        vec![
+            (
+                Lw,
+                address(Immediate::Number(4), Some(Register::Name(RegisterName::Sp)))
+            ),
+            (
+                Sb,
+                address(Immediate::Number(0), Some(Register::Name(RegisterName::Sp)))
+            ),
+            (
+                Lwl,
+                address(Immediate::Number(100), Some(Register::Number(0)))
+            ),
+            (Lui, hi(label(0))),
+            (Ll, address(lo(label(0)), Some(at()))),
+            (Lui, hi(label(8))),
+            (Sc, address(lo(label(8)), Some(at()))),
+            (Lui, hi(label(0))),
+            (Addu, OperandKind::Register(t1())),
+            (Pref, address(lo(label(0)), Some(at()))),
+            (Lui, hi(label(-4))),
+            (Addu, OperandKind::Register(t1())),
+            (Cache, address(lo(label(-4)), Some(at()))),
+        ]
+    );
+}
+
+#[test]
+fn error_recovery() {
+    let input = "add $t0 $t1
+lw $t0, 4($t1
+ok: sub $t0, $t1, $t2
+j
+";
+    let tokens = Lexer::new(input).lex().unwrap();
+    let errors = Parser::new(tokens.clone().into_iter()).parse().unwrap_err();
+    assert_eq!(
+        errors,
+        vec![
+            ParserError::new(ParserErrorKind::MissingComma, 8..11),
+            ParserError::new(ParserErrorKind::UnexpectedToken("`)`".into()), 25..26),
+            ParserError::new(
+                ParserErrorKind::WrongOperandCount {
+                    found: 0,
+                    usage: InstructionKind::J.usage()
+                },
+                48..49
+            ),
+        ]
+    );
+    let errors = Parser::new(tokens.into_iter())
+        .with_max_errors(1)
+        .parse()
+        .unwrap_err();
+    assert_eq!(errors.len(), 1);
+}
+
+#[test]
+fn warnings() {
+    let input = ".data
+bytes: .byte 1, 300, -129
+.text
+main:
+    add $zero, $t0, $t1
+    lw $t0, 4($at)
+    j end
+    sub $k0, $t0, $t1
+unused:
+end:
+    jr $ra
+.ktext
+    mfc0 $k0, $13";
+    let tokens = Lexer::new(input).lex().unwrap();
+    let (_, warnings) = Parser::new(tokens.into_iter()).parse().unwrap();
+    let k0 = Register::PrefixedNumber(RegisterPrefixedName::new_unchecked('k', 0));
+    assert_eq!(
+        warnings,
+        vec![
+            Warning::new(WarningKind::TruncatedValue(Directive::Byte), 22..25),
+            Warning::new(WarningKind::TruncatedValue(Directive::Byte), 27..31),
+            Warning::new(WarningKind::WriteToZero, 52..57),
+            Warning::new(
+                WarningKind::ReservedRegister(Register::Name(RegisterName::At)),
+                80..86
+            ),
+            Warning::new(WarningKind::UnreachableCode, 101..118),
+            Warning::new(WarningKind::ReservedRegister(k0), 105..108),
+            Warning::new(WarningKind::UnusedLabel("bytes".into()), 6..11),
+            Warning::new(WarningKind::UnusedLabel("unused".into()), 119..125),
+        ]
+    );
+}
+
+#[test]
+fn expressions() {
+    let input = ".eqv SIZE 4
+.set COUNT, SIZE * 2 + 1
+.set noat
+.data
+array: .space COUNT*SIZE
+.word array + SIZE, end - array
+.text
+    addi $t0, $t0, (SIZE*4)+1
+    ori $t0, $t0, ~0 & 0xff | 1 << 8
+    sll $t0, $t0, -SIZE % 3 + 2
+    lw $t0, SIZE * 2($sp)
+    lw $t0, array+SIZE*2
+end:";
+    let program = parse(input).unwrap();
+    let operands = program
+        .segments
+        .iter()
+        .flat_map(|s| &s.stmts)
+        .filter_map(|s| match s {
+            Statement::Instruction(i) => Some(i.operands.last().unwrap().kind.clone()),
+            Statement::Data(d) => Some(d.operands.last().unwrap().kind.clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let number = |num| OperandKind::Immediate(Immediate::Number(num));
+    let sp = Some(Register::Name(RegisterName::Sp));
+    let at = Some(Register::Name(RegisterName::At));
+    let label = |offset| Immediate::Label("array".into(), offset);
+    // the text segment comes first, as the constants are defined in it
+    let OperandKind::Immediate(Immediate::Expr(difference)) = &operands[7] else {
+        panic!("Expected an expression");
+    };
+    assert_eq!(difference.to_string(), "(end - array)");
+    assert_eq!(difference.span, 98..109);
+    assert_eq!(
+        operands,
+        vec![
+            number(17),
+            number(0x1ff),
+            number(1),
+            OperandKind::Address(Address {
+                offset: Immediate::Number(8),
+                base: sp
+            }),
+            OperandKind::Immediate(Immediate::Hi(Box::new(label(8)))),
+            OperandKind::Address(Address {
+                offset: Immediate::Lo(Box::new(label(8))),
+                base: at
+            }),
+            number(36),
+            operands[7].clone(),
+        ]
+    );
+
+    let cases = [
+        (
+            ".eqv N 0\naddi $t0, $t0, 4 / (N * 2)",
+            ParserErrorKind::DivisionByZero,
+            28..35,
+        ),
+        (
+            "addi $t0, $t0, 1 << 64",
+            ParserErrorKind::ShiftOutOfRange(64),
+            20..22,
+        ),
+        (
+            ".data\n.word x >> -1\nx:",
+            ParserErrorKind::ShiftOutOfRange(-1),
+            17..19,
+        ),
+        (
+            "addi $t0, $t0, (-0x7fffffffffffffff - 1) / -1",
+            ParserErrorKind::Overflow,
+            15..45,
+        ),
+        (
+            "addi $t0, $t0, 0x4000 * 2",
+            ParserErrorKind::ValueOutOfRange {
+                range: -32768..=32767,
+                expected: "`addi` expects a signed 16 bit immediate here".into(),
+            },
+            15..25,
+        ),
+        (
+            "andi $t0, $t0, -1",
+            ParserErrorKind::ValueOutOfRange {
+                range: 0..=65535,
+                expected: "`andi` expects a zero extended 16 bit immediate here".into(),
+            },
+            15..17,
+        ),
+        (
+            "sll $t0, $t0, 32",
+            ParserErrorKind::ValueOutOfRange {
+                range: 0..=31,
+                expected: "`sll` expects a 5 bit shift amount here".into(),
+            },
+            14..16,
+        ),
+        (
+            ".data\n.space -1",
+            ParserErrorKind::ValueOutOfRange {
+                range: 0..=u32::MAX as i64,
+                expected: "`.space` expects a number of bytes that fits in 32 bits".into(),
+            },
+            13..15,
+        ),
+        (
+            ".data\n.space 0x100000000",
+            ParserErrorKind::ValueOutOfRange {
+                range: 0..=u32::MAX as i64,
+                expected: "`.space` expects a number of bytes that fits in 32 bits".into(),
+            },
+            13..24,
+        ),
+        (
+            ".data\n.align 40",
+            ParserErrorKind::ValueOutOfRange {
+                range: 0..=31,
+                expected: "`.align` expects the power of two of a 32 bit alignment".into(),
+            },
+            13..15,
+        ),
+        (
+            ".data\n.align -1",
+            ParserErrorKind::ValueOutOfRange {
+                range: 0..=31,
+                expected: "`.align` expects the power of two of a 32 bit alignment".into(),
+            },
+            13..15,
+        ),
+        (
+            ".data\n.space label",
+            ParserErrorKind::WrongOperandKind {
+                usage: Directive::Space.usage().to_owned(),
+            },
+            13..18,
+        ),
+        (
+            "addi $t0, $t0, (1 + 2",
+            ParserErrorKind::UnexpectedToken("`)`".into()),
+            21..22,
+        ),
+    ];
+    for (input, kind, span) in cases {
+        assert_eq!(parse(input), Err(ParserError::new(kind, span)));
+    }
+}
+
+#[test]
+fn wide_literals() {
+    let input = ".data
+.word 0xDEADBEEF, -0x80000000
+.text
+    lui $t0, 0xFFFF
+    ori $t0, $t0, 0xffff
+    lw $t0, 0x10010004($t1)";
+    let tokens = Lexer::new(input).lex().unwrap();
+    let (program, warnings) = Parser::new(tokens.into_iter()).parse().unwrap();
+    assert_eq!(warnings, vec![]);
+    let text = program
+        .segments
+        .iter()
+        .find(|s| s.kind == SegmentKind::Text)
+        .unwrap();
+    let last = text
+        .stmts
+        .iter()
+        .map(|s| match s {
+            Statement::Instruction(i) => (i.kind, i.operands.last().unwrap().kind.clone()),
+            _ => panic!("Expected only instructions"),
+        })
+        .collect::<Vec<_>>();
+    let offset = || Box::new(Immediate::Number(0x1001_0004));
+    assert_eq!(
+        last,
+        vec![
+            (
+                InstructionKind::Lui,
+                OperandKind::Immediate(Immediate::Number(0xffff))
+            ),
+            (
+                InstructionKind::Ori,
+                OperandKind::Immediate(Immediate::Number(0xffff))
+            ),
+            (
+                InstructionKind::Lui,
+                OperandKind::Immediate(Immediate::Hi(offset()))
+            ),
+            (
+                InstructionKind::Addu,
+                OperandKind::Register(Register::PrefixedNumber(
+                    RegisterPrefixedName::new_unchecked('t', 1)
+                ))
+            ),
+            (
+                InstructionKind::Lw,
+                OperandKind::Address(Address {
+                    offset: Immediate::Lo(offset()),
+                    base: Some(Register::Name(RegisterName::At))
+                })
+            ),
+        ]
+    );
+
+    let cases = [
+        (
+            "addi $t0, $t0, 40000",
+            15..20,
+            -32768..=32767,
+            "`addi` expects a signed 16 bit immediate",
+        ),
+        (
+            "lui $t0, 0x10000",
+            9..16,
+            0..=65535,
+            "`lui` expects a zero extended 16 bit immediate",
+        ),
+        (
+            "srl $t0, $t0, 0x80000000",
+            14..24,
+            0..=31,
+            "`srl` expects a 5 bit shift amount",
+        ),
+        (
+            "sw $t0, 0x100000000",
+            8..19,
+            i32::MIN as i64..=u32::MAX as i64,
+            "`sw` expects a 32 bit address",
+        ),
+    ];
+    for (input, span, range, expected) in cases {
+        assert_eq!(
+            parse(input),
+            Err(ParserError::new(
+                ParserErrorKind::ValueOutOfRange {
+                    range,
+                    expected: format!("{expected} here"),
+                },
+                span
+            ))
+        );
+    }
+}
+
+#[test]
+fn char_literals() {
+    let input = ".data
+.byte 'a', '\\n':2
+.text
+    ori $a0, $zero, 'A' + 1
+    addi $t0, $t0, -'0'";
+    let program = parse(input).unwrap();
+    let operands = program
+        .segments
+        .iter()
+        .flat_map(|s| &s.stmts)
+        .flat_map(|s| match s {
+            Statement::Instruction(i) => vec![i.operands.last().unwrap().kind.clone()],
+            Statement::Data(d) => d.operands.iter().map(|o| o.kind.clone()).collect(),
+            _ => Vec::new(),
+        })
+        .collect::<Vec<_>>();
+    let number = |num| OperandKind::Immediate(Immediate::Number(num));
+    assert_eq!(
+        operands,
+        vec![
+            number(97),
+            OperandKind::Repeat(Box::new(number(10)), 2),
+            number(66),
+            number(-48),
+        ]
+    );
+}
+
+#[test]
+fn dialects() {
+    let parse = |input, dialect| {
+        let tokens = Lexer::new(input).with_dialect(dialect).lex().unwrap();
+        Parser::new(tokens.into_iter())
+            .with_dialect(dialect)
+            .parse()
+            .map(|(program, _)| program)
+            .map_err(|mut errs| errs.remove(0))
+    };
+    let input = ".section .data
+x: .word 1
+.section .text
+lw $t0, x";
+    let program = parse(input, Dialect::Gnu).unwrap();
+    let kinds = program.segments.iter().map(|s| s.kind).collect::<Vec<_>>();
+    assert_eq!(kinds, [SegmentKind::Data, SegmentKind::Text]);
+    assert_eq!(
+        parse(".section x", Dialect::Gnu),
+        Err(ParserError::new(
+            ParserErrorKind::UnexpectedToken("a segment like `.text` or `.data`".into()),
+            9..10
+        ))
+    );
+
+    // `$at` can't be used to expand an address after `.set noat`
+    let input = ".set noat
+lw $t0, 4($t1)
+lw $t0, x
+.set at
+lw $t0, x";
+    assert_eq!(
+        parse(input, Dialect::Spim),
+        Err(ParserError::new(ParserErrorKind::AtUnavailable, 33..34))
+    );
+    assert!(parse(input, Dialect::Mars).is_ok());
+    assert!(parse(&input.replace("lw $t0, x\n.set", ".set"), Dialect::Gnu).is_ok());
+}
+
+#[test]
+fn pseudo_instructions() {
+    let input = "li $t0, 0x12345678
+blt $t0, 5, end
+div $t0, $t1
+end: div $zero, $t1, $t2";
+    let tokens = Lexer::new(input).lex().unwrap();
+    let (program, warnings) = Parser::new(tokens.into_iter()).parse().unwrap();
+    let instructions = program.segments[0]
+        .stmts
+        .iter()
+        .filter_map(|s| match s {
+            Statement::Instruction(i) => Some((i.kind, i.span.clone())),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    // every generated instruction points at the pseudo-instruction
+    use InstructionKind::*;
+    assert_eq!(
+        instructions,
+        vec![
+            (Lui, 0..18),
+            (Ori, 0..18),
+            (Slti, 19..34),
+            (Bne, 19..34),
+            (Div, 35..47),
+            (Div, 53..72),
+            (Mflo, 53..72),
+        ]
+    );
+    assert_eq!(
+        warnings,
+        vec![Warning::new(WarningKind::WriteToZero, 57..62)]
+    );
+
+    let usage = PseudoKind::Li.usage();
+    assert_eq!(usage, "li expects `$rt, imm`");
+    assert_eq!(
+        parse("li $t0"),
+        Err(ParserError::new(
+            ParserErrorKind::WrongOperandCount { found: 1, usage },
+            0..6
+        ))
+    );
+    assert_eq!(
+        parse("li $t0, 0x100000000"),
+        Err(ParserError::new(
+            ParserErrorKind::ValueOutOfRange {
+                range: i32::MIN as i64..=u32::MAX as i64,
+                expected: "`li` expects a 32 bit immediate here".into(),
+            },
+            8..19
+        ))
+    );
+    // the machine instruction is reported when neither form accepts the operands
+    assert_eq!(
+        parse("div $t0, 4"),
+        Err(ParserError::new(
+            ParserErrorKind::WrongOperandKind {
+                usage: InstructionKind::Div.usage()
+            },
+            9..10
+        ))
+    );
+
+    // only the expansions that need a temporary register use `$at`
+    let input = ".set noat
+li $t0, 0x12345678
+la $t0, 4($sp)
+abs $t0, $t1";
+    let tokens = Lexer::new(input).with_dialect(Dialect::Spim).lex().unwrap();
+    assert_eq!(
+        Parser::new(tokens.into_iter())
+            .with_dialect(Dialect::Spim)
+            .parse()
+            .map_err(|mut errs| errs.remove(0))
+            .map(|_| ()),
+        Err(ParserError::new(
+            ParserErrorKind::PseudoNeedsAt(PseudoKind::Abs),
+            44..56
+        ))
+    );
+}
+
+#[test]
+fn local_labels() {
+    let input = "1: addi $t0, $t0, -1
+    bne $t0, $zero, 1b
+    j 1f
+1: j 1b + 4";
+    let program = parse(input).unwrap();
+    let names = program.segments[0]
+        .stmts
+        .iter()
+        .filter_map(|s| match s {
+            Statement::Label { name, .. } => Some(name.clone()),
+            Statement::Instruction(i) => i
+                .operands
+                .last()
+                .unwrap()
+                .kind
+                .labels()
+                .first()
+                .map(|l| l.to_string()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    // each reference points to the closest definition in its direction
+    assert_eq!(names, ["1:0", "1:0", "1:1", "1:1", "1:1"]);
+
+    assert_eq!(
+        parse("b 2b\n2: nop"),
+        Err(ParserError::new(
+            ParserErrorKind::UndefinedLocalLabel {
+                number: 2,
+                forward: false
+            },
+            2..4
+        ))
+    );
+    assert_eq!(
+        parse("2: nop\nb 2f"),
+        Err(ParserError::new(
+            ParserErrorKind::UndefinedLocalLabel {
+                number: 2,
+                forward: true
+            },
+            9..11
+        ))
+    );
+}
+
+#[test]
+fn relocation_operators() {
+    let program = parse("lui $t0, %hi(x + 4)\nlw $t1, %lo(x)($t0)").unwrap();
+    let operands = program.segments[0]
+        .stmts
+        .iter()
+        .map(|s| match s {
+            Statement::Instruction(i) => i.operands[1].kind.clone(),
+            _ => panic!("Expected only instructions"),
+        })
+        .collect::<Vec<_>>();
+    let t0 = Register::PrefixedNumber(RegisterPrefixedName::new_unchecked('t', 0));
+    // `%lo` fits in the offset, so the load is not expanded
+    assert_eq!(
+        operands,
+        [
+            OperandKind::Immediate(Immediate::Hi(Box::new(Immediate::Label("x".into(), 4)))),
+            OperandKind::Address(Address {
+                offset: Immediate::Lo(Box::new(Immediate::Label("x".into(), 0))),
+                base: Some(t0),
+            }),
+        ]
+    );
+    assert_eq!(
+        parse("lui $t0, %hi(x"),
+        Err(ParserError::new(
+            ParserErrorKind::UnexpectedToken("`)`".into()),
+            14..15
+        ))
+    );
+    assert_eq!(
+        parse("lui $t0, %hi(x) + 1"),
+        Err(ParserError::new(
+            ParserErrorKind::UnexpectedToken("a comma or the end of the line".into()),
+            16..17
+        ))
+    );
+}
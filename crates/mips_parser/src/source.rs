@@ -0,0 +1,179 @@
+use std::ops::Range;
+
+/// Identifies a file added to a [`SourceDb`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FileId(usize);
+
+/// A position in a file, the line and the column both start from 1.
+/// The column counts characters, not bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub file: FileId,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug)]
+struct SourceFile {
+    name: String,
+    /// The position of the file in the text of the database
+    range: Range<usize>,
+    /// The offsets where each line starts, relative to the start of the file
+    line_starts: Vec<usize>,
+}
+
+/// Holds the text of all the files that make up a program, either passed as input or
+/// included with `.include`.
+///
+/// The files are laid out one after the other as if they were a single text, so a byte span
+/// identifies both the file and the position inside it. Tokens, errors and statements don't
+/// store a [`FileId`] next to their span: every span is a position in the database, and
+/// [`SourceDb::locate`] is the way to find the file, line and column it points to.
+#[derive(Debug, Default)]
+pub struct SourceDb {
+    text: String,
+    files: Vec<SourceFile>,
+}
+
+impl SourceDb {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file to the database. The name is shown in the diagnostics and, if it's a path,
+    /// the files it includes are searched relative to it.
+    pub fn add_file(&mut self, name: impl Into<String>, content: &str) -> FileId {
+        let start = self.text.len();
+        self.text.push_str(content);
+        let line_starts = std::iter::once(0)
+            .chain(content.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        self.files.push(SourceFile {
+            name: name.into(),
+            range: start..self.text.len(),
+            line_starts,
+        });
+        // files are separated by a newline, so that the span of the end of a file doesn't
+        // point to the start of the next one
+        self.text.push('\n');
+        FileId(self.files.len() - 1)
+    }
+
+    /// Returns all the files, in the order they were added
+    pub fn files(&self) -> impl Iterator<Item = FileId> {
+        (0..self.files.len()).map(FileId)
+    }
+
+    /// Returns the file with the given name, if it was added
+    pub fn find(&self, name: &str) -> Option<FileId> {
+        self.files.iter().position(|f| f.name == name).map(FileId)
+    }
+
+    pub fn name(&self, file: FileId) -> &str {
+        &self.files[file.0].name
+    }
+
+    pub fn content(&self, file: FileId) -> &str {
+        &self.text[self.files[file.0].range.clone()]
+    }
+
+    /// Returns the span the whole file occupies in the database
+    pub fn span(&self, file: FileId) -> Range<usize> {
+        self.files[file.0].range.clone()
+    }
+
+    /// The text of all the files, which the spans point into
+    pub(crate) fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns the file that contains the position. The position right after the end of a file,
+    /// where its end of file token is, also belongs to it.
+    pub fn file_of(&self, offset: usize) -> FileId {
+        let index = self
+            .files
+            .partition_point(|f| f.range.start <= offset)
+            .saturating_sub(1);
+        FileId(index)
+    }
+
+    /// Returns the file the span points into and the span relative to the start of that file
+    pub fn resolve(&self, span: &Range<usize>) -> (FileId, Range<usize>) {
+        let file = self.file_of(span.start);
+        let start = self.files[file.0].range.start;
+        (file, span.start - start..span.end - start)
+    }
+
+    /// Returns the file a span points into, and the line and column where the span starts.
+    /// This is the position to show for any span of a token, an error or a statement.
+    pub fn locate(&self, span: &Range<usize>) -> (FileId, usize, usize) {
+        let Location { file, line, column } = self.location(span.start);
+        (file, line, column)
+    }
+
+    /// Returns the line and column of a position
+    pub fn location(&self, offset: usize) -> Location {
+        let (file, span) = self.resolve(&(offset..offset));
+        let source = &self.files[file.0];
+        let line = source.line_starts.partition_point(|s| *s <= span.start);
+        let line_start = source.line_starts[line - 1];
+        let content = self.content(file);
+        let column = content
+            .get(line_start..span.start.min(content.len()))
+            .map_or(0, |s| s.chars().count());
+        Location {
+            file,
+            line,
+            column: column + 1,
+        }
+    }
+
+    /// Returns the text of a line, without the newline, the first line is 1
+    pub fn line(&self, file: FileId, line: usize) -> Option<&str> {
+        let source = &self.files[file.0];
+        let start = *source.line_starts.get(line.checked_sub(1)?)?;
+        let end = source
+            .line_starts
+            .get(line)
+            .map_or(source.range.len(), |end| end - 1);
+        Some(&self.content(file)[start..end])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locations() {
+        let mut db = SourceDb::new();
+        let main = db.add_file("main.asm", "main:\n    j è\n");
+        let lib = db.add_file("lib.asm", "f: jr $ra");
+        assert_eq!(db.content(lib), "f: jr $ra");
+        assert_eq!(db.span(lib), 16..25);
+        assert_eq!(db.file_of(15), main);
+        assert_eq!(db.file_of(16), lib);
+        assert_eq!(db.resolve(&(19..21)), (lib, 3..5));
+        assert_eq!(
+            db.location(0),
+            Location {
+                file: main,
+                line: 1,
+                column: 1
+            }
+        );
+        // `è` takes two bytes but is one character
+        let location = db.location(db.span(main).start + 14);
+        assert_eq!((location.line, location.column), (2, 8));
+        let location = db.location(db.span(main).end);
+        assert_eq!((location.line, location.column), (3, 1));
+        let location = db.location(22);
+        assert_eq!((location.file, location.line, location.column), (lib, 1, 7));
+        assert_eq!(db.line(main, 2), Some("    j è"));
+        assert_eq!(db.line(lib, 1), Some("f: jr $ra"));
+        assert_eq!(db.line(lib, 2), None);
+        assert_eq!(db.find("lib.asm"), Some(lib));
+        assert_eq!(db.locate(&(19..21)), (lib, 1, 4));
+        assert_eq!(db.locate(&(6..10)), (main, 2, 1));
+    }
+}
@@ -0,0 +1,12 @@
+# GNU as: numeric local labels can be defined many times
+    .text
+    .globl main
+main:
+    addi $t0, $zero, 10
+1:  addi $t0, $t0, -1
+    beq $t0, $zero, 1f
+    b 1b
+1:  addi $t1, $zero, 3
+1:  addi $t1, $t1, -1
+    bne $t1, $zero, 1b
+    jr $ra
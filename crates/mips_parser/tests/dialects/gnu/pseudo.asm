@@ -0,0 +1,13 @@
+# GNU as: pseudo-instructions, there is no `subi`
+    .data
+count:  .word 3
+    .text
+    .globl main
+main:
+    lw $t0, count
+    li $t1, 0x12345678
+    subu $t0, $t0, $t1; neg $t0, $t0
+    rem $t2, $t0, 7
+    bgeu $t2, $t1, 1f
+    move $v0, $t2
+1:  jr $ra
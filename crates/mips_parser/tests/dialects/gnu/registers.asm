@@ -0,0 +1,10 @@
+# GNU as: `$s8` and the `$r` names of the registers
+    .text
+    .globl main
+main:
+    add $s8, $sp, $r0
+    sw $r31, 4($s8)
+    .set noat
+    addu $at, $r4, $r5
+    .set at
+    jr $ra
@@ -0,0 +1,16 @@
+# GNU as: the address of a label built from its halves, and small data near `$gp`
+    .data
+counter:
+    .word 0
+buffer:
+    .space 64
+    .text
+    .globl main
+main:
+    lui $t0, %hi(buffer)
+    addiu $t0, $t0, %lo(buffer)
+    lw $t1, %gp_rel(counter)($gp)
+    addiu $t1, $t1, 1
+    sw $t1, %gp_rel(counter)($gp)
+    sw $t1, 4($t0)
+    jr $ra
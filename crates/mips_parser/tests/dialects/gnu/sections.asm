@@ -0,0 +1,11 @@
+/* GNU as: `.section`, `.global` and C style comments */
+    .section .data
+value:  .word 0x10, 010     /* 010 is octal */
+    .section .text
+    .global main
+main:
+    lw $t0, value; addi $t0, $t0, 1
+    /* a comment
+       over more lines */
+    sw $t0, value
+    jr $ra
@@ -0,0 +1,14 @@
+# MARS: `.eqv` constants, `.kdata`, and numbers with a leading zero are decimal
+.eqv SIZE, 010
+.data
+buffer: .space SIZE
+count: .word 010, 0x10, 0b10
+.kdata
+saved: .word 0
+.text
+.globl main
+main:
+    lw $t0, count
+    addi $t0, $t0, SIZE
+    sw $t0, buffer
+    li_done: jr $ra
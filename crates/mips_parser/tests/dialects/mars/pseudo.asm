@@ -0,0 +1,33 @@
+# MARS: pseudo-instructions, expanded with `$at`
+.data
+values: .word 3, -7, 12, 0
+message: .asciiz "sum of the absolute values: "
+.text
+main:
+    la $s0, values
+    li $s1, 0
+loop:
+    lw $t0, 0($s0)
+    beqz $t0, done
+    abs $t0, $t0
+    add $s1, $s1, $t0
+    addi $s0, $s0, 4
+    bge $s1, 0x12345, done
+    b loop
+done:
+    li $v0, 4
+    la $a0, message
+    syscall
+    move $a0, $s1
+    li $v0, 1
+    syscall
+    rem $t1, $s1, 10
+    seq $t2, $t1, 2
+    blt $t2, 1, exit
+    mul $t3, $s1, -3
+    subi $t5, $s1, 1
+    subiu $t6, $s1, 0x12345
+    ulw $t4, message
+exit:
+    li $v0, 10
+    syscall
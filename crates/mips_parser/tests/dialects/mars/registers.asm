@@ -0,0 +1,7 @@
+# MARS: `$fp` and the numbered registers
+.text
+main:
+    move_fp: add $fp, $sp, $zero
+    lw $t0, 100000($30)
+    sw $t1, -4($fp)
+    jr $ra
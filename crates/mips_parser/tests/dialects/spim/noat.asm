@@ -0,0 +1,8 @@
+# SPIM: `.set noat` lets the program use `$at`
+.text
+main:
+    .set noat
+    add $at, $t0, $t1
+    .set at
+    lw $t0, 100000($t1)
+    jr $ra
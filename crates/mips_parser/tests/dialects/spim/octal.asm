@@ -0,0 +1,9 @@
+# SPIM: numbers with a leading zero are octal
+.data
+mask: .word 0777, 017
+.text
+.globl main
+main:
+    lw $t0, mask
+    andi $t1, $t0, 0777
+    jr $ra
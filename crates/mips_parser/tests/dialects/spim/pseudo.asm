@@ -0,0 +1,15 @@
+# SPIM: pseudo-instructions, `sub` stands in for the `subi` of MARS
+.data
+values: .word 5, -2, 9
+.text
+main:
+    la $t0, values
+    lw $t1, 4($t0)
+    abs $t1, $t1
+    li $t2, 1
+    sub $t1, $t1, $t2
+    sge $t3, $t1, 0x10000
+    bnez $t3, done
+    ulw $t4, 1($t0)
+done:
+    jr $ra
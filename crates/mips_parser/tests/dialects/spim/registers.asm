@@ -0,0 +1,7 @@
+# SPIM: `$s8` is another name for `$fp`
+.text
+main:
+    add $s8, $sp, $zero
+    sw $ra, 4($s8)
+    lw $ra, 4($s8)
+    jr $ra
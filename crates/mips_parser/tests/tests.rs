@@ -1,6 +1,11 @@
 use instruction_encoding_derive::InstructionEncoding;
+use mips_parser::cst::SyntaxTree;
+use mips_parser::defs::dialect::Dialect;
 use mips_parser::defs::instruction::{InstructionEncoding, InstructionFormat};
+use mips_parser::defs::program::{SegmentKind, StorableData};
 use mips_parser::defs::Bits;
+use mips_parser::source::SourceDb;
+use mips_parser::{CompileOutput, MipsCompiler, WarningName};
 
 #[test]
 fn instruction_derive_macro() {
@@ -12,6 +17,10 @@ fn instruction_derive_macro() {
         Jump,
         #[instruction(0b010000, I)]
         Addi,
+        #[instruction(0b000001, I, rt = 0b00001)]
+        Bgez,
+        #[instruction(0b011111, R, 0b100000, shamt = 0b10000, rs = 0)]
+        Seb,
     }
     let add = Instruction::Add;
     let addi = Instruction::Addi;
@@ -26,4 +35,188 @@ fn instruction_derive_macro() {
     assert_eq!(addi.opcode(), Bits::new(0b010000));
     assert_eq!(addi.format(), InstructionFormat::I);
     assert_eq!(addi.funct(), None);
+    assert_eq!(addi.rt(), None);
+
+    let bgez = Instruction::Bgez;
+    assert_eq!(bgez.opcode(), Bits::new(0b000001));
+    assert_eq!(bgez.rs(), None);
+    assert_eq!(bgez.rt(), Some(Bits::new(0b00001)));
+    let seb = Instruction::Seb;
+    assert_eq!(seb.funct(), Some(Bits::new(0b100000)));
+    assert_eq!(seb.rs(), Some(Bits::new(0)));
+    assert_eq!(seb.rt(), None);
+    assert_eq!(seb.rd(), None);
+    assert_eq!(seb.shamt(), Some(Bits::new(0b10000)));
+}
+
+#[test]
+fn warning_config() {
+    let input = "main:
+    add $zero, $t0, $t1
+unused: # @allow(unused-label)
+    jr $ra";
+    let mut sources = SourceDb::new();
+    let file = sources.add_file("main.asm", input);
+    let output = MipsCompiler::new(&mut sources, file).compile().unwrap();
+    assert_eq!(
+        output.warnings.iter().map(|w| w.name()).collect::<Vec<_>>(),
+        vec![WarningName::WriteToZero]
+    );
+    let output = MipsCompiler::new(&mut sources, file)
+        .allow_warning("write-to-zero".parse().unwrap())
+        .compile()
+        .unwrap();
+    assert!(output.warnings.is_empty());
+    let err = MipsCompiler::new(&mut sources, file)
+        .with_warnings_as_errors(true)
+        .compile()
+        .unwrap_err();
+    assert_eq!(err.errors.len(), 1);
+
+    // the errors of the assembler are limited too
+    let file = sources.add_file("labels.asm", "j a\nj b\nj c\n");
+    let err = MipsCompiler::new(&mut sources, file)
+        .with_max_errors(2)
+        .compile()
+        .unwrap_err();
+    assert_eq!(err.errors.len(), 2);
+}
+
+#[test]
+fn includes() {
+    let mut sources = SourceDb::new();
+    let lib = sources.add_file("lib/util.asm", "f: jr $ra\n    add $t0, $t1\n");
+    let main = sources.add_file(
+        "lib/main.asm",
+        ".include \"util.asm\"\nmain: jal f\n    addi $t0, $t0\n",
+    );
+    let err = MipsCompiler::new(&mut sources, main).compile().unwrap_err();
+    let locations = err
+        .errors
+        .iter()
+        .map(|e| {
+            let location = sources.location(e.get_span().start);
+            (location.file, location.line, location.column)
+        })
+        .collect::<Vec<_>>();
+    // each error points to the file it's in
+    assert_eq!(locations, vec![(lib, 2, 5), (main, 3, 5)]);
+
+    let mut sources = SourceDb::new();
+    let file = sources.add_file("loop.asm", "main: nop\n.include \"loop.asm\"");
+    let err = MipsCompiler::new(&mut sources, file).compile().unwrap_err();
+    assert_eq!(err.errors.len(), 1);
+    assert_eq!(
+        err.errors[0].to_string(),
+        "Parsing error: `loop.asm` includes itself."
+    );
+    assert_eq!(sources.resolve(&err.errors[0].get_span()), (file, 19..29));
+
+    let mut sources = SourceDb::new();
+    let file = sources.add_file("main.asm", ".include \"missing.asm\"");
+    let err = MipsCompiler::new(&mut sources, file).compile().unwrap_err();
+    assert!(err.errors[0]
+        .to_string()
+        .starts_with("Parsing error: Couldn't include `missing.asm`"));
+}
+
+#[test]
+fn error_at_end_of_file() {
+    // the end of file is past the content of a file without a final newline
+    for input in ["addi $t0,", "li $t0, 1 <<"] {
+        let mut sources = SourceDb::new();
+        let file = sources.add_file("main.asm", input);
+        let err = MipsCompiler::new(&mut sources, file).compile().unwrap_err();
+        assert_eq!(
+            sources.resolve(&err.errors[0].get_span()),
+            (file, input.len()..input.len() + 1)
+        );
+        err.display_formatted(&sources).unwrap();
+    }
+}
+
+/// Compiles a file of the corpus of a dialect with the given dialect
+fn compile_corpus_file(
+    dir: Dialect,
+    name: &str,
+    dialect: Dialect,
+) -> Result<CompileOutput, String> {
+    let path = format!("{}/tests/dialects/{dir}/{name}", env!("CARGO_MANIFEST_DIR"));
+    let content = std::fs::read_to_string(&path).unwrap();
+    let mut sources = SourceDb::new();
+    let file = sources.add_file(path, &content);
+    MipsCompiler::new(&mut sources, file)
+        .with_dialect(dialect)
+        .compile()
+        .map_err(|err| format!("{err:?}"))
+}
+
+#[test]
+fn dialect_corpora() {
+    for dialect in [Dialect::Mars, Dialect::Spim, Dialect::Gnu] {
+        let dir = format!("{}/tests/dialects/{dialect}", env!("CARGO_MANIFEST_DIR"));
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let name = entry.unwrap().file_name().into_string().unwrap();
+            if let Err(err) = compile_corpus_file(dialect, &name, dialect) {
+                panic!("{dialect}/{name} doesn't compile: {err}");
+            }
+        }
+    }
+
+    // the syntax of one dialect is rejected by the others
+    let rejected = [
+        (Dialect::Mars, "constants.asm", Dialect::Spim),
+        (Dialect::Mars, "constants.asm", Dialect::Gnu),
+        (Dialect::Mars, "pseudo.asm", Dialect::Spim),
+        (Dialect::Mars, "pseudo.asm", Dialect::Gnu),
+        (Dialect::Spim, "registers.asm", Dialect::Mars),
+        (Dialect::Gnu, "sections.asm", Dialect::Mars),
+        (Dialect::Gnu, "sections.asm", Dialect::Spim),
+        (Dialect::Gnu, "registers.asm", Dialect::Spim),
+    ];
+    for (dir, name, dialect) in rejected {
+        assert!(
+            compile_corpus_file(dir, name, dialect).is_err(),
+            "{dir}/{name} compiles as {dialect}"
+        );
+    }
+
+    // `010` is ten in MARS and eight in the other dialects
+    let words = |dir, name, dialect| {
+        let output = compile_corpus_file(dir, name, dialect).unwrap();
+        let data = output
+            .program
+            .segments
+            .into_iter()
+            .find(|s| s.kind == SegmentKind::Data)
+            .unwrap()
+            .data;
+        data.into_iter()
+            .filter_map(|d| match d {
+                StorableData::Word(word) => Some(word),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        words(Dialect::Mars, "constants.asm", Dialect::Mars),
+        [10, 16, 2]
+    );
+    assert_eq!(words(Dialect::Gnu, "sections.asm", Dialect::Gnu), [16, 8]);
+    assert_eq!(words(Dialect::Spim, "octal.asm", Dialect::Spim), [511, 15]);
+}
+
+#[test]
+fn syntax_tree_round_trip() {
+    for dialect in [Dialect::Mars, Dialect::Spim, Dialect::Gnu] {
+        let dir = format!("{}/tests/dialects/{dialect}", env!("CARGO_MANIFEST_DIR"));
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let content = std::fs::read_to_string(&path).unwrap();
+            let mut sources = SourceDb::new();
+            let file = sources.add_file(path.display().to_string(), &content);
+            let tree = SyntaxTree::parse(&sources, file, dialect);
+            assert_eq!(tree.to_string(), content, "{}", path.display());
+        }
+    }
 }
@@ -1,12 +1,37 @@
 use clap::Parser;
-use mips_parser::MipsCompiler;
-use std::{borrow::Borrow, path::PathBuf};
+use mips_parser::defs::dialect::Dialect;
+use mips_parser::disassembler::{Disassembler, RegisterNames};
+use mips_parser::source::SourceDb;
+use mips_parser::{MipsCompiler, WarningName};
+use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[command(version, about)]
 struct Args {
-    #[clap(required(true))]
+    #[clap(required_unless_present("disassemble"))]
     files: Vec<PathBuf>,
+    /// Stop compiling a file after this many errors, at least 1
+    #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    max_errors: Option<usize>,
+    /// Silence a kind of warning, like `unused-label`
+    #[arg(short = 'A', long = "allow", value_name = "WARNING")]
+    allowed_warnings: Vec<WarningName>,
+    /// Report warnings as errors
+    #[arg(long)]
+    werror: bool,
+    /// The assembler whose syntax the files follow: `mars`, `spim` or `gnu`
+    #[arg(long, default_value_t = Dialect::Mars)]
+    dialect: Dialect,
+    /// Print the instructions of files of hexadecimal machine words instead, or of the standard
+    /// input if there are no files
+    #[arg(long)]
+    disassemble: bool,
+    /// How the disassembly writes registers: `abi` like `$t0` or `numeric` like `$8`
+    #[arg(long, default_value_t = RegisterNames::Abi)]
+    registers: RegisterNames,
+    /// The address of the first disassembled word, in hexadecimal
+    #[arg(long, value_parser = parse_address, default_value = "0x00400000")]
+    base: u32,
 }
 
 #[derive(thiserror::Error)]
@@ -22,7 +47,12 @@ impl std::fmt::Debug for MipsError {
 
 fn main() -> Result<(), MipsError> {
     let args = Args::parse();
-    for path in args.files {
+    if args.disassemble {
+        return disassemble(&args);
+    }
+    // the files share the database, so every diagnostic can point to the file it belongs to
+    let mut sources = SourceDb::new();
+    for path in &args.files {
         if !path.try_exists()? {
             return Err(std::io::Error::new(
                 std::io::ErrorKind::NotFound,
@@ -38,16 +68,90 @@ fn main() -> Result<(), MipsError> {
             .into());
         }
         let file_content = std::fs::read_to_string(path.clone())?;
-        let res = MipsCompiler::new(&file_content).compile();
+        let file = sources.add_file(path.to_string_lossy(), &file_content);
+        let mut compiler = MipsCompiler::new(&mut sources, file)
+            .with_dialect(args.dialect)
+            .with_warnings_as_errors(args.werror);
+        if let Some(max_errors) = args.max_errors {
+            compiler = compiler.with_max_errors(max_errors);
+        }
+        for warning in &args.allowed_warnings {
+            compiler = compiler.allow_warning(*warning);
+        }
+        let res = compiler.compile();
+        // errors and warnings are displayed with ariadne
         match res {
-            Ok(_) => {}
+            Ok(output) => {
+                for warning in output.warnings {
+                    warning.display_formatted(&sources)?;
+                }
+            }
             Err(err) => {
-                // the error is displayed with ariadne
-                let a = path.file_name().unwrap().to_string_lossy();
-                let file_name: &str = a.borrow();
-                err.display_formatted(file_name.to_owned(), &file_content)?;
+                err.display_formatted(&sources)?;
             }
         };
     }
     Ok(())
 }
+
+/// Prints the instructions of the words in the files of the arguments, or in the standard input
+/// if there are none
+fn disassemble(args: &Args) -> Result<(), MipsError> {
+    let disassembler = Disassembler::new().with_register_names(args.registers);
+    if args.files.is_empty() {
+        let input = std::io::read_to_string(std::io::stdin())?;
+        return disassemble_words(&disassembler, "<stdin>", &input, args.base);
+    }
+    for path in &args.files {
+        let input = std::fs::read_to_string(path)?;
+        if args.files.len() > 1 {
+            println!("{}:", path.to_string_lossy());
+        }
+        disassemble_words(&disassembler, &path.to_string_lossy(), &input, args.base)?;
+    }
+    Ok(())
+}
+
+/// Prints the instructions of the hexadecimal words of the input, placed from `address`. The
+/// words are separated by whitespace or commas and `#` starts a comment. The words that are not
+/// instructions are printed as `.word`.
+fn disassemble_words(
+    disassembler: &Disassembler,
+    name: &str,
+    input: &str,
+    mut address: u32,
+) -> Result<(), MipsError> {
+    for (i, line) in input.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let words = line
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|w| !w.is_empty());
+        for word in words {
+            let Ok(word) = parse_address(word) else {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "`{word}` in {name} at line {} is not a hexadecimal word.",
+                        i + 1
+                    ),
+                )
+                .into());
+            };
+            match disassembler.disassemble(word, address) {
+                Some(text) => println!("{address:08x}:  {word:08x}  {text}"),
+                None => println!("{address:08x}:  {word:08x}  .word {word:#010x}"),
+            }
+            address = address.wrapping_add(4);
+        }
+    }
+    Ok(())
+}
+
+/// Parses a hexadecimal number of up to 32 bits, with or without the `0x` prefix
+fn parse_address(text: &str) -> Result<u32, std::num::ParseIntError> {
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+    u32::from_str_radix(digits, 16)
+}